@@ -0,0 +1,92 @@
+#ifndef SPADE_SERIAL_H
+#define SPADE_SERIAL_H
+
+/* Generated by cbindgen from spade-serial-ffi. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The outcome of a call which talks to a device.
+typedef enum SpadeStatus {
+  // The call succeeded. For `spade_upload_game`, the device stored the
+  // game.
+  SPADE_STATUS_OK = 0,
+  // The device does not have enough flash for the game.
+  SPADE_STATUS_OUT_OF_FLASH,
+  // The device cannot store any more games.
+  SPADE_STATUS_OUT_OF_METADATA,
+  // The device rejected the game with a response this library does not
+  // know, like one from a newer Spade version.
+  SPADE_STATUS_REJECTED,
+  // A pointer passed was null, or a string was not valid UTF-8.
+  SPADE_STATUS_INVALID_ARGUMENT,
+  // The name of the game is over 100 bytes or contains a NUL character.
+  SPADE_STATUS_INVALID_NAME,
+  // The game is too large to describe in the upload header.
+  SPADE_STATUS_FAILED_CONVERSION,
+  // Reading from or writing to the device failed.
+  SPADE_STATUS_IO_ERROR,
+  // The output of the device ended without a response.
+  SPADE_STATUS_NO_RESPONSE,
+  // The device stopped accepting bytes partway through a write.
+  SPADE_STATUS_WRITE_STALLED,
+  // Reading from or writing to the device timed out.
+  SPADE_STATUS_TIMEOUT,
+  // The device was disconnected.
+  SPADE_STATUS_DISCONNECTED,
+} SpadeStatus;
+
+// A connection to a device running Spade, from `spade_open`.
+typedef struct SpadePort SpadePort;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Opens the serial port at `path` with the settings Spade uses, like
+// `spade_serial::open`. Returns null if it cannot be opened.
+//
+// The port must be closed with `spade_close`.
+//
+// ### Safety
+// `path` must be null or a NUL-terminated string.
+SpadePort *spade_open(const char *path);
+
+// Closes `port`. Does nothing if it is null.
+//
+// ### Safety
+// `port` must be null or from `spade_open`, and not have been closed
+// already.
+void spade_close(SpadePort *port);
+
+// Checks whether the device on `port` is running a legacy Spade version,
+// like `spade_serial::is_running_legacy`, and stores the answer in
+// `legacy`.
+//
+// ### Safety
+// `port` must be null or from `spade_open`, and `legacy` must be null or
+// valid to write a `bool` to.
+SpadeStatus spade_is_legacy(SpadePort *port, bool *legacy);
+
+// Uploads the `game_len` bytes at `game` to the device on `port` under
+// `name`, like `spade_serial::upload_game`.
+//
+// Returns `SPADE_STATUS_OK` if the device stored the game, the response of
+// the device if it rejected it, or what went wrong otherwise.
+//
+// ### Safety
+// `port` must be null or from `spade_open`, `name` must be null or a
+// NUL-terminated string, and `game` must be null or point to `game_len`
+// bytes.
+SpadeStatus spade_upload_game(SpadePort *port,
+                              const char *name,
+                              const uint8_t *game,
+                              uintptr_t game_len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SPADE_SERIAL_H */
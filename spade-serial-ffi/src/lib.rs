@@ -0,0 +1,254 @@
+//! C bindings for [spade-serial](https://crates.io/crates/spade-serial), so
+//! tools written in other languages can upload games to devices running
+//! Spade.
+//!
+//! Open a device with `spade_open`, pass it to the other functions, then
+//! close it with `spade_close`:
+//! ```c
+//! #include "spade_serial.h"
+//!
+//! SpadePort *port = spade_open("/dev/ttyACM0");
+//! if (port) {
+//!     const char *game = "console.log('hi')";
+//!     SpadeStatus status = spade_upload_game(port, "hi", (const uint8_t *)game, strlen(game));
+//!     spade_close(port);
+//! }
+//! ```
+//!
+//! The header, `include/spade_serial.h`, is generated with
+//! [cbindgen](https://crates.io/crates/cbindgen):
+//! ```sh
+//! cbindgen --config cbindgen.toml --output include/spade_serial.h
+//! ```
+#![warn(missing_docs)]
+
+use std::ffi::{c_char, CStr};
+use std::ptr;
+use std::slice;
+
+use spade_serial::{
+    is_running_legacy, open, upload_game, SpadeTransport, UploadError, UploadResult,
+};
+
+/// A connection to a device running Spade, from `spade_open`.
+pub struct SpadePort {
+    io: Box<dyn SpadeTransport + Send>,
+}
+
+/// The outcome of a call which talks to a device.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpadeStatus {
+    /// The call succeeded. For `spade_upload_game`, the device stored the
+    /// game.
+    Ok = 0,
+    /// The device does not have enough flash for the game.
+    OutOfFlash,
+    /// The device cannot store any more games.
+    OutOfMetadata,
+    /// The device rejected the game with a response this library does not
+    /// know, like one from a newer Spade version.
+    Rejected,
+    /// A pointer passed was null, or a string was not valid UTF-8.
+    InvalidArgument,
+    /// The name of the game is over 100 bytes or contains a NUL character.
+    InvalidName,
+    /// The game is too large to describe in the upload header.
+    FailedConversion,
+    /// Reading from or writing to the device failed.
+    IoError,
+    /// The output of the device ended without a response.
+    NoResponse,
+    /// The device stopped accepting bytes partway through a write.
+    WriteStalled,
+    /// Reading from or writing to the device timed out.
+    Timeout,
+    /// The device was disconnected.
+    Disconnected,
+}
+
+impl From<UploadResult> for SpadeStatus {
+    fn from(value: UploadResult) -> Self {
+        match value {
+            UploadResult::AllGood => SpadeStatus::Ok,
+            UploadResult::OutOfFlash => SpadeStatus::OutOfFlash,
+            UploadResult::OutOfMetadata => SpadeStatus::OutOfMetadata,
+            UploadResult::Rejected(_) => SpadeStatus::Rejected,
+            // Only legacy uploads are unconfirmed, which are never made here.
+            UploadResult::Unconfirmed => SpadeStatus::Ok,
+            _ => SpadeStatus::Rejected,
+        }
+    }
+}
+
+impl From<UploadError> for SpadeStatus {
+    fn from(value: UploadError) -> Self {
+        match value {
+            UploadError::InvalidName => SpadeStatus::InvalidName,
+            UploadError::FailedConversion => SpadeStatus::FailedConversion,
+            // Uploads are never given a token to cancel them with.
+            UploadError::IOError | UploadError::Cancelled => SpadeStatus::IoError,
+            UploadError::NoResponse => SpadeStatus::NoResponse,
+            UploadError::WriteStalled => SpadeStatus::WriteStalled,
+            UploadError::Timeout => SpadeStatus::Timeout,
+            UploadError::Disconnected => SpadeStatus::Disconnected,
+            _ => SpadeStatus::IoError,
+        }
+    }
+}
+
+impl From<std::io::Error> for SpadeStatus {
+    fn from(value: std::io::Error) -> Self {
+        UploadError::from(value).into()
+    }
+}
+
+/// Opens the serial port at `path` with the settings Spade uses, like
+/// `spade_serial::open`. Returns null if it cannot be opened.
+///
+/// The port must be closed with `spade_close`.
+///
+/// ### Safety
+/// `path` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn spade_open(path: *const c_char) -> *mut SpadePort {
+    let Some(path) = str_arg(path) else {
+        return ptr::null_mut();
+    };
+
+    match open(path) {
+        Ok(port) => Box::into_raw(Box::new(SpadePort { io: Box::new(port) })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Closes `port`. Does nothing if it is null.
+///
+/// ### Safety
+/// `port` must be null or from `spade_open`, and not have been closed
+/// already.
+#[no_mangle]
+pub unsafe extern "C" fn spade_close(port: *mut SpadePort) {
+    if !port.is_null() {
+        drop(Box::from_raw(port));
+    }
+}
+
+/// Checks whether the device on `port` is running a legacy Spade version,
+/// like `spade_serial::is_running_legacy`, and stores the answer in
+/// `legacy`.
+///
+/// ### Safety
+/// `port` must be null or from `spade_open`, and `legacy` must be null or
+/// valid to write a `bool` to.
+#[no_mangle]
+pub unsafe extern "C" fn spade_is_legacy(port: *mut SpadePort, legacy: *mut bool) -> SpadeStatus {
+    let (Some(port), Some(legacy)) = (port.as_mut(), legacy.as_mut()) else {
+        return SpadeStatus::InvalidArgument;
+    };
+
+    match is_running_legacy(&mut *port.io) {
+        Ok(is_legacy) => {
+            *legacy = is_legacy;
+            SpadeStatus::Ok
+        }
+        Err(error) => error.into(),
+    }
+}
+
+/// Uploads the `game_len` bytes at `game` to the device on `port` under
+/// `name`, like `spade_serial::upload_game`.
+///
+/// Returns `SPADE_STATUS_OK` if the device stored the game, the response of
+/// the device if it rejected it, or what went wrong otherwise.
+///
+/// ### Safety
+/// `port` must be null or from `spade_open`, `name` must be null or a
+/// NUL-terminated string, and `game` must be null or point to `game_len`
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn spade_upload_game(
+    port: *mut SpadePort,
+    name: *const c_char,
+    game: *const u8,
+    game_len: usize,
+) -> SpadeStatus {
+    let (Some(port), Some(name)) = (port.as_mut(), str_arg(name)) else {
+        return SpadeStatus::InvalidArgument;
+    };
+    if game.is_null() {
+        return SpadeStatus::InvalidArgument;
+    }
+    let game = slice::from_raw_parts(game, game_len);
+
+    match upload_game(&mut *port.io, name, game) {
+        Ok(result) => result.into(),
+        Err(error) => error.into(),
+    }
+}
+
+/// Returns the string at `string`, or `None` if it is null or not UTF-8.
+///
+/// ### Safety
+/// `string` must be null or a NUL-terminated string which outlives the
+/// returned one.
+unsafe fn str_arg<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        return None;
+    }
+    CStr::from_ptr(string).to_str().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spade_serial::mock::{Firmware, SerialMock};
+
+    fn mock_port(mock: SerialMock) -> *mut SpadePort {
+        Box::into_raw(Box::new(SpadePort { io: Box::new(mock) }))
+    }
+
+    #[test]
+    fn upload() {
+        let game = b"console.log('ffi')";
+        unsafe {
+            let port = mock_port(SerialMock::new().game_slots(1));
+            let upload =
+                || spade_upload_game(port, c"ffi test".as_ptr(), game.as_ptr(), game.len());
+            assert_eq!(upload(), SpadeStatus::Ok);
+            assert_eq!(upload(), SpadeStatus::OutOfMetadata);
+            assert_eq!(
+                spade_upload_game(port, ptr::null(), game.as_ptr(), game.len()),
+                SpadeStatus::InvalidArgument
+            );
+            assert_eq!(
+                spade_upload_game(port, c"ffi test".as_ptr(), ptr::null(), 0),
+                SpadeStatus::InvalidArgument
+            );
+            spade_close(port);
+        }
+    }
+
+    #[test]
+    fn legacy() {
+        unsafe {
+            let mut legacy = true;
+            let port = mock_port(SerialMock::new());
+            assert_eq!(spade_is_legacy(port, &mut legacy), SpadeStatus::Ok);
+            assert!(!legacy);
+            spade_close(port);
+
+            let port = mock_port(SerialMock::new().firmware(Firmware::Legacy));
+            assert_eq!(spade_is_legacy(port, &mut legacy), SpadeStatus::Ok);
+            assert!(legacy);
+            assert_eq!(
+                spade_is_legacy(port, ptr::null_mut()),
+                SpadeStatus::InvalidArgument
+            );
+            spade_close(port);
+
+            assert!(spade_open(ptr::null()).is_null());
+            spade_close(ptr::null_mut());
+        }
+    }
+}
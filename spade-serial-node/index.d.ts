@@ -0,0 +1,8 @@
+export * from './binding'
+
+/**
+ * Yields each line printed by the device at the serial port `port`, like by
+ * `console.log` in the running game, until its output ends. Breaking out of
+ * the loop stops reading from the device.
+ */
+export function monitor(port: string): AsyncGenerator<string, void, undefined>
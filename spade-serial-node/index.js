@@ -0,0 +1,50 @@
+const binding = require('./binding.js')
+
+/**
+ * Yields each line printed by the device at the serial port `port`, like by
+ * `console.log` in the running game, until its output ends. Breaking out of
+ * the loop stops reading from the device.
+ *
+ * @param {string} port
+ * @returns {AsyncGenerator<string, void, undefined>}
+ */
+async function* monitor(port) {
+  const lines = []
+  let ended = false
+  let failure = null
+  let wake = null
+
+  const watcher = binding.watchConsole(port, (err, line) => {
+    if (err) {
+      failure = err
+    } else if (line === null) {
+      ended = true
+    } else {
+      lines.push(line)
+    }
+    if (wake) {
+      wake()
+      wake = null
+    }
+  })
+
+  try {
+    while (true) {
+      if (lines.length > 0) {
+        yield lines.shift()
+      } else if (failure) {
+        throw failure
+      } else if (ended) {
+        return
+      } else {
+        await new Promise((resolve) => {
+          wake = resolve
+        })
+      }
+    }
+  } finally {
+    watcher.stop()
+  }
+}
+
+module.exports = { ...binding, monitor }
@@ -0,0 +1,58 @@
+use std::thread;
+
+use napi::threadsafe_function::{
+    ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode,
+};
+use napi::{Error, JsFunction, Result};
+use napi_derive::napi;
+use spade_serial::{attach_console, open, CancellationToken};
+
+/// The output of a device being passed to a callback, from `watchConsole`.
+#[napi]
+pub struct ConsoleWatcher {
+    token: CancellationToken,
+}
+
+#[napi]
+impl ConsoleWatcher {
+    /// Stops passing lines to the callback. It is called with `null` once it
+    /// has stopped, within the read timeout of the port.
+    #[napi]
+    pub fn stop(&self) {
+        self.token.cancel();
+    }
+}
+
+/// Calls `onLine` with each line printed by the device at the serial port
+/// `port`, like by `console.log` in the running game, until `stop` is
+/// called on the returned watcher.
+///
+/// `onLine` is called with `null` as the line once the output ends, or with
+/// an error if reading fails.
+#[napi(ts_args_type = "port: string, onLine: (err: Error | null, line: string | null) => void")]
+pub fn watch_console(port: String, on_line: JsFunction) -> Result<ConsoleWatcher> {
+    let on_line: ThreadsafeFunction<Option<String>, ErrorStrategy::CalleeHandled> = on_line
+        .create_threadsafe_function(0, |ctx: ThreadSafeCallContext<Option<String>>| {
+            Ok(vec![ctx.value])
+        })?;
+    let mut port = open(&port).map_err(|err| Error::from_reason(err.to_string()))?;
+
+    let token = CancellationToken::new();
+    let watcher = ConsoleWatcher {
+        token: token.clone(),
+    };
+    thread::spawn(move || {
+        let result = attach_console(&mut port, &token, |line| {
+            on_line.call(
+                Ok(Some(line.text().to_owned())),
+                ThreadsafeFunctionCallMode::NonBlocking,
+            );
+        });
+        let end = result
+            .map(|()| None)
+            .map_err(|err| Error::from_reason(err.to_string()));
+        on_line.call(end, ThreadsafeFunctionCallMode::NonBlocking);
+    });
+
+    Ok(watcher)
+}
@@ -0,0 +1,62 @@
+//! Node.js bindings for [spade-serial](https://crates.io/crates/spade-serial),
+//! built with [napi-rs](https://napi.rs).
+//!
+//! Uploads and reads run on other threads, so they do not block the event
+//! loop. `index.js` wraps `watchConsole` in an async iterator, `monitor`.
+#![warn(missing_docs)]
+
+use napi::bindgen_prelude::AsyncTask;
+use napi::{Env, Error, Result, Task};
+use napi_derive::napi;
+use spade_serial::{open, upload, Game, GameName};
+
+// napi generates undocumented public methods on classes, such as
+// `into_instance`, so `missing_docs` is allowed for `ConsoleWatcher`.
+#[allow(missing_docs)]
+mod console;
+
+pub use console::{watch_console, ConsoleWatcher};
+
+/// Returns the serial ports which could be devices running Spade, found by
+/// the USB vendor and product ID of the Sprig.
+#[napi]
+pub fn discover() -> Result<Vec<String>> {
+    let ports = spade_serial::discover().map_err(|err| Error::from_reason(err.to_string()))?;
+    Ok(ports.into_iter().map(|port| port.port_name).collect())
+}
+
+/// An upload run by `uploadGame` on the thread pool of Node.js.
+pub struct Upload {
+    port: String,
+    game: Game,
+}
+
+impl Task for Upload {
+    type Output = String;
+    type JsValue = String;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let mut port = open(&self.port).map_err(|err| Error::from_reason(err.to_string()))?;
+        match upload(&mut port, &self.game) {
+            Ok(result) => Ok(result.response().to_owned()),
+            Err(err) => Err(Error::from_reason(err.to_string())),
+        }
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        Ok(output)
+    }
+}
+
+/// Uploads `game` to the device at the serial port `port` under `name`.
+///
+/// Resolves to the response of the device: `"ALL_GOOD"` if it stored the
+/// game, or the rejection, like `"OO_FLASH"`. Rejects if the name or game
+/// cannot be uploaded, or communicating with the device fails.
+#[napi]
+pub fn upload_game(port: String, name: String, game: String) -> Result<AsyncTask<Upload>> {
+    let name = GameName::new(&name).map_err(|err| Error::from_reason(err.to_string()))?;
+    let game = Game::new(name, game).map_err(|err| Error::from_reason(err.to_string()))?;
+
+    Ok(AsyncTask::new(Upload { port, game }))
+}
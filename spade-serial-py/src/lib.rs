@@ -0,0 +1,186 @@
+//! Python bindings for [spade-serial](https://crates.io/crates/spade-serial),
+//! built with [PyO3](https://pyo3.rs) and
+//! [maturin](https://www.maturin.rs).
+//!
+//! The module is called `spade_serial`:
+//! ```python
+//! import spade_serial
+//!
+//! for port in spade_serial.discover():
+//!     response = spade_serial.upload_game(
+//!         port,
+//!         "workshop",
+//!         open("workshop.js").read(),
+//!         progress=lambda written, total: print(f"{written}/{total} bytes"),
+//!     )
+//!     print(port, response)
+//! ```
+#![warn(missing_docs)]
+
+use std::time::Duration;
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyOSError, PyValueError};
+use pyo3::prelude::*;
+use spade_serial::{open, upload_game_with, Game, GameName, UploadOptions, UploadProgress};
+
+create_exception!(
+    spade_serial,
+    UploadFailed,
+    PyException,
+    "The game could not be uploaded because of a problem communicating with the device."
+);
+
+/// Returns the serial ports which could be devices running Spade, found by
+/// the USB vendor and product ID of the Sprig.
+#[pyfunction]
+fn discover() -> PyResult<Vec<String>> {
+    let ports = spade_serial::discover().map_err(|err| PyOSError::new_err(err.to_string()))?;
+    Ok(ports.into_iter().map(|port| port.port_name).collect())
+}
+
+/// Uploads `game` to the device at the serial port `port` under `name`.
+///
+/// Returns the response of the device: `"ALL_GOOD"` if it stored the game,
+/// or the rejection, like `"OO_FLASH"`. `progress` is called with the bytes
+/// of the game written so far and the size of the game after each write.
+/// The upload is tried again up to `retries` more times if the device does
+/// not respond, and reads wait up to `timeout` seconds.
+///
+/// Raises `ValueError` if the name, game, or timeout cannot be used, `OSError`
+/// if the port cannot be opened, and `UploadFailed` if communicating with the
+/// device fails.
+#[pyfunction]
+#[pyo3(signature = (port, name, game, *, progress=None, retries=0, timeout=None))]
+fn upload_game(
+    py: Python<'_>,
+    port: &str,
+    name: &str,
+    game: String,
+    progress: Option<PyObject>,
+    retries: u32,
+    timeout: Option<f64>,
+) -> PyResult<String> {
+    let name = GameName::new(name).map_err(|err| PyValueError::new_err(err.to_string()))?;
+    let game = Game::new(name, game).map_err(|err| PyValueError::new_err(err.to_string()))?;
+
+    let timeout = timeout
+        .map(|timeout| {
+            Duration::try_from_secs_f64(timeout)
+                .map_err(|err| PyValueError::new_err(err.to_string()))
+        })
+        .transpose()?;
+
+    let mut options = UploadOptions::new().retries(retries);
+    if let Some(progress) = progress {
+        options = options.on_progress(move |update| {
+            let UploadProgress::Game { written, total } = update else {
+                return;
+            };
+            Python::with_gil(|py| {
+                // Exceptions cannot be raised from here, so they are reported
+                // like ones raised in `__del__`.
+                if let Err(err) = progress.call1(py, (written, total)) {
+                    err.write_unraisable(py, None);
+                }
+            });
+        });
+    }
+
+    let mut port = open(port).map_err(|err| PyOSError::new_err(err.to_string()))?;
+    if let Some(timeout) = timeout {
+        port.set_timeout(timeout)
+            .map_err(|err| PyOSError::new_err(err.to_string()))?;
+    }
+    let result = py.allow_threads(|| upload_game_with(&mut port, &game, &options));
+
+    match result {
+        Ok(result) => Ok(result.response().to_owned()),
+        Err(err) => Err(UploadFailed::new_err(err.to_string())),
+    }
+}
+
+/// Uploads games to Sprig devices running Spade.
+#[pymodule]
+#[pyo3(name = "spade_serial")]
+fn spade_serial_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(discover, m)?)?;
+    m.add_function(wrap_pyfunction!(upload_game, m)?)?;
+    m.add("UploadFailed", m.py().get_type::<UploadFailed>())?;
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::io::{ErrorKind, Read, Write};
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+
+    use serialport::{SerialPort, TTYPort};
+    use spade_serial::mock::SerialMock;
+
+    use super::*;
+
+    /// Serves a mock device on one end of a pseudoterminal, returning the
+    /// path of the other end and the source of the first game uploaded.
+    fn mock_device() -> (String, Receiver<Vec<u8>>) {
+        let (mut host, device) = TTYPort::pair().unwrap();
+        let path = device.name().unwrap();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let _device = device;
+            let mut mock = SerialMock::new().game_slots(1);
+            host.set_timeout(Duration::from_millis(10)).unwrap();
+            let mut buf = [0; 256];
+            loop {
+                match Read::read(&mut host, &mut buf) {
+                    Ok(bytes) => mock.write_all(&buf[..bytes]).unwrap(),
+                    Err(err) if err.kind() == ErrorKind::TimedOut => {}
+                    Err(_) => return,
+                }
+                let bytes = mock.read(&mut buf).unwrap();
+                host.write_all(&buf[..bytes]).unwrap();
+                if let Some(game) = mock.games().first() {
+                    let _ = sender.send(game.source().to_vec());
+                }
+            }
+        });
+
+        (path, receiver)
+    }
+
+    #[test]
+    fn upload_timeout() {
+        pyo3::prepare_freethreaded_python();
+        let (path, games) = mock_device();
+
+        let result = Python::with_gil(|py| {
+            upload_game(
+                py,
+                &path,
+                "timeout test",
+                String::from("console.log(1)"),
+                None,
+                0,
+                Some(2.0),
+            )
+        });
+        assert_eq!(result.unwrap(), "ALL_GOOD");
+        assert_eq!(games.recv().unwrap(), b"console.log(1)");
+
+        let result = Python::with_gil(|py| {
+            let result = upload_game(
+                py,
+                &path,
+                "timeout test",
+                String::from("console.log(1)"),
+                None,
+                0,
+                Some(-1.0),
+            );
+            result.unwrap_err().is_instance_of::<PyValueError>(py)
+        });
+        assert!(result);
+    }
+}
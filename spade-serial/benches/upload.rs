@@ -0,0 +1,81 @@
+//! Benchmarks uploading over a loopback transport, which acts like a device
+//! but costs a fixed time for every write, like a USB transfer.
+
+use std::hint::black_box;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use spade_serial::{upload_game_with, Game, GameName, UploadOptions, UploadResult};
+
+/// The time each write takes, on top of copying the bytes.
+const WRITE_COST: Duration = Duration::from_micros(20);
+
+/// The length of the command, name, and game length written before the game.
+const HEADER_LEN: usize = 110;
+
+/// Accepts an upload and responds with 'ALL_GOOD' once the whole game was
+/// written.
+struct Loopback {
+    expected: usize,
+    written: usize,
+}
+
+impl Read for Loopback {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.written < self.expected {
+            return Ok(0);
+        }
+
+        let response = b"ALL_GOOD";
+        buf[..response.len()].copy_from_slice(response);
+        Ok(response.len())
+    }
+}
+
+impl Write for Loopback {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let start = Instant::now();
+        while start.elapsed() < WRITE_COST {
+            std::hint::spin_loop();
+        }
+
+        self.written += black_box(buf).len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn upload(c: &mut Criterion) {
+    let source = "console.log('benchmark');\n".repeat(4000);
+    let game = Game::new(GameName::new("benchmark").unwrap(), source).unwrap();
+
+    let mut group = c.benchmark_group("upload_100kb");
+    group.throughput(Throughput::Bytes(game.source().len() as u64));
+    for chunk_size in [8, 64, 512] {
+        let options = UploadOptions::new().chunk_size(chunk_size);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(chunk_size),
+            &options,
+            |b, options| {
+                b.iter(|| {
+                    let mut port = Loopback {
+                        expected: HEADER_LEN + game.source().len(),
+                        written: 0,
+                    };
+                    assert_eq!(
+                        upload_game_with(&mut port, &game, options),
+                        Ok(UploadResult::AllGood)
+                    );
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, upload);
+criterion_main!(benches);
@@ -0,0 +1,191 @@
+//! Simulates a device running Spade, shared over TCP with RFC 2217 like a
+//! serial port from ser2net, so tools and tests can run without hardware.
+//!
+//! ```sh
+//! cargo run --example simulator --features mock -- --port 2217 --games 4 --noise 500
+//! TEST_DEVICE=rfc2217://127.0.0.1:2217 cargo test
+//! ```
+//!
+//! Options:
+//! - `--port <port>`: The TCP port to listen on, 2217 by default.
+//! - `--games <count>`: How many games the device can store.
+//! - `--flash <bytes>`: How many bytes of flash are free for games.
+//! - `--legacy`: Behave like a legacy Spade version.
+//! - `--noise <ms>`: Print a log line every `ms` milliseconds, like a running
+//!   game.
+//!
+//! One client is served at a time, and the stored games are kept between
+//! connections, like a device which stays plugged in.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use spade_serial::mock::{Firmware, SerialMock};
+
+/// Interpret as command.
+const IAC: u8 = 255;
+/// Subnegotiation begin.
+const SB: u8 = 250;
+/// Subnegotiation end.
+const SE: u8 = 240;
+/// The first of the option negotiation commands, `WILL`, `WONT`, `DO` and
+/// `DONT`.
+const WILL: u8 = 251;
+
+/// Where the server is in the telnet stream from the client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Telnet {
+    Data,
+    Iac,
+    Negotiation,
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+struct Options {
+    port: u16,
+    games: Option<usize>,
+    flash: Option<usize>,
+    legacy: bool,
+    noise: Option<Duration>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        port: 2217,
+        games: None,
+        flash: None,
+        legacy: false,
+        noise: None,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{arg} needs a value"))?
+                .parse::<u64>()
+                .map_err(|err| format!("invalid value for {arg}: {err}"))
+        };
+        match arg.as_str() {
+            "--port" => options.port = value()? as u16,
+            "--games" => options.games = Some(value()? as usize),
+            "--flash" => options.flash = Some(value()? as usize),
+            "--noise" => options.noise = Some(Duration::from_millis(value()?)),
+            "--legacy" => options.legacy = true,
+            _ => return Err(format!("unknown argument {arg}")),
+        }
+    }
+
+    Ok(options)
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(2);
+    });
+
+    let mut device = SerialMock::new();
+    if let Some(games) = options.games {
+        device = device.game_slots(games);
+    }
+    if let Some(flash) = options.flash {
+        device = device.flash_size(flash);
+    }
+    if options.legacy {
+        device = device.firmware(Firmware::Legacy);
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", options.port)).unwrap_or_else(|err| {
+        eprintln!("Could not listen on port {}: {err}", options.port);
+        std::process::exit(1);
+    });
+    println!("Simulating a Spade device on 127.0.0.1:{}", options.port);
+
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| serve(stream, &mut device, options.noise));
+        if let Err(err) = result {
+            eprintln!("Connection ended: {err}");
+        }
+        println!("{} games stored", device.games().len());
+    }
+}
+
+/// Passes data between the client and the device until the client
+/// disconnects.
+fn serve(
+    mut stream: TcpStream,
+    device: &mut SerialMock,
+    noise: Option<Duration>,
+) -> Result<(), std::io::Error> {
+    stream.set_read_timeout(Some(Duration::from_millis(10)))?;
+
+    let mut state = Telnet::Data;
+    let mut frame = 0;
+    let mut last_noise = Instant::now();
+    let mut buf = [0; 256];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Err(err) if err.kind() == ErrorKind::ConnectionReset => return Ok(()),
+            Ok(bytes) => {
+                let data = strip_telnet(&buf[..bytes], &mut state);
+                device.write_all(&data)?;
+            }
+            Err(err) if matches!(err.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {}
+            Err(err) => return Err(err),
+        }
+
+        if let Some(interval) = noise {
+            if last_noise.elapsed() >= interval {
+                frame += 1;
+                device.push_output(format!("frame {frame}\n").as_bytes());
+                last_noise = Instant::now();
+            }
+        }
+
+        let mut output = Vec::new();
+        device.read_to_end(&mut output)?;
+        if !output.is_empty() {
+            let mut escaped = Vec::with_capacity(output.len());
+            for byte in output {
+                if byte == IAC {
+                    escaped.push(IAC);
+                }
+                escaped.push(byte);
+            }
+            stream.write_all(&escaped)?;
+        }
+    }
+}
+
+/// Returns the data in `bytes` without telnet commands. Option negotiation
+/// and COM port settings are ignored, since the simulated device does not
+/// have a baud rate.
+fn strip_telnet(bytes: &[u8], state: &mut Telnet) -> Vec<u8> {
+    let mut data = Vec::with_capacity(bytes.len());
+    for &byte in bytes {
+        *state = match (*state, byte) {
+            (Telnet::Data, IAC) => Telnet::Iac,
+            (Telnet::Data, _) => {
+                data.push(byte);
+                Telnet::Data
+            }
+            (Telnet::Iac, IAC) => {
+                data.push(IAC);
+                Telnet::Data
+            }
+            (Telnet::Iac, SB) => Telnet::Subnegotiation,
+            (Telnet::Iac, command) if command >= WILL => Telnet::Negotiation,
+            (Telnet::Iac, _) | (Telnet::Negotiation, _) => Telnet::Data,
+            (Telnet::Subnegotiation, IAC) => Telnet::SubnegotiationIac,
+            (Telnet::Subnegotiation, _) => Telnet::Subnegotiation,
+            (Telnet::SubnegotiationIac, SE) => Telnet::Data,
+            (Telnet::SubnegotiationIac, _) => Telnet::Subnegotiation,
+        };
+    }
+
+    data
+}
@@ -0,0 +1,34 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use spade_serial::protocol::{ALL_GOOD, OO_FLASH, OO_METADATA};
+use spade_serial::ResponseScanner;
+
+const TOKENS: [(&[u8], u8); 3] = [(ALL_GOOD, 0), (OO_FLASH, 1), (OO_METADATA, 2)];
+
+// The first byte sets the chunk size; the rest is output from the device.
+// Scanning in chunks must find the same tokens as scanning all at once.
+fuzz_target!(|data: &[u8]| {
+    let Some((&chunk_size, output)) = data.split_first() else {
+        return;
+    };
+    let chunk_size = usize::from(chunk_size).max(1);
+
+    let scan = |chunk_size: usize| {
+        let mut scanner = ResponseScanner::new(&TOKENS);
+        let mut found = Vec::new();
+        let mut offset = 0;
+        for mut chunk in output.chunks(chunk_size) {
+            while let Some((value, consumed)) = scanner.feed(chunk) {
+                assert!(consumed > 0 && consumed <= chunk.len());
+                offset += consumed;
+                found.push((value, offset));
+                chunk = &chunk[consumed..];
+            }
+            offset += chunk.len();
+        }
+        found
+    };
+
+    assert_eq!(scan(chunk_size), scan(output.len().max(1)));
+});
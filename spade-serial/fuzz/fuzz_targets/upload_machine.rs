@@ -0,0 +1,33 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use spade_serial::protocol::HEADER_LEN;
+use spade_serial::UploadMachine;
+
+// The first byte sets the chunk size of the game and of the output; the rest
+// is split in half into the game and the output from the device. Uploading
+// must write the whole game, end, and not panic on any output.
+fuzz_target!(|data: &[u8]| {
+    let Some((&chunk_size, rest)) = data.split_first() else {
+        return;
+    };
+    let chunk_size = usize::from(chunk_size).max(1);
+    let (game, output) = rest.split_at(rest.len() / 2);
+    let game = String::from_utf8_lossy(game);
+
+    let mut machine = UploadMachine::new("fuzz", &game)
+        .unwrap()
+        .with_chunk_size(chunk_size);
+    let mut written = 0;
+    while let Some(bytes) = machine.next_write() {
+        written += bytes.len();
+    }
+    assert_eq!(written, HEADER_LEN + game.len());
+
+    for chunk in output.chunks(chunk_size) {
+        if machine.feed_read(chunk).is_some() {
+            assert!(!machine.is_waiting());
+            break;
+        }
+    }
+});
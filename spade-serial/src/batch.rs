@@ -0,0 +1,84 @@
+use crate::trace::{event, span};
+use crate::{
+    upload_game_with, CancellationToken, Game, GameName, SpadeTransport, UploadError,
+    UploadOptions, UploadResult,
+};
+
+/// Options for `spade_serial::upload_many`.
+#[derive(Debug, Clone, Default)]
+pub struct BatchOptions {
+    /// Stop after a game is rejected with `UploadResult::OutOfFlash`. By
+    /// default, the remaining games are still uploaded since smaller ones may
+    /// fit.
+    pub stop_on_out_of_flash: bool,
+    /// Cancels the game being uploaded and skips the rest.
+    pub token: CancellationToken,
+    /// How many times each game is tried again before giving up, like
+    /// `UploadOptions::retries`. A game which still fails stops the batch as
+    /// usual.
+    pub retries: u32,
+}
+
+/// Uploads several games to a device, one after another over the same
+/// connection.
+///
+/// Each game is uploaded like with `spade_serial::upload_game_with`, and its
+/// result is returned alongside its name in the order given. Uploading
+/// stops early, leaving the remaining games out of the results, when:
+/// - The device responds with `UploadResult::OutOfMetadata`, since no more
+///   games can be stored.
+/// - The device responds with `UploadResult::OutOfFlash` and
+///   `options.stop_on_out_of_flash` is set.
+/// - An error occurs which means the connection can no longer be used:
+///   `UploadError::IOError`, `UploadError::NoResponse`,
+///   `UploadError::Cancelled`, `UploadError::WriteStalled`,
+///   `UploadError::Timeout`, or `UploadError::Disconnected`.
+///
+/// Errors specific to one game, like it being too large, do not stop the
+/// remaining games from being uploaded.
+pub fn upload_many(
+    io: &mut (impl SpadeTransport + ?Sized),
+    games: &[Game],
+    options: &BatchOptions,
+) -> Vec<(GameName, Result<UploadResult, UploadError>)> {
+    span!(info_span, "upload_many", games = games.len());
+
+    let upload_options = UploadOptions::new()
+        .token(options.token.clone())
+        .retries(options.retries);
+    let mut results = Vec::with_capacity(games.len());
+
+    for game in games {
+        let name = game.name();
+        let result = upload_game_with(io, game, &upload_options);
+        let stop = match &result {
+            Ok(UploadResult::OutOfMetadata) => true,
+            Ok(UploadResult::OutOfFlash) => options.stop_on_out_of_flash,
+            // The device responded, so it can still be uploaded to.
+            Ok(UploadResult::AllGood | UploadResult::Rejected(_) | UploadResult::Unconfirmed) => {
+                false
+            }
+            Err(
+                UploadError::IOError
+                | UploadError::NoResponse
+                | UploadError::Cancelled
+                | UploadError::WriteStalled
+                | UploadError::Timeout
+                | UploadError::Disconnected,
+            ) => true,
+            Err(UploadError::InvalidName | UploadError::FailedConversion) => false,
+        };
+
+        results.push((name.clone(), result));
+        if stop {
+            event!(
+                info,
+                uploaded = results.len(),
+                "stopping batch upload early"
+            );
+            break;
+        }
+    }
+
+    results
+}
@@ -0,0 +1,297 @@
+use std::fmt;
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+use crate::trace::event;
+use crate::{CancellationToken, SpadeTransport};
+
+/// A line of output printed by the device, such as by `console.log` in the
+/// running game.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConsoleLine {
+    text: String,
+}
+
+impl ConsoleLine {
+    /// Creates a line from the bytes read from the device, without the line
+    /// ending. Invalid UTF-8 is replaced with `U+FFFD`.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+
+        Self {
+            text: String::from_utf8_lossy(bytes).into_owned(),
+        }
+    }
+
+    /// Returns the text of the line, without the line ending.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl fmt::Display for ConsoleLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Splits the output of a device into `ConsoleLine`s.
+///
+/// Each call to `next` reads from the device until a full line is available.
+/// If a read fails, such as when it times out, the error is returned and
+/// iteration can be continued afterwards without losing output. Iteration ends
+/// when a read returns no bytes, which means the end of the stream was
+/// reached.
+///
+/// Any partial line which has been read but not returned is lost when the
+/// reader is dropped.
+pub struct ConsoleReader<'a, T: ?Sized> {
+    io: &'a mut T,
+    pending: Vec<u8>,
+}
+
+impl<'a, T: SpadeTransport + ?Sized> ConsoleReader<'a, T> {
+    /// Creates a reader for the output of `io`.
+    pub fn new(io: &'a mut T) -> Self {
+        Self {
+            io,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<T: SpadeTransport + ?Sized> Iterator for ConsoleReader<'_, T> {
+    type Item = Result<ConsoleLine, std::io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(end) = self.pending.iter().position(|&byte| byte == b'\n') {
+                let line = ConsoleLine::from_bytes(&self.pending[..end]);
+                self.pending.drain(..=end);
+
+                break Some(Ok(line));
+            }
+
+            let mut buf = [0; 64];
+            match self.io.read(&mut buf) {
+                Ok(0) if self.pending.is_empty() => break None,
+                Ok(0) => {
+                    let line = ConsoleLine::from_bytes(&self.pending);
+                    self.pending.clear();
+
+                    break Some(Ok(line));
+                }
+                Ok(bytes) => self.pending.extend_from_slice(&buf[..bytes]),
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => break Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// Passes each line printed by the device to `on_line` until the stream ends
+/// or `token` is cancelled.
+///
+/// Reads which time out are retried, so this can be used to show the output
+/// of a running game for as long as the serial port is open. The token is
+/// checked whenever a line is received or a read times out.
+///
+/// ### Errors
+/// This function returns any IO errors from `SpadeTransport::read` other than
+/// `ErrorKind::TimedOut` and `ErrorKind::WouldBlock`.
+pub fn attach_console(
+    io: &mut (impl SpadeTransport + ?Sized),
+    token: &CancellationToken,
+    mut on_line: impl FnMut(ConsoleLine),
+) -> Result<(), std::io::Error> {
+    let mut reader = ConsoleReader::new(io);
+
+    while !token.is_cancelled() {
+        match reader.next() {
+            Some(Ok(line)) => on_line(line),
+            Some(Err(err)) if matches!(err.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+                continue
+            }
+            Some(Err(err)) => return Err(err),
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// An uncaught JavaScript error printed by the device while running a game.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RuntimeError {
+    /// The error message, starting with the name of the error, like
+    /// `'TypeError: x is not a function'`.
+    pub message: String,
+    /// The line in the game source where the error occurred, if it was
+    /// printed.
+    pub line: Option<u32>,
+    /// The column in the game source where the error occurred, if it was
+    /// printed.
+    pub column: Option<u32>,
+}
+
+impl RuntimeError {
+    /// Parses an error from a line printed by the device.
+    ///
+    /// A line is considered an error if it contains the name of a JavaScript
+    /// error followed by a colon, like `'ReferenceError: x is not defined'` or
+    /// `'Uncaught Error: oops'`. The location is read from `'line 12'` or
+    /// `':12:5'` in the line, if present.
+    fn parse(text: &str) -> Option<Self> {
+        let start = error_name_start(text)?;
+        let (line, column) = parse_location(text);
+
+        Some(Self {
+            message: text[start..].trim_end().to_owned(),
+            line,
+            column,
+        })
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "{} ({}:{})", self.message, line, column),
+            (Some(line), None) => write!(f, "{} (line {})", self.message, line),
+            _ => f.write_str(&self.message),
+        }
+    }
+}
+
+impl ConsoleLine {
+    /// Returns the runtime error printed on this line, if it is one. See
+    /// `RuntimeError` for how lines are recognized.
+    pub fn runtime_error(&self) -> Option<RuntimeError> {
+        RuntimeError::parse(&self.text)
+    }
+}
+
+/// Finds the start of the first word ending in `'Error:'`, like `'TypeError:'`.
+fn error_name_start(text: &str) -> Option<usize> {
+    text.match_indices("Error:").find_map(|(index, _)| {
+        let start = text[..index]
+            .rfind(|c: char| !c.is_ascii_alphanumeric())
+            .map_or(0, |boundary| boundary + 1);
+
+        text[start..]
+            .starts_with(|c: char| c.is_ascii_uppercase())
+            .then_some(start)
+    })
+}
+
+/// Finds a line and column number, either written out as `'line 12'` and
+/// `'column 5'`, or as `':12:5'` at the end of a path.
+fn parse_location(text: &str) -> (Option<u32>, Option<u32>) {
+    let number_after = |word: &str| {
+        let index = text.find(word)? + word.len();
+        leading_number(&text[index..])
+    };
+
+    if let Some(line) = number_after("line ") {
+        return (Some(line), number_after("column "));
+    }
+
+    text.match_indices(':')
+        .find_map(|(index, _)| {
+            let rest = &text[index + 1..];
+            let line = leading_number(rest)?;
+            let column = rest
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .strip_prefix(':')
+                .and_then(leading_number);
+
+            Some((Some(line), column))
+        })
+        .unwrap_or((None, None))
+}
+
+/// Parses the digits at the start of `text`, if there are any.
+fn leading_number(text: &str) -> Option<u32> {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+
+    text[..end].parse().ok()
+}
+
+/// The state of a game shortly after it was uploaded, returned by
+/// `spade_serial::wait_for_runtime_status`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RuntimeStatus {
+    /// No error was printed by the device while waiting.
+    Started,
+    /// The device printed an uncaught error.
+    Error(RuntimeError),
+}
+
+/// Watches the output of the device for `window` to check if the game that
+/// was just uploaded crashed.
+///
+/// Any output which is not an error is discarded. If an error is printed, the
+/// stack trace lines following it (starting with `'at '`) are read to find the
+/// location of the error if it was not on the first line.
+///
+/// The time spent can exceed `window` by up to the read timeout of `io`. If
+/// the end of the stream is reached before `window` elapses, the game is
+/// considered to have started.
+///
+/// ### Errors
+/// This function returns any IO errors from `SpadeTransport::read` other than
+/// `ErrorKind::TimedOut` and `ErrorKind::WouldBlock`.
+pub fn wait_for_runtime_status(
+    io: &mut (impl SpadeTransport + ?Sized),
+    window: Duration,
+) -> Result<RuntimeStatus, std::io::Error> {
+    let deadline = Instant::now() + window;
+    let mut reader = ConsoleReader::new(io);
+
+    while Instant::now() < deadline {
+        match reader.next() {
+            Some(Ok(line)) => {
+                if let Some(mut error) = line.runtime_error() {
+                    event!(debug, line = line.text(), "runtime error printed");
+                    if error.line.is_none() {
+                        (error.line, error.column) = stack_trace_location(&mut reader);
+                    }
+
+                    return Ok(RuntimeStatus::Error(error));
+                }
+            }
+            Some(Err(err)) if matches!(err.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+                continue
+            }
+            Some(Err(err)) => return Err(err),
+            None => break,
+        }
+    }
+
+    Ok(RuntimeStatus::Started)
+}
+
+/// Reads the stack trace following an error and returns the first location in
+/// it. Stops at the first line which is not part of the stack trace, or if a
+/// read fails.
+fn stack_trace_location(
+    reader: &mut ConsoleReader<impl SpadeTransport + ?Sized>,
+) -> (Option<u32>, Option<u32>) {
+    while let Some(Ok(line)) = reader.next() {
+        if !line.text().trim_start().starts_with("at ") {
+            break;
+        }
+
+        let location = parse_location(line.text());
+        if location.0.is_some() {
+            return location;
+        }
+    }
+
+    (None, None)
+}
@@ -0,0 +1,60 @@
+//! Communication over [embedded-io](https://crates.io/crates/embedded-io)
+//! transports.
+//!
+//! These functions behave like the ones at the root of the crate, but work
+//! without `std`, so another microcontroller can upload games to a device
+//! running Spade over its UART or USB host port.
+
+use embedded_io::{Read, Write};
+
+use crate::protocol::{LEGACY_RESPONSE, LEGACY_STARTUP_SEQ};
+use crate::{UploadError, UploadMachine, UploadResult};
+
+/// Checks if the device is running a legacy Spade version.
+///
+/// See `spade_serial::is_running_legacy`, which this behaves like. The
+/// response is compared byte for byte, so a response which is not valid
+/// UTF-8 means the device is not running a legacy version.
+///
+/// ### Errors
+/// This function returns any errors from writing to or reading from `io`.
+pub fn is_running_legacy<T: Read + Write + ?Sized>(io: &mut T) -> Result<bool, T::Error> {
+    io.write_all(&LEGACY_STARTUP_SEQ)?;
+
+    let mut response_buf = [0; LEGACY_RESPONSE.len()];
+    io.read(&mut response_buf)?;
+
+    Ok(&response_buf == LEGACY_RESPONSE)
+}
+
+/// Uploads a game to a device running Spade.
+///
+/// See `spade_serial::upload_game`, which this behaves like, including
+/// taking the game as a string or bytes. Errors from writing to or reading
+/// from `io` are returned as `UploadError::IOError`.
+pub fn upload_game<T: Read + Write + ?Sized>(
+    io: &mut T,
+    name: &str,
+    game: &(impl AsRef<[u8]> + ?Sized),
+) -> Result<UploadResult, UploadError> {
+    let mut machine = UploadMachine::new(name, game)?;
+
+    while let Some(bytes) = machine.next_write() {
+        io.write_all(bytes).map_err(|_| UploadError::IOError)?;
+        if machine.needs_flush() {
+            io.flush().map_err(|_| UploadError::IOError)?;
+        }
+    }
+
+    let mut buf = [0; 64];
+    loop {
+        let bytes = io.read(&mut buf).map_err(|_| UploadError::IOError)?;
+        if bytes > 0 {
+            if let Some(result) = machine.feed_read(&buf[..bytes]) {
+                break Ok(result);
+            }
+        } else {
+            break machine.finish_read().ok_or(UploadError::NoResponse);
+        }
+    }
+}
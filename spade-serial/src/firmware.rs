@@ -0,0 +1,233 @@
+//! Updating the Spade firmware on a Sprig.
+//!
+//! The RP2040 in the Sprig can be restarted into its BOOTSEL mode, where it
+//! appears as a USB drive named 'RPI-RP2'. Copying a UF2 image of Spade to the
+//! drive flashes it and restarts the device, which then appears as a serial
+//! port again. This is how legacy devices can be updated to a Spade version
+//! supporting the current protocol.
+//!
+//! Where the drive is mounted depends on the operating system and how it is
+//! set up, so it must be given, although `find_drive` checks the usual places.
+//! Flashing over USB without the drive, with picoboot, is not supported.
+//!
+//! Available with the `serialport` feature.
+//! ```no_run
+//! use spade_serial::firmware;
+//!
+//! let image = std::fs::read("spade.uf2").unwrap();
+//! let port = firmware::update_firmware("/dev/ttyACM0", "/media/user/RPI-RP2", &image)?;
+//! println!("Updated device at {}", port.port_name);
+//! # Ok::<(), firmware::FirmwareError>(())
+//! ```
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serialport::SerialPortInfo;
+
+use crate::port::wait_for_ports;
+
+/// Opening the serial port at this baud rate restarts devices using the Pico
+/// SDK's USB serial, like Spade, into BOOTSEL mode.
+pub const BOOTSEL_BAUD_RATE: u32 = 1200;
+
+/// How long `update_firmware` waits for the drive to be mounted and for the
+/// device to appear again after flashing.
+pub const DEFAULT_WAIT: Duration = Duration::from_secs(10);
+
+/// The file every RP2040 BOOTSEL drive contains.
+const INFO_FILE: &str = "INFO_UF2.TXT";
+
+/// The name of the BOOTSEL drive.
+const DRIVE_NAME: &str = "RPI-RP2";
+
+/// The name of the file the image is copied to on the drive. The RP2040 flashes
+/// any UF2 file written to it.
+const IMAGE_FILE: &str = "SPADE.UF2";
+
+/// The size of each block in a UF2 image.
+const UF2_BLOCK_LEN: usize = 512;
+
+/// The magic numbers at the start of each UF2 block.
+const UF2_MAGIC: [u32; 2] = [0x0A324655, 0x9E5D5157];
+
+/// How often the drive is checked while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Represents the errors which can occur while updating the firmware.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FirmwareError {
+    /// The image is not a UF2 image.
+    InvalidImage,
+    /// The BOOTSEL drive did not appear at the given path in time.
+    DriveNotFound,
+    /// No device appeared after the image was copied to the drive.
+    DeviceNotFound,
+    /// An error occurred while opening the serial port or copying the image.
+    IOError,
+}
+
+impl From<std::io::Error> for FirmwareError {
+    fn from(_value: std::io::Error) -> Self {
+        FirmwareError::IOError
+    }
+}
+
+impl From<serialport::Error> for FirmwareError {
+    fn from(_value: serialport::Error) -> Self {
+        FirmwareError::IOError
+    }
+}
+
+impl fmt::Display for FirmwareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FirmwareError::InvalidImage => write!(f, "firmware image is not a UF2 image"),
+            FirmwareError::DriveNotFound => write!(f, "BOOTSEL drive was not found"),
+            FirmwareError::DeviceNotFound => {
+                write!(f, "device did not appear after updating its firmware")
+            }
+            FirmwareError::IOError => write!(f, "error occurred while updating the firmware"),
+        }
+    }
+}
+
+/// Updates the firmware of the device at `port_path` to the UF2 image
+/// `uf2`, and returns the serial port of the device once it has restarted.
+///
+/// The device is restarted into BOOTSEL mode with `enter_bootsel`, the image
+/// is copied to the drive mounted at `mount` with `copy_uf2` once it appears,
+/// and then the device is waited for with `wait_for_device`. Each wait gives
+/// up after `DEFAULT_WAIT`.
+///
+/// ### Errors
+/// Returns `FirmwareError::InvalidImage` before touching the device if `uf2`
+/// is not a UF2 image, or any error from the steps above.
+pub fn update_firmware(
+    port_path: &str,
+    mount: impl AsRef<Path>,
+    uf2: &[u8],
+) -> Result<SerialPortInfo, FirmwareError> {
+    validate_uf2(uf2)?;
+    enter_bootsel(port_path)?;
+    copy_uf2(mount, uf2, DEFAULT_WAIT)?;
+    wait_for_device(DEFAULT_WAIT)
+}
+
+/// Restarts the device at `port_path` into BOOTSEL mode by opening its serial
+/// port at `BOOTSEL_BAUD_RATE`.
+///
+/// ### Errors
+/// Returns `FirmwareError::IOError` if the port cannot be opened.
+pub fn enter_bootsel(port_path: &str) -> Result<(), FirmwareError> {
+    // The device restarts as soon as the port is opened, so the port is
+    // closed right away.
+    drop(serialport::new(port_path, BOOTSEL_BAUD_RATE).open()?);
+    Ok(())
+}
+
+/// Copies the UF2 image `uf2` to the BOOTSEL drive mounted at `mount`, waiting
+/// up to `wait` for the drive to appear.
+///
+/// ### Errors
+/// Returns `FirmwareError::InvalidImage` if `uf2` is not a UF2 image,
+/// `FirmwareError::DriveNotFound` if `mount` is not a BOOTSEL drive in time,
+/// or `FirmwareError::IOError` if the image cannot be written.
+pub fn copy_uf2(mount: impl AsRef<Path>, uf2: &[u8], wait: Duration) -> Result<(), FirmwareError> {
+    validate_uf2(uf2)?;
+
+    let mount = mount.as_ref();
+    let deadline = Instant::now() + wait;
+    while !is_drive(mount) {
+        if Instant::now() >= deadline {
+            return Err(FirmwareError::DriveNotFound);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    std::fs::write(mount.join(IMAGE_FILE), uf2)?;
+    Ok(())
+}
+
+/// Waits up to `wait` for a device which could be running Spade to appear,
+/// and returns its serial port. See `spade_serial::discover`.
+///
+/// ### Errors
+/// Returns `FirmwareError::DeviceNotFound` if no device appeared in time, or
+/// `FirmwareError::IOError` if the serial ports cannot be listed.
+pub fn wait_for_device(wait: Duration) -> Result<SerialPortInfo, FirmwareError> {
+    wait_for_ports(wait)?
+        .into_iter()
+        .next()
+        .ok_or(FirmwareError::DeviceNotFound)
+}
+
+/// Waits up to `wait` for a BOOTSEL drive to be mounted where operating
+/// systems usually mount it, and returns where it is mounted: `/Volumes` on
+/// macOS, any drive letter on Windows, and `/media/$USER`, `/run/media/$USER`
+/// or `/media` elsewhere.
+///
+/// ### Errors
+/// Returns `FirmwareError::DriveNotFound` if no drive was found in time.
+pub fn find_drive(wait: Duration) -> Result<PathBuf, FirmwareError> {
+    let candidates = drive_candidates();
+    let deadline = Instant::now() + wait;
+    loop {
+        if let Some(mount) = candidates.iter().find(|mount| is_drive(mount)) {
+            return Ok(mount.clone());
+        }
+        if Instant::now() >= deadline {
+            return Err(FirmwareError::DriveNotFound);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Returns where a BOOTSEL drive could be mounted. See `find_drive`.
+fn drive_candidates() -> Vec<PathBuf> {
+    if cfg!(target_os = "macos") {
+        return vec![Path::new("/Volumes").join(DRIVE_NAME)];
+    }
+    if cfg!(windows) {
+        return ('D'..='Z')
+            .map(|letter| PathBuf::from(format!("{letter}:\\")))
+            .collect();
+    }
+
+    let mut candidates = Vec::new();
+    if let Ok(user) = std::env::var("USER") {
+        candidates.push(Path::new("/media").join(&user).join(DRIVE_NAME));
+        candidates.push(Path::new("/run/media").join(&user).join(DRIVE_NAME));
+    }
+    candidates.push(Path::new("/media").join(DRIVE_NAME));
+    candidates
+}
+
+/// Checks whether a BOOTSEL drive is mounted at `mount`.
+fn is_drive(mount: &Path) -> bool {
+    mount.join(INFO_FILE).is_file()
+}
+
+/// Checks that `uf2` is made of UF2 blocks, so it can be checked before the
+/// device is restarted into BOOTSEL mode.
+///
+/// ### Errors
+/// Returns `FirmwareError::InvalidImage` if `uf2` is not a UF2 image.
+pub fn validate_uf2(uf2: &[u8]) -> Result<(), FirmwareError> {
+    let valid = !uf2.is_empty()
+        && uf2.len().is_multiple_of(UF2_BLOCK_LEN)
+        && uf2.chunks(UF2_BLOCK_LEN).all(|block| {
+            block
+                .chunks(4)
+                .zip(UF2_MAGIC)
+                .all(|(bytes, magic)| bytes == magic.to_le_bytes())
+        });
+
+    match valid {
+        true => Ok(()),
+        false => Err(FirmwareError::InvalidImage),
+    }
+}
@@ -0,0 +1,86 @@
+use std::thread;
+
+use serialport::SerialPortInfo;
+
+use crate::trace::{event, span};
+use crate::{discover, open, upload, Game, SpadeTransport, UploadError, UploadResult};
+
+/// All of the devices connected to this computer, to upload to at once.
+///
+/// Each upload opens its device's port with `spade_serial::open` on its own
+/// thread, so uploading to many devices takes about as long as uploading to
+/// one.
+/// ```no_run
+/// use spade_serial::{Fleet, Game};
+///
+/// let game = Game::from_file("workshop.js").unwrap();
+/// for (port, result) in Fleet::discover()?.upload(&game) {
+///     println!("{port}: {result:?}");
+/// }
+/// # Ok::<(), serialport::Error>(())
+/// ```
+///
+/// Available with the `serialport` feature.
+#[derive(Debug, Clone, Default)]
+pub struct Fleet {
+    ports: Vec<String>,
+}
+
+impl Fleet {
+    /// Creates a fleet of the devices found by `spade_serial::discover`.
+    ///
+    /// ### Errors
+    /// Returns any errors from `spade_serial::discover`.
+    pub fn discover() -> Result<Self, serialport::Error> {
+        Ok(Self::from_ports(discover()?))
+    }
+
+    /// Creates a fleet of the devices at `ports`.
+    pub fn from_ports(ports: impl IntoIterator<Item = SerialPortInfo>) -> Self {
+        Self {
+            ports: ports.into_iter().map(|port| port.port_name).collect(),
+        }
+    }
+
+    /// Returns the paths of the ports in the fleet.
+    pub fn ports(&self) -> &[String] {
+        &self.ports
+    }
+
+    /// Uploads `game` to every device in the fleet in parallel, and returns
+    /// the result for each port in the same order as `Fleet::ports`.
+    ///
+    /// A device whose port cannot be opened gets `UploadError::IOError`, and
+    /// does not affect the others.
+    pub fn upload(&self, game: &Game) -> Vec<(String, Result<UploadResult, UploadError>)> {
+        upload_each(&self.ports, |path| Ok(open(path)?), game)
+    }
+}
+
+/// Opens each port with `open` and uploads `game` to it, each on its own
+/// thread.
+pub(crate) fn upload_each<T: SpadeTransport>(
+    ports: &[String],
+    open: impl Fn(&str) -> Result<T, std::io::Error> + Sync,
+    game: &Game,
+) -> Vec<(String, Result<UploadResult, UploadError>)> {
+    span!(info_span, "fleet_upload", devices = ports.len());
+
+    let open = &open;
+    thread::scope(|scope| {
+        let uploads: Vec<_> = ports
+            .iter()
+            .map(|path| scope.spawn(move || upload(&mut open(path)?, game)))
+            .collect();
+
+        ports
+            .iter()
+            .zip(uploads)
+            .map(|(path, upload)| {
+                let result = upload.join().unwrap_or(Err(UploadError::IOError));
+                event!(info, port = %path, ?result, "uploaded to device");
+                (path.clone(), result)
+            })
+            .collect()
+    })
+}
@@ -0,0 +1,220 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::GameName;
+
+/// A game ready to be uploaded, with its name and source.
+///
+/// Creating a `Game` checks the source, so problems are found before anything
+/// is sent to the device. Upload it with `spade_serial::upload`.
+///
+/// The source is usually JavaScript, but it is held as bytes and sent as it
+/// is, so formats which are not UTF-8 text can be uploaded too.
+///
+/// With the `serde` feature, it can be serialized but not deserialized, since
+/// that would skip the checks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Game {
+    name: GameName,
+    source: Vec<u8>,
+}
+
+/// Represents the reasons a game can be rejected by `Game::new` and similar
+/// constructors.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GameError {
+    /// The source is empty.
+    EmptySource,
+    /// The source is too large for its length to be sent to the device. See
+    /// `Game::MAX_SOURCE_BYTES`.
+    TooLarge,
+    /// The source does not have a Sprig `@title:` header, which was required.
+    MissingTitle,
+    /// The name could not be stored on the device. See `GameName::new`.
+    InvalidName,
+    /// An error occurred while reading the game from a file.
+    IOError,
+}
+
+impl From<std::io::Error> for GameError {
+    fn from(_value: std::io::Error) -> Self {
+        GameError::IOError
+    }
+}
+
+impl From<crate::GameNameError> for GameError {
+    fn from(_value: crate::GameNameError) -> Self {
+        GameError::InvalidName
+    }
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::EmptySource => write!(f, "game source is empty"),
+            GameError::TooLarge => write!(
+                f,
+                "game source is larger than {} bytes",
+                Game::MAX_SOURCE_BYTES
+            ),
+            GameError::MissingTitle => write!(f, "game source has no @title: header"),
+            GameError::InvalidName => write!(f, "game name cannot be stored on the device"),
+            GameError::IOError => write!(f, "error occurred while reading the game"),
+        }
+    }
+}
+
+/// The metadata declared in the header comment of a Sprig game.
+///
+/// Games in the Sprig gallery start with a block comment like this, which
+/// `GameMetadata::parse` reads:
+/// ```text
+/// /*
+/// @title: Maze
+/// @author: leo
+/// @tags: ['puzzle', 'maze']
+/// */
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameMetadata {
+    /// The value of `@title:`, if it is present and not empty.
+    pub title: Option<String>,
+    /// The value of `@author:`, if it is present and not empty.
+    pub author: Option<String>,
+    /// The values listed by `@tags:`, without quotes.
+    pub tags: Vec<String>,
+}
+
+impl GameMetadata {
+    /// Reads the metadata from the block comment at the start of `source`.
+    ///
+    /// Fields which are missing from the comment, or which are not in a
+    /// comment at the start of the source, are left empty. Unknown fields are
+    /// ignored.
+    pub fn parse(source: &str) -> Self {
+        let mut metadata = Self::default();
+
+        let Some(header) = source.trim_start().strip_prefix("/*") else {
+            return metadata;
+        };
+        let header = header.split("*/").next().unwrap_or_default();
+
+        for line in header.lines() {
+            let line = line.trim_start_matches(|c: char| c.is_whitespace() || c == '*');
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match key {
+                "@title" if !value.is_empty() => metadata.title = Some(value.to_owned()),
+                "@author" if !value.is_empty() => metadata.author = Some(value.to_owned()),
+                "@tags" => {
+                    metadata.tags = value
+                        .trim_start_matches('[')
+                        .trim_end_matches(']')
+                        .split(',')
+                        .map(|tag| tag.trim().trim_matches(|c| c == '\'' || c == '"'))
+                        .filter(|tag| !tag.is_empty())
+                        .map(str::to_owned)
+                        .collect()
+                }
+                _ => {}
+            }
+        }
+
+        metadata
+    }
+}
+
+impl Game {
+    /// The largest source that can be uploaded, in bytes, since its length is
+    /// sent as a 32 bit integer. The device may still reject a smaller game
+    /// with `UploadResult::OutOfFlash` if it does not have enough space.
+    pub const MAX_SOURCE_BYTES: usize = u32::MAX as usize;
+
+    /// Creates a game, checking that its source can be uploaded.
+    ///
+    /// ### Errors
+    /// Returns `GameError::EmptySource` if `source` is empty, or
+    /// `GameError::TooLarge` if it is over `MAX_SOURCE_BYTES` bytes long.
+    pub fn new(name: GameName, source: impl Into<Vec<u8>>) -> Result<Self, GameError> {
+        let source = source.into();
+        if source.is_empty() {
+            Err(GameError::EmptySource)
+        } else if source.len() > Self::MAX_SOURCE_BYTES {
+            Err(GameError::TooLarge)
+        } else {
+            Ok(Self { name, source })
+        }
+    }
+
+    /// Creates a game like `Game::new`, but also requires the source to have a
+    /// Sprig `@title:` header with a value, like the games in the Sprig
+    /// gallery. See `GameMetadata`.
+    ///
+    /// ### Errors
+    /// Returns the same errors as `Game::new`, or `GameError::MissingTitle` if
+    /// there is no `@title:` header.
+    pub fn new_with_title(name: GameName, source: impl Into<Vec<u8>>) -> Result<Self, GameError> {
+        let game = Self::new(name, source)?;
+        match game.metadata().title {
+            Some(_) => Ok(game),
+            None => Err(GameError::MissingTitle),
+        }
+    }
+
+    /// Creates a game named after the `@title:` header in its source.
+    ///
+    /// The name is made to fit with `GameName::truncate_lossy`.
+    ///
+    /// ### Errors
+    /// Returns the same errors as `Game::new`, or `GameError::MissingTitle` if
+    /// there is no `@title:` header.
+    pub fn from_source_with_inferred_name(source: impl Into<Vec<u8>>) -> Result<Self, GameError> {
+        let source = source.into();
+        let title = GameMetadata::parse(&String::from_utf8_lossy(&source))
+            .title
+            .ok_or(GameError::MissingTitle)?;
+
+        Self::new(GameName::truncate_lossy(&title), source)
+    }
+
+    /// Reads a game from the file at `path`, named after the file without its
+    /// extension.
+    ///
+    /// The name is made to fit with `GameName::truncate_lossy`.
+    ///
+    /// ### Errors
+    /// Returns `GameError::IOError` if the file cannot be read, or the same
+    /// errors as `Game::new`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, GameError> {
+        let path = path.as_ref();
+        let source = std::fs::read(path)?;
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+
+        Self::new(GameName::truncate_lossy(&name), source)
+    }
+
+    /// Returns the name the game is stored under.
+    pub fn name(&self) -> &GameName {
+        &self.name
+    }
+
+    /// Returns the source of the game.
+    pub fn source(&self) -> &[u8] {
+        &self.source
+    }
+
+    /// Reads the metadata from the header comment of the source. Parts of
+    /// the source which are not valid UTF-8 are read as U+FFFD.
+    pub fn metadata(&self) -> GameMetadata {
+        GameMetadata::parse(&String::from_utf8_lossy(&self.source))
+    }
+}
@@ -0,0 +1,96 @@
+use crate::protocol::{LEGACY_END_SEQ, LEGACY_RESPONSE, LEGACY_STARTUP_SEQ};
+use crate::trace::{event, span};
+use crate::{
+    probe, upload_named, ProbeResult, ResponseScanner, SpadeTransport, UploadError, UploadResult,
+};
+
+/// Uploads a game to a device running a legacy Spade version.
+///
+/// Legacy versions store a single game without a name. The game is sent
+/// between the legacy startup sequence, `[0, 1, 2, 3, 4]`, and the end
+/// sequence, `[4, 3, 2, 1, 0]`, after the device responds to the startup
+/// sequence with 'found startup seq!'. The device takes the first end
+/// sequence as the end of the game, so a game containing those bytes, which
+/// only binary data would, is cut short.
+///
+/// Legacy versions do not respond once the game has been received, so
+/// `UploadResult::Unconfirmed` is returned once it has been written; they
+/// never report running out of space.
+///
+/// ### Errors
+/// Returns `UploadError::IOError` if an I/O operation fails, or
+/// `UploadError::NoResponse` if the output from the device ended without
+/// responding to the startup sequence.
+pub fn upload_game_legacy(
+    io: &mut (impl SpadeTransport + ?Sized),
+    game: impl AsRef<[u8]>,
+) -> Result<UploadResult, UploadError> {
+    let game = game.as_ref();
+    span!(info_span, "upload_game_legacy", game_bytes = game.len());
+
+    io.write_all(&LEGACY_STARTUP_SEQ)?;
+    io.flush()?;
+
+    let mut scanner = ResponseScanner::new(&[(LEGACY_RESPONSE, ())]);
+    let mut buf = [0; 64];
+    loop {
+        let bytes = io.read(&mut buf)?;
+        if bytes == 0 {
+            event!(warn, "device output ended without a response");
+            return Err(UploadError::NoResponse);
+        }
+        if scanner.feed(&buf[..bytes]).is_some() {
+            break;
+        }
+    }
+
+    write_legacy_game(io, game)
+}
+
+/// Uploads a game with the protocol the device supports.
+///
+/// The device is checked with `spade_serial::probe`, so output from the
+/// running game before the response does not matter. Legacy versions are
+/// then sent the game like with `spade_serial::upload_game_legacy`, ignoring
+/// `name`; others like with `spade_serial::upload_game`.
+///
+/// ### Errors
+/// Returns the same errors as `spade_serial::upload_game`, or
+/// `UploadError::NoResponse` if the device did not respond like any Spade
+/// version.
+pub fn upload_game_auto(
+    io: &mut (impl SpadeTransport + ?Sized),
+    name: impl AsRef<str>,
+    game: impl AsRef<[u8]>,
+) -> Result<UploadResult, UploadError> {
+    let game = game.as_ref();
+    // Probing sends the startup sequence, so a legacy device is already
+    // waiting for the game afterwards.
+    match probe(io)? {
+        ProbeResult::Legacy => {
+            event!(info, protocol = "legacy", "uploading");
+            write_legacy_game(io, game)
+        }
+        ProbeResult::Modern => {
+            event!(info, protocol = "v2", "uploading");
+            upload_named(io, name.as_ref(), game, None)
+        }
+        ProbeResult::NotSpade => {
+            event!(warn, "device did not respond like Spade");
+            Err(UploadError::NoResponse)
+        }
+    }
+}
+
+/// Writes a game to a legacy device which has received the startup sequence.
+pub(crate) fn write_legacy_game(
+    io: &mut (impl SpadeTransport + ?Sized),
+    game: &[u8],
+) -> Result<UploadResult, UploadError> {
+    event!(debug, bytes = game.len(), "writing game");
+    io.write_all(game)?;
+    io.write_all(&LEGACY_END_SEQ)?;
+    io.flush()?;
+
+    Ok(UploadResult::Unconfirmed)
+}
@@ -1,11 +1,13 @@
 //! Communication with devices running Spade.
 //!
 //! Issues commands to a device running Spade over a provided serial port. It
-//! can be used to upload games and check if the device is running a legacy
-//! Spade version. This crate is not thread safe.
+//! can be used to upload games, check if the device is running a legacy
+//! Spade version, and read the console output of the running game. The
+//! functions in this crate each need exclusive access to the serial port; to
+//! share a device between threads, use `SyncSpadeDevice`.
 //!
-//! First, get a `Read + Write`r for the serial port connected to the device.
-//! You can do this by using the
+//! First, get a `SpadeTransport` for the serial port connected to the device.
+//! Any `Read + Write`r is one, so you can do this by using the
 //! [serialport](https://crates.io/crates/serialport) crate; this example uses
 //! the device at `/dev/cu.usbmodem14101`.
 //! ```no_run
@@ -17,6 +19,10 @@
 //! # let legacy = spade_serial::is_running_legacy(&mut port).unwrap_or(false);
 //! # Ok::<(), serialport::Error>(())
 //! ```
+//! With the `serialport` feature enabled, `spade_serial::open` does the same,
+//! and `spade_serial::discover` lists the ports which could be connected to a
+//! device running Spade.
+//!
 //! Then, pass it to methods in this crate.
 //! ```no_run
 //! # use std::time::Duration;
@@ -27,13 +33,126 @@
 //! let legacy = spade_serial::is_running_legacy(&mut port).unwrap_or(false);
 //! # Ok::<(), serialport::Error>(())
 //! ```
+//!
+//! ### Features
+//! - `std` (default): Everything which needs the standard library, including
+//!   `SpadeTransport` and the functions which use it. Without it, the crate
+//!   is `no_std` and only `UploadMachine`, the types it uses, and the constants
+//!   in the `protocol` module are available.
+//! - `serialport`: `spade_serial::open`, `spade_serial::discover`, `Fleet`,
+//!   and the `firmware` module.
+//! - `embedded-io`: The `embedded` module, which uploads over
+//!   [embedded-io](https://crates.io/crates/embedded-io) transports, like the
+//!   UART of another microcontroller. It does not need `std`.
+//! - `tracing`: Spans and events from
+//!   [tracing](https://crates.io/crates/tracing) for each command, including
+//!   the phases of an upload at `DEBUG` and the raw bytes exchanged with the
+//!   device at `TRACE`.
+//! - `mock`: The `mock` module, with a mock device running Spade for testing
+//!   code which uses this crate without a physical device.
+//! - `web`: The `web` module, which uploads over the Web Serial API from a
+//!   browser, with [wasm-bindgen](https://crates.io/crates/wasm-bindgen). It
+//!   does not need `std`, so the rest of the crate can be left out.
+//! - `serde`: `Serialize` and `Deserialize` from
+//!   [serde](https://crates.io/crates/serde) for the results and errors of
+//!   commands, and other plain data like `GameName` and `RuntimeError`.
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(missing_docs)]
 
-use std::convert::TryFrom;
-use std::fmt;
-use std::io::{ErrorKind, Read, Write};
-use std::num::TryFromIntError;
-use std::str::Utf8Error;
+#[cfg(feature = "std")]
+mod batch;
+#[cfg(feature = "std")]
+mod cancel;
+#[cfg(feature = "std")]
+mod console;
+#[cfg(feature = "embedded-io")]
+pub mod embedded;
+#[cfg(feature = "serialport")]
+pub mod firmware;
+#[cfg(feature = "serialport")]
+mod fleet;
+#[cfg(feature = "std")]
+mod game;
+#[cfg(feature = "std")]
+mod legacy;
+mod machine;
+#[cfg(any(feature = "mock", all(test, feature = "std")))]
+pub mod mock;
+mod name;
+#[cfg(feature = "std")]
+mod options;
+#[cfg(feature = "serialport")]
+mod port;
+#[cfg(feature = "std")]
+mod probe;
+pub mod protocol;
+#[cfg(feature = "std")]
+mod raw;
+mod rejection;
+mod scanner;
+#[cfg(feature = "std")]
+mod sync;
+mod trace;
+#[cfg(feature = "std")]
+pub mod transport;
+// web-sys only provides the Web Serial API to WebAssembly, with its unstable
+// APIs enabled, so the module is left out elsewhere, like with
+// `--all-features` on other targets.
+#[cfg(all(feature = "web", target_arch = "wasm32", web_sys_unstable_apis))]
+pub mod web;
+#[cfg(all(feature = "web", target_arch = "wasm32", not(web_sys_unstable_apis)))]
+compile_error!("the web feature needs RUSTFLAGS=--cfg=web_sys_unstable_apis for web-sys to provide the Web Serial API");
+
+#[cfg(feature = "std")]
+pub use batch::{upload_many, BatchOptions};
+#[cfg(feature = "std")]
+pub use cancel::CancellationToken;
+#[cfg(feature = "std")]
+pub use console::{
+    attach_console, wait_for_runtime_status, ConsoleLine, ConsoleReader, RuntimeError,
+    RuntimeStatus,
+};
+#[cfg(feature = "serialport")]
+pub use fleet::Fleet;
+#[cfg(feature = "std")]
+pub use game::{Game, GameError, GameMetadata};
+#[cfg(feature = "std")]
+pub use legacy::{upload_game_auto, upload_game_legacy};
+pub use machine::{UploadMachine, UploadProgress};
+#[cfg(feature = "std")]
+pub use name::GameName;
+pub use name::GameNameError;
+#[cfg(feature = "std")]
+pub use options::{upload_game_reported, upload_game_with, UploadOptions, UploadReport};
+#[cfg(feature = "serialport")]
+pub use port::{
+    discover, negotiate_baud, open, DEFAULT_BAUD_RATE, DEFAULT_TIMEOUT, SPADE_USB_PID,
+    SPADE_USB_VID,
+};
+#[cfg(feature = "std")]
+pub use probe::{probe, ProbeResult};
+#[cfg(feature = "std")]
+pub use raw::{send_raw_command, ResponseMatcher};
+pub use rejection::{Rejection, MAX_REJECTION_LEN};
+pub use scanner::{ResponseScanner, MAX_TOKEN_LEN};
+#[cfg(feature = "std")]
+pub use sync::SyncSpadeDevice;
+#[cfg(feature = "std")]
+pub use transport::SpadeTransport;
+
+use core::fmt;
+use core::num::TryFromIntError;
+use core::str::Utf8Error;
+#[cfg(feature = "std")]
+use std::io::ErrorKind;
+
+#[cfg(feature = "std")]
+use protocol::{LEGACY_RESPONSE, LEGACY_STARTUP_SEQ};
+#[cfg(feature = "std")]
+use transport::PacketWriter;
+
+#[cfg(feature = "std")]
+use trace::{event, span};
 
 /// Checks if the device is running a legacy Spade version.
 ///
@@ -47,16 +166,25 @@ use std::str::Utf8Error;
 /// yield indefinitely.
 ///
 /// ### Errors
-/// This function may return any IO errors from `Write::write_all` or
-/// `Read::read`. It may also return an error of `ErrorKind::InvalidData` if
+/// This function may return any IO errors from `SpadeTransport::write_all` or
+/// `SpadeTransport::read`. It may also return an error of `ErrorKind::InvalidData` if
 /// the response from the device is not valid UTF-8.
-pub fn is_running_legacy(io: &mut (impl Write + Read)) -> Result<bool, std::io::Error> {
-    let legacy_startup_seq = [0, 1, 2, 3, 4];
-    io.write_all(&legacy_startup_seq).and_then(|_| {
-        let mut response_buf = [0; 18];
-        io.read(&mut response_buf[..]).and_then(|_| {
+#[cfg(feature = "std")]
+pub fn is_running_legacy(io: &mut (impl SpadeTransport + ?Sized)) -> Result<bool, std::io::Error> {
+    span!(debug_span, "is_running_legacy");
+
+    io.write_all(&LEGACY_STARTUP_SEQ).and_then(|_| {
+        let mut response_buf = [0; LEGACY_RESPONSE.len()];
+        io.read(&mut response_buf[..]).and_then(|_bytes| {
+            event!(
+                debug,
+                bytes = _bytes,
+                response = %String::from_utf8_lossy(&response_buf[.._bytes]),
+                "received legacy probe response"
+            );
+
             std::str::from_utf8(&response_buf)
-                .map(|response| response == "found startup seq!")
+                .map(|response| response.as_bytes() == LEGACY_RESPONSE)
                 .map_err(|_| std::io::Error::from(ErrorKind::InvalidData))
         })
     })
@@ -67,6 +195,8 @@ pub fn is_running_legacy(io: &mut (impl Write + Read)) -> Result<bool, std::io::
 ///
 /// Returned in a Result by `spade_serial::upload_game`.
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UploadResult {
     /// Represents the response `'ALL_GOOD'`. This means the game was accepted
     /// by the device.
@@ -79,28 +209,86 @@ pub enum UploadResult {
     /// rejected by the device because it has already reached the limit of games
     /// that can be stored.
     OutOfMetadata,
+    /// Represents a response rejecting the game which this crate does not
+    /// know, like `'OO_NAME'` from a newer Spade version. If the output of
+    /// the device ends without a known response, the last `'OO_'` followed
+    /// by uppercase letters, digits and underscores in it is taken as one,
+    /// instead of failing with `UploadError::NoResponse`.
+    Rejected(Rejection),
+    /// The game was written to a device running a legacy Spade version.
+    /// Legacy versions do not respond once they have received a game, so
+    /// whether it was accepted is not known.
+    Unconfirmed,
+}
+
+impl UploadResult {
+    /// Returns the response of the device, like `"ALL_GOOD"` or the
+    /// rejection, or `"UNCONFIRMED"` for `UploadResult::Unconfirmed`, which
+    /// has none.
+    /// ```
+    /// # use spade_serial::UploadResult;
+    /// assert_eq!(UploadResult::OutOfFlash.response(), "OO_FLASH");
+    /// ```
+    pub fn response(&self) -> &str {
+        match self {
+            UploadResult::AllGood => "ALL_GOOD",
+            UploadResult::OutOfFlash => "OO_FLASH",
+            UploadResult::OutOfMetadata => "OO_METADATA",
+            UploadResult::Rejected(rejection) => rejection.as_str(),
+            UploadResult::Unconfirmed => "UNCONFIRMED",
+        }
+    }
 }
 
 /// Represents the possible communication errors while trying to upload a game
 /// with `spade_serial::upload_game`.
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UploadError {
-    /// The name provided was too large (over 100 bytes).
+    /// The name provided was too large (over 100 bytes) or contained a NUL
+    /// character. See `spade_serial::GameName`.
     InvalidName,
     /// An important type conversion failed. This could be due to the size of
-    /// `usize` on the running device or invalud UTF-8 from the serial device.
+    /// `usize` on the running device or invalid UTF-8 from the serial device.
     FailedConversion,
-    /// An error occured during an I/O operation, like reading or writing from
+    /// An error occurred during an I/O operation, like reading or writing from
     /// the serial port.
     IOError,
     /// The output from the device was read, but no response regarding the
     /// upload operation was found.
     NoResponse,
+    /// The upload was cancelled with a `CancellationToken` before a response
+    /// was received.
+    Cancelled,
+    /// The device stopped accepting bytes partway through a write. See
+    /// `SpadeTransport::write_all`.
+    WriteStalled,
+    /// Reading from or writing to the device timed out, with an I/O error of
+    /// `ErrorKind::TimedOut`. While waiting for the response, reads which
+    /// time out are only given up on after `UploadOptions::max_timeouts` of
+    /// them in a row.
+    Timeout,
+    /// The device was disconnected, like by being unplugged, so nothing more
+    /// can be read from or written to it. This is an I/O error of
+    /// `ErrorKind::BrokenPipe` or `ErrorKind::NotConnected`, which serial
+    /// ports return once the device is gone.
+    ///
+    /// Spade has no command to check that a device is still there while it
+    /// is writing a game to flash, so a device disconnected while it is being
+    /// waited on is only noticed when reading from it fails.
+    Disconnected,
 }
 
+#[cfg(feature = "std")]
 impl From<std::io::Error> for UploadError {
-    fn from(_value: std::io::Error) -> Self {
-        UploadError::IOError
+    fn from(value: std::io::Error) -> Self {
+        match value.kind() {
+            ErrorKind::WriteZero => UploadError::WriteStalled,
+            ErrorKind::TimedOut => UploadError::Timeout,
+            ErrorKind::BrokenPipe | ErrorKind::NotConnected => UploadError::Disconnected,
+            _ => UploadError::IOError,
+        }
     }
 }
 
@@ -118,214 +306,1530 @@ impl From<Utf8Error> for UploadError {
 
 impl fmt::Display for UploadError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "error occured during upload")
+        match self {
+            UploadError::InvalidName => write!(f, "game name cannot be stored on the device"),
+            UploadError::FailedConversion => write!(f, "game is too large to upload"),
+            UploadError::IOError => write!(f, "reading from or writing to the device failed"),
+            UploadError::NoResponse => write!(f, "device did not respond to the upload"),
+            UploadError::Cancelled => write!(f, "upload was cancelled"),
+            UploadError::WriteStalled => write!(f, "device stopped accepting bytes"),
+            UploadError::Timeout => write!(f, "device timed out"),
+            UploadError::Disconnected => write!(f, "device was disconnected"),
+        }
     }
 }
 
 /// Uploads a game to a device running Spade.
 ///
-/// This function will send a game to a device via the provided `Write` using
+/// This function will send a game to a device via the provided transport using
 /// the `'UPLOAD'` command. Then, it waits for one of the expected
-/// `UploadResult`s from the `Read`.
+/// `UploadResult`s from the device. The name can be any kind of string, like
+/// `&str` or `&String`. The game can be a string or bytes, like `&[u8]`, and
+/// is sent as it is, so formats which are not text are not mangled.
 ///
 /// It returns the `UploadResult` if the I/O communication was successful, and
-/// an `UploadError` if an error occurs before the upload completes.
+/// an `UploadError` if an error occurs before the upload completes. Other
+/// output from the device while waiting, like logs from a running game, is
+/// skipped. Output read in the same chunk as the response, after it, is
+/// discarded.
+///
+/// Uploading does not allocate, so it can be used on constrained hosts and in
+/// loops uploading to many devices.
+///
+/// To upload without blocking, use `spade_serial::UploadMachine`.
+///
+/// ### Migrating from 0.1
+/// This function used to take the name and game as `&String`. Calls passing
+/// `&String` still compile, so existing code does not need to change, but
+/// `String`s built only to be passed here can be replaced with `&str`.
+#[cfg(feature = "std")]
 pub fn upload_game(
-    io: &mut (impl Write + Read),
-    name: &String,
-    game: &String,
+    io: &mut (impl SpadeTransport + ?Sized),
+    name: impl AsRef<str>,
+    game: impl AsRef<[u8]>,
 ) -> Result<UploadResult, UploadError> {
-    if name.len() > 100 {
-        Err(UploadError::InvalidName)
-    } else {
-        io.write_all("UPLOAD".as_bytes())?;
-        io.write_all(name.as_bytes())?;
-        io.write_all(vec![0; 100 - name.len()].as_slice())?;
-
-        let game_len = u32::try_from(game.len())?;
-        io.write_all(&game_len.to_le_bytes())?;
-
-        io.write_all(game.as_bytes())?;
-
-        // Look for ALL_GOOD, OO_FLASH, or OO_METADATA
-        let mut buf = [0; 11];
-        loop {
-            buf.rotate_left(1);
-            if io.read(&mut buf[10..])? > 0 {
-                let buf_str = std::str::from_utf8(&buf)?;
-
-                match buf_str {
-                    str if str.contains("ALL_GOOD") => break Ok(UploadResult::AllGood),
-                    str if str.contains("OO_FLASH") => break Ok(UploadResult::OutOfFlash),
-                    str if str.contains("OO_METADATA") => break Ok(UploadResult::OutOfMetadata),
-                    _ => continue,
-                }
-            } else {
+    upload_named(io, name.as_ref(), game.as_ref(), None)
+}
+
+/// Uploads a game to a device running Spade, stopping early if `token` is
+/// cancelled.
+///
+/// This behaves like `spade_serial::upload_game`, but checks `token` before
+/// each write and while waiting for the response. If it was cancelled, no
+/// more data is written, any output waiting to be read from the device is
+/// discarded, and `UploadError::Cancelled` is returned.
+///
+/// The device will have received an incomplete game if the upload is cancelled
+/// while the game is being written. Spade does not store incomplete games, but
+/// the device may need to be reset before it accepts another upload.
+#[cfg(feature = "std")]
+pub fn upload_game_cancellable(
+    io: &mut (impl SpadeTransport + ?Sized),
+    name: impl AsRef<str>,
+    game: impl AsRef<[u8]>,
+    token: &CancellationToken,
+) -> Result<UploadResult, UploadError> {
+    upload_named(io, name.as_ref(), game.as_ref(), Some(token))
+}
+
+/// Uploads a game, stopping early if there is a `token` and it is cancelled.
+///
+/// Uploads without a token do not allocate.
+#[cfg(feature = "std")]
+pub(crate) fn upload_named(
+    io: &mut (impl SpadeTransport + ?Sized),
+    name: &str,
+    game: &[u8],
+    token: Option<&CancellationToken>,
+) -> Result<UploadResult, UploadError> {
+    span!(info_span, "upload_game", name, game_bytes = game.len());
+
+    let mut sent = 0;
+    run_upload(
+        io,
+        UploadMachine::new(name, game)?,
+        token,
+        None,
+        None,
+        0,
+        &mut sent,
+    )
+}
+
+/// Writes the upload from `machine` and waits for the response, stopping
+/// early if there is a `token` and it is cancelled. If there is a
+/// `header_delay`, it is waited for after the header is flushed. If there is
+/// a `progress` callback, it is called before the first write, after each
+/// write, and when the response is received. Up to `max_timeouts` reads in a
+/// row may time out while waiting for the response. The number of bytes
+/// written is added to `sent`, even if the upload fails.
+#[cfg(feature = "std")]
+fn run_upload(
+    io: &mut (impl SpadeTransport + ?Sized),
+    mut machine: UploadMachine,
+    token: Option<&CancellationToken>,
+    mut header_delay: Option<std::time::Duration>,
+    progress: Option<&(dyn Fn(UploadProgress) + Send + Sync)>,
+    max_timeouts: u32,
+    sent: &mut usize,
+) -> Result<UploadResult, UploadError> {
+    let is_cancelled = || token.is_some_and(CancellationToken::is_cancelled);
+    let report = |machine: &UploadMachine| {
+        if let Some(progress) = progress {
+            progress(machine.progress());
+        }
+    };
+
+    report(&machine);
+
+    let mut writer = PacketWriter::new(&mut *io);
+    while let Some(bytes) = machine.next_write() {
+        if is_cancelled() {
+            event!(info, "upload cancelled while writing");
+            drain(writer.into_inner());
+            return Err(UploadError::Cancelled);
+        }
+
+        event!(trace, bytes = bytes.len(), "writing");
+        writer.write_all(bytes)?;
+        *sent += bytes.len();
+        if machine.needs_flush() {
+            writer.flush()?;
+
+            // The header is the first write to be flushed.
+            if let Some(delay) = header_delay.take() {
+                event!(debug, ?delay, "waiting after header");
+                std::thread::sleep(delay);
+            }
+        }
+        report(&machine);
+    }
+    report(&machine);
+
+    let mut buf = [0; 64];
+    let mut timeouts = 0;
+    loop {
+        if is_cancelled() {
+            event!(info, "upload cancelled while waiting for response");
+            drain(io);
+            break Err(UploadError::Cancelled);
+        }
+
+        let read = io.read(&mut buf);
+        let result = match read {
+            Ok(0) => machine.finish_read(),
+            Ok(bytes) => {
+                event!(trace, output = %String::from_utf8_lossy(&buf[..bytes]), "read");
+                machine.feed_read(&buf[..bytes])
+            }
+            // The device may have stopped printing after an unknown
+            // rejection.
+            Err(ref error) if error.kind() == ErrorKind::TimedOut => machine.finish_read(),
+            Err(_) => None,
+        };
+
+        match (result, read) {
+            (Some(result), _) => {
+                event!(debug, ?result, "received response");
+                report(&machine);
+                break Ok(result);
+            }
+            (None, Ok(0)) => {
+                event!(warn, "device output ended without a response");
                 break Err(UploadError::NoResponse);
             }
+            (None, Ok(_)) => timeouts = 0,
+            (None, Err(error)) if error.kind() == ErrorKind::TimedOut => {
+                timeouts += 1;
+                if timeouts > max_timeouts {
+                    event!(warn, timeouts, "timed out waiting for response");
+                    break Err(UploadError::Timeout);
+                }
+            }
+            (None, Err(error)) => break Err(error.into()),
         }
     }
 }
 
+/// Uploads a `Game` to a device running Spade.
+///
+/// This behaves like `spade_serial::upload_game`, but the name and source
+/// have already been checked when the `Game` was created.
+#[cfg(feature = "std")]
+pub fn upload(
+    io: &mut (impl SpadeTransport + ?Sized),
+    game: &Game,
+) -> Result<UploadResult, UploadError> {
+    upload_named(io, game.name().as_str(), game.source(), None)
+}
+
+/// Reads and discards output from the device until nothing is left to read or
+/// the read fails, such as when it times out.
+#[cfg(feature = "std")]
+fn drain(io: &mut (impl SpadeTransport + ?Sized)) {
+    let mut buf = [0; 64];
+    while let Ok(1..) = io.read(&mut buf) {}
+}
+
 /// Unit testing with a mock serial device. For testing actual devices, use
 /// the integration tests.
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
-    use std::collections::VecDeque;
+    use std::io::{Read, Write};
+    use std::time::Duration;
 
     use super::*;
+    use crate::mock::{Fault, Firmware, SerialMock};
+
+    #[test]
+    fn upload_result() {
+        let mut port = SerialMock::new().game_slots(1);
+        assert_eq!(
+            upload_game(&mut port, "all good test", "console.log('good')"),
+            Ok(UploadResult::AllGood)
+        );
+
+        let mut port = SerialMock::new().game_slots(1).flash_size(1);
+        assert_eq!(
+            upload_game(
+                &mut port,
+                "oo flash test",
+                "console.log('barely any space')"
+            ),
+            Ok(UploadResult::OutOfFlash)
+        );
 
-    struct Game {
-        name: String,
-        source: String,
-        source_size: usize,
+        let mut port = SerialMock::new().game_slots(0);
+        assert_eq!(
+            upload_game(&mut port, "oo meta test", "console.log('too many games')"),
+            Ok(UploadResult::OutOfMetadata)
+        );
     }
 
-    enum UploadProgress {
-        Header,
-        Body,
+    #[test]
+    fn binary_game() {
+        let game = [0x1f, 0x8b, 0xff, 0x00, b'\n', 0x80];
+        let mut port = SerialMock::new().game_slots(1);
+        assert_eq!(
+            upload_game(&mut port, "binary test", game),
+            Ok(UploadResult::AllGood)
+        );
+        assert_eq!(port.games()[0].source(), game);
     }
 
-    struct SerialMock {
-        games_left: i32,
-        slots_left: usize,
-        legacy: bool,
-        current_game: Option<Game>,
-        progress: UploadProgress,
-        read_buf: VecDeque<u8>,
+    #[test]
+    fn unknown_rejection() {
+        let rejected = Some(UploadResult::Rejected(Rejection::new("OO_NAME").unwrap()));
+
+        let mut machine = UploadMachine::new("rejected", "").unwrap();
+        assert_eq!(machine.feed_read(b"OO_OTHER\nFOO_BAR\nOO_NA"), None);
+        assert_eq!(machine.feed_read(b"ME\nlog"), None);
+        assert_eq!(machine.finish_read(), rejected);
+
+        // At the very end of the output.
+        let mut machine = UploadMachine::new("rejected", "").unwrap();
+        assert_eq!(machine.feed_read(b"OO_name OO_NAME"), None);
+        assert_eq!(machine.finish_read(), rejected);
+
+        // Known responses come first, and words which are not all uppercase
+        // or too long are not taken as one.
+        let mut machine = UploadMachine::new("rejected", "").unwrap();
+        let long = [&b"OO_"[..], &[b'A'; MAX_REJECTION_LEN]].concat();
+        assert_eq!(machine.feed_read(&long), None);
+        assert_eq!(machine.feed_read(b"\nOO_name\n"), None);
+        assert_eq!(machine.finish_read(), None);
+        assert_eq!(
+            machine.feed_read(b"OO_NAME\nOO_FLASH\n"),
+            Some(UploadResult::OutOfFlash)
+        );
+
+        let mut port = SerialMock::new().fault(Fault::Unresponsive);
+        port.push_output(b"OO_NAME");
+        assert_eq!(
+            upload_game(&mut port, "rejected", "console.log('new firmware')"),
+            Ok(UploadResult::Rejected(Rejection::new("OO_NAME").unwrap()))
+        );
     }
 
-    impl SerialMock {
-        fn new(games_left: i32, slots_left: usize, legacy: bool) -> Self {
+    #[test]
+    fn upload_machine() {
+        let mut port = SerialMock::new().game_slots(1);
+        let mut machine = UploadMachine::new("machine test", "console.log('sans-io')").unwrap();
+        while let Some(bytes) = machine.next_write() {
+            Write::write_all(&mut port, bytes).unwrap();
+        }
+        assert!(machine.is_waiting());
+
+        let mut response = Vec::new();
+        port.read_to_end(&mut response).unwrap();
+        assert_eq!(response, b"ALL_GOOD");
+
+        // Split across reads and surrounded by other output.
+        assert_eq!(machine.feed_read(b"log\nALL_"), None);
+        assert_eq!(
+            machine.feed_read(b"GOOD\nOO_FLASH"),
+            Some(UploadResult::AllGood)
+        );
+        assert_eq!(machine.result(), Some(&UploadResult::AllGood));
+        assert!(!machine.is_waiting());
+
+        let name = "a".repeat(100);
+        let mut machine = UploadMachine::new(&name, "").unwrap();
+        let writes: Vec<Vec<u8>> =
+            std::iter::from_fn(|| machine.next_write().map(Vec::from)).collect();
+        assert_eq!(writes.len(), 1);
+        assert_eq!(writes[0].len(), 110);
+        assert!(writes[0].starts_with(b"UPLOAD"));
+        assert_eq!(
+            &writes[0][protocol::NAME_OFFSET..protocol::LENGTH_OFFSET],
+            name.as_bytes()
+        );
+        assert!(writes[0].ends_with(&[0, 0, 0, 0]));
+
+        // Flushed after the header and after the last chunk of the game.
+        let game = "a".repeat(150);
+        let mut machine = UploadMachine::new("flush test", &game).unwrap();
+        let mut flushes = Vec::new();
+        while machine.next_write().is_some() {
+            flushes.push(machine.needs_flush());
+        }
+        assert_eq!(flushes, [true, false, false, true]);
+
+        // Flushed after every chunk smaller than the default.
+        let mut machine = UploadMachine::new("flush test", &game)
+            .unwrap()
+            .with_chunk_size(32);
+        let mut flushes = Vec::new();
+        while machine.next_write().is_some() {
+            flushes.push(machine.needs_flush());
+        }
+        assert_eq!(flushes, [true; 6]);
+    }
+
+    #[test]
+    fn upload_many() {
+        let games = [
+            ("first", "console.log(1)".to_owned()),
+            ("too big", "a".repeat(200)),
+            ("second", "console.log(2)".to_owned()),
+            ("no slots left", "console.log(3)".to_owned()),
+            ("skipped", "console.log(4)".to_owned()),
+        ]
+        .map(|(name, source)| Game::new(GameName::new(name).unwrap(), source).unwrap());
+
+        let mut port = SerialMock::new().game_slots(2).flash_size(150);
+        let results = super::upload_many(&mut port, &games, &BatchOptions::default());
+        let results: Vec<_> = results
+            .into_iter()
+            .map(|(name, result)| (name.to_string(), result))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                (String::from("first"), Ok(UploadResult::AllGood)),
+                (String::from("too big"), Ok(UploadResult::OutOfFlash)),
+                (String::from("second"), Ok(UploadResult::AllGood)),
+                (
+                    String::from("no slots left"),
+                    Ok(UploadResult::OutOfMetadata)
+                ),
+            ]
+        );
+
+        let mut port = SerialMock::new().game_slots(2).flash_size(150);
+        let options = BatchOptions {
+            stop_on_out_of_flash: true,
+            ..Default::default()
+        };
+        assert_eq!(super::upload_many(&mut port, &games, &options).len(), 2);
+
+        let mut port = FlakyMock::new(usize::MAX);
+        port.port = SerialMock::new().game_slots(4);
+        port.silent_reads = 1;
+        let options = BatchOptions {
+            retries: 1,
+            ..Default::default()
+        };
+        let results = super::upload_many(&mut port, &games[..1], &options);
+        assert_eq!(results[0].1, Ok(UploadResult::AllGood));
+    }
+
+    #[test]
+    fn response_scanner() {
+        let tokens: &[(&[u8], u8)] = &[(b"OO_METADATA", 1), (b"ALL_GOOD", 2)];
+
+        // Every way of splitting the token into two reads.
+        let output = b"log: OO_METADATA!";
+        for split in 0..output.len() {
+            let mut scanner = ResponseScanner::new(tokens);
+            let (first, second) = output.split_at(split);
+            let found = match scanner.feed(first) {
+                Some(found) => Some(found),
+                None => scanner
+                    .feed(second)
+                    .map(|(value, end)| (value, split + end)),
+            };
+            assert_eq!(found, Some((1, 16)));
+        }
+
+        // One byte at a time, with a token right after another.
+        let mut scanner = ResponseScanner::new(tokens);
+        let found: Vec<u8> = b"ALL_GOODOO_METADATA"
+            .chunks(1)
+            .filter_map(|byte| scanner.feed(byte).map(|(value, _)| value))
+            .collect();
+        assert_eq!(found, [2, 1]);
+
+        // Longer than the window, then a token.
+        let mut scanner = ResponseScanner::new(tokens);
+        assert_eq!(scanner.feed(&[b'x'; 100]), None);
+        assert_eq!(scanner.feed(b"ALL_GOOD"), Some((2, 8)));
+
+        let mut scanner = ResponseScanner::new(tokens);
+        assert_eq!(scanner.feed(b"ALL_"), None);
+        scanner.reset();
+        assert_eq!(scanner.feed(b"GOOD"), None);
+    }
+
+    #[test]
+    fn upload_with_logs() {
+        let mut port = SerialMock::new().game_slots(1);
+        port.push_output("still running\nOO_ALL_GOO\nlegacy\n".repeat(10).as_bytes());
+        assert_eq!(
+            upload_game(&mut port, "chatty test", "console.log('noisy')"),
+            Ok(UploadResult::AllGood)
+        );
+    }
+
+    #[test]
+    fn sync_device() {
+        let device = SyncSpadeDevice::new(SerialMock::new().game_slots(2));
+        let results = std::thread::scope(|scope| {
+            ["first", "second", "third"]
+                .map(|name| scope.spawn(|| device.upload_game(name, "console.log('sync')")))
+                .map(|upload| upload.join().unwrap())
+        });
+
+        let all_good = results
+            .iter()
+            .filter(|result| **result == Ok(UploadResult::AllGood))
+            .count();
+        assert_eq!(all_good, 2);
+        assert!(results.contains(&Ok(UploadResult::OutOfMetadata)));
+        assert_eq!(device.probe().unwrap(), ProbeResult::Modern);
+        assert_eq!(device.into_inner().game_slots_left(), 0);
+    }
+
+    /// A mock device which disconnects after a number of writes, and can stay
+    /// silent for a number of reads.
+    struct FlakyMock {
+        port: SerialMock,
+        writes_left: usize,
+        silent_reads: usize,
+        timed_out_reads: usize,
+    }
+
+    impl FlakyMock {
+        fn new(writes_left: usize) -> Self {
             Self {
-                games_left,
-                slots_left,
-                legacy,
-                current_game: None,
-                progress: UploadProgress::Header,
-                read_buf: VecDeque::new(),
+                port: SerialMock::new().game_slots(1),
+                writes_left,
+                silent_reads: 0,
+                timed_out_reads: 0,
             }
         }
     }
 
-    impl Read for SerialMock {
+    impl Read for FlakyMock {
         fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-            let mut bytes = 0;
-            for byte in buf.iter_mut() {
-                if let Some(new) = self.read_buf.pop_front() {
-                    *byte = new;
-                    bytes = bytes + 1;
-                }
+            if self.silent_reads > 0 {
+                self.silent_reads -= 1;
+                return Ok(0);
+            }
+            if self.timed_out_reads > 0 {
+                self.timed_out_reads -= 1;
+                return Err(std::io::ErrorKind::TimedOut.into());
             }
-            Ok(bytes)
+            Read::read(&mut self.port, buf)
         }
     }
 
-    impl Write for SerialMock {
+    impl Write for FlakyMock {
         fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-            let text = std::str::from_utf8(buf)
-                .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))
-                .unwrap();
-
-            if text == "UPLOAD" {
-                self.current_game = Some(Game {
-                    name: String::new(),
-                    source: String::new(),
-                    source_size: 0,
-                });
-                self.progress = UploadProgress::Header
-            } else if buf == [0, 1, 2, 3, 4] {
-                self.read_buf.extend(match self.legacy {
-                    true => "found startup seq!".as_bytes(),
-                    false => "legacy startup detected".as_bytes(),
-                });
-            } else if let Some(game) = &mut self.current_game {
-                match self.progress {
-                    UploadProgress::Header => {
-                        if game.name.len() < 100 {
-                            let bytes = text.len().min(100 - game.name.len());
-                            game.name.push_str(&text[..bytes])
-                        }
-
-                        if game.name.len() >= 100 {
-                            self.progress = UploadProgress::Body
-                        }
-                    }
-                    UploadProgress::Body => {
-                        if game.source_size == 0 {
-                            let (int_bytes, _) = buf.split_at(std::mem::size_of::<u32>());
-                            game.source_size =
-                                usize::try_from(u32::from_le_bytes(int_bytes.try_into().unwrap()))
-                                    .unwrap();
-                        } else {
-                            game.source.push_str(text);
-
-                            if game.source.len() >= game.source_size {
-                                // Upload finish
-                                self.read_buf.extend(
-                                    // I'm too lazy. I don't want to calculate slots.
-                                    if game.source_size > self.slots_left {
-                                        "OO_FLASH".as_bytes()
-                                    } else if self.games_left <= 0 {
-                                        "OO_METADATA".as_bytes()
-                                    } else {
-                                        self.games_left -= 1;
-                                        "ALL_GOOD".as_bytes()
-                                    },
-                                );
-                            }
-                        }
-                    }
-                }
+            if self.writes_left == 0 {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
             }
+            self.writes_left -= 1;
+            Write::write(&mut self.port, buf)
+        }
 
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reconnecting() {
+        use transport::{ReconnectPolicy, Reconnecting};
+
+        let upload = |port: &mut Reconnecting<FlakyMock>| {
+            upload_game_cancellable(
+                port,
+                "reconnect test",
+                "console.log('reset')",
+                &CancellationToken::new(),
+            )
+        };
+
+        let mut port = Reconnecting::new(FlakyMock::new(1), || Ok(FlakyMock::new(usize::MAX)));
+        assert_eq!(
+            port.run(ReconnectPolicy::Fail, upload),
+            Err(UploadError::IOError)
+        );
+
+        let mut port = Reconnecting::new(FlakyMock::new(1), || Ok(FlakyMock::new(usize::MAX)));
+        assert_eq!(
+            port.run(ReconnectPolicy::Restart { attempts: 1 }, upload),
+            Ok(UploadResult::AllGood)
+        );
+        assert_eq!(port.get_ref().port.game_slots_left(), 0);
+
+        let mut port = Reconnecting::new(FlakyMock::new(1), || Ok(FlakyMock::new(1)));
+        assert_eq!(
+            port.run(ReconnectPolicy::Restart { attempts: 3 }, upload),
+            Err(UploadError::IOError)
+        );
+    }
+
+    #[test]
+    fn upload_options() {
+        let game = Game::new(
+            GameName::new("options test").unwrap(),
+            "console.log('options')".repeat(10),
+        )
+        .unwrap();
+
+        // By default, the game is written in 64 byte packets: the 110 byte
+        // header, then 220 bytes of game.
+        let mut transport = CountingTransport {
+            port: SerialMock::new().game_slots(2),
+            writes: 0,
+        };
+        assert_eq!(
+            upload_game_with(&mut transport, &game, &UploadOptions::new()),
+            Ok(UploadResult::AllGood)
+        );
+        assert_eq!(transport.writes, 5);
+
+        // Smaller chunks are written on their own: 31 of 7 bytes, then 3.
+        transport.writes = 0;
+        let options = UploadOptions::new().chunk_size(7);
+        assert_eq!(
+            upload_game_with(&mut transport, &game, &options),
+            Ok(UploadResult::AllGood)
+        );
+        assert_eq!(transport.writes, 33);
+
+        let mut port = FlakyMock::new(usize::MAX);
+        port.port = SerialMock::new().game_slots(5);
+        port.silent_reads = 1;
+        assert_eq!(
+            upload_game_with(&mut port, &game, &UploadOptions::new()),
+            Err(UploadError::NoResponse)
+        );
+        port.silent_reads = 1;
+        assert_eq!(
+            upload_game_with(&mut port, &game, &UploadOptions::new().retries(1)),
+            Ok(UploadResult::AllGood)
+        );
+
+        // The mock does not support timeouts, so its own behaviour is kept.
+        let options = UploadOptions::new().timeout(Duration::from_secs(1));
+        assert_eq!(
+            upload_game_with(&mut port, &game, &options),
+            Ok(UploadResult::AllGood)
+        );
+
+        let options = UploadOptions::new().header_delay(Duration::from_millis(50));
+        let start = std::time::Instant::now();
+        assert_eq!(
+            upload_game_with(&mut port, &game, &options),
+            Ok(UploadResult::AllGood)
+        );
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let options = UploadOptions::new().retries(3).token(token);
+        assert_eq!(
+            upload_game_with(&mut port, &game, &options),
+            Err(UploadError::Cancelled)
+        );
+    }
+
+    #[test]
+    fn upload_timeouts() {
+        let game = Game::new(
+            GameName::new("timeout test").unwrap(),
+            String::from("console.log(1)"),
+        )
+        .unwrap();
+
+        let mut port = FlakyMock::new(usize::MAX);
+        port.timed_out_reads = 2;
+        let options = UploadOptions::new().max_timeouts(1);
+        assert_eq!(
+            upload_game_with(&mut port, &game, &options),
+            Err(UploadError::Timeout)
+        );
+
+        let mut port = FlakyMock::new(usize::MAX);
+        port.timed_out_reads = 2;
+        let options = UploadOptions::new().max_timeouts(2);
+        assert_eq!(
+            upload_game_with(&mut port, &game, &options),
+            Ok(UploadResult::AllGood)
+        );
+
+        // Reads returning nothing mean the output ended, not a timeout.
+        let mut port = FlakyMock::new(usize::MAX);
+        port.silent_reads = 1;
+        assert_eq!(
+            upload_game_with(&mut port, &game, &options),
+            Err(UploadError::NoResponse)
+        );
+    }
+
+    #[test]
+    fn upload_report() {
+        let game = Game::new(
+            GameName::new("report test").unwrap(),
+            "console.log('report')".repeat(10),
+        )
+        .unwrap();
+        let bytes = protocol::HEADER_LEN + game.source().len();
+
+        let mut port = FlakyMock::new(usize::MAX);
+        port.port = SerialMock::new().game_slots(4);
+        let report = upload_game_reported(&mut port, &game, &UploadOptions::new()).unwrap();
+        assert_eq!(report.result, UploadResult::AllGood);
+        assert_eq!(report.bytes_sent, bytes);
+        assert_eq!(report.retries, 0);
+
+        // Bytes written by failed tries are counted too.
+        port.silent_reads = 1;
+        let options = UploadOptions::new().retries(2);
+        let report = upload_game_reported(&mut port, &game, &options).unwrap();
+        assert_eq!(report.bytes_sent, bytes * 2);
+        assert_eq!(report.retries, 1);
+        assert!(report.effective_baud() > 0.0);
+    }
+
+    #[test]
+    fn upload_progress() {
+        let game = Game::new(
+            GameName::new("progress test").unwrap(),
+            "console.log('progress')".repeat(10),
+        )
+        .unwrap();
+        let total = game.source().len();
+
+        let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let options = UploadOptions::new().chunk_size(100).on_progress({
+            let reports = reports.clone();
+            move |progress| reports.lock().unwrap().push(progress)
+        });
+        let mut port = SerialMock::new();
+        assert_eq!(
+            upload_game_with(&mut port, &game, &options),
+            Ok(UploadResult::AllGood)
+        );
+        assert_eq!(
+            *reports.lock().unwrap(),
+            [
+                UploadProgress::Header,
+                UploadProgress::Game { written: 0, total },
+                UploadProgress::Game {
+                    written: 100,
+                    total
+                },
+                UploadProgress::Game {
+                    written: 200,
+                    total
+                },
+                UploadProgress::Game {
+                    written: total,
+                    total
+                },
+                UploadProgress::Waiting,
+                UploadProgress::Done,
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_command() {
+        let timeout = Duration::from_secs(1);
+        let mut port = SerialMock::new().firmware(Firmware::Legacy).game_slots(1);
+        port.push_output(b"log\n");
+        assert_eq!(
+            send_raw_command(
+                &mut port,
+                &[0, 1, 2, 3, 4],
+                ResponseMatcher::Tokens(&[b"seq!"]),
+                timeout
+            )
+            .unwrap(),
+            b"log\nfound startup seq!"
+        );
+
+        let mut port = SerialMock::new().game_slots(1);
+        let command = [0, 1, 2, 3, 4];
+        let response = send_raw_command(&mut port, &command, ResponseMatcher::Length(6), timeout);
+        assert_eq!(response.unwrap(), b"legacy");
+        let response = send_raw_command(&mut port, &[], ResponseMatcher::UntilTimeout, timeout);
+        assert_eq!(response.unwrap(), b" startup detected");
+        let response = send_raw_command(&mut port, &[], ResponseMatcher::Length(1), timeout);
+        assert_eq!(
+            response.unwrap_err().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "tokens must not be empty")]
+    fn raw_command_without_tokens() {
+        let mut port = SerialMock::new();
+        let _ = send_raw_command(
+            &mut port,
+            &[0, 1, 2, 3, 4],
+            ResponseMatcher::Tokens(&[]),
+            Duration::from_secs(1),
+        );
+    }
+
+    #[test]
+    fn messages() {
+        assert_eq!(UploadError::Timeout.to_string(), "device timed out");
+        assert_eq!(
+            UploadError::IOError.to_string(),
+            "reading from or writing to the device failed"
+        );
+        assert_eq!(UploadResult::AllGood.response(), "ALL_GOOD");
+        assert_eq!(UploadResult::Unconfirmed.response(), "UNCONFIRMED");
+    }
+
+    /// Counts the allocations made by each thread.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// A device which accepts any upload of `expected` bytes without
+    /// allocating.
+    struct Loopback {
+        expected: usize,
+        written: usize,
+    }
+
+    impl Read for Loopback {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.written < self.expected {
+                return Ok(0);
+            }
+            buf[..8].copy_from_slice(b"ALL_GOOD");
+            Ok(8)
+        }
+    }
+
+    impl Write for Loopback {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written += buf.len();
             Ok(buf.len())
         }
 
         fn flush(&mut self) -> std::io::Result<()> {
-            todo!()
+            Ok(())
         }
     }
 
     #[test]
-    fn upload_result() {
-        let mut port = SerialMock::new(1, 150, false);
+    fn no_allocations() {
+        let name = String::from("allocation test");
+        let game = "console.log('no allocations')".repeat(100);
+        let mut port = Loopback {
+            expected: 110 + game.len(),
+            written: 0,
+        };
+
+        let allocations = ALLOCATIONS.with(|count| count.get());
+        let result = upload_game(&mut port, &name, &game);
+        assert_eq!(result, Ok(UploadResult::AllGood));
+        port.written = 0;
+        let result = upload_game(&mut port, "allocation test", game.as_str());
+        assert_eq!(ALLOCATIONS.with(|count| count.get()), allocations);
+        assert_eq!(result, Ok(UploadResult::AllGood));
+    }
+
+    #[test]
+    fn cancelled() {
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let mut port = SerialMock::new().game_slots(1);
+        port.push_output("leftover output".as_bytes());
         assert_eq!(
-            upload_game(
-                &mut port,
-                &String::from("all good test"),
-                &String::from("console.log('good')")
-            ),
+            upload_game_cancellable(&mut port, "cancel test", "console.log('cancelled')", &token),
+            Err(UploadError::Cancelled)
+        );
+        assert!(port.received().is_empty());
+        assert!(!port.has_output());
+    }
+
+    #[test]
+    fn game() {
+        let name = GameName::new("game test").unwrap();
+        assert_eq!(
+            Game::new(name.clone(), String::new()),
+            Err(GameError::EmptySource)
+        );
+
+        let source = String::from("/*\n@title: \n*/\nconsole.log('untitled')");
+        assert!(Game::new(name.clone(), source.clone()).is_ok());
+        assert_eq!(
+            Game::new_with_title(name.clone(), source),
+            Err(GameError::MissingTitle)
+        );
+
+        let source = String::from("/*\n@title: Game Test\n*/\nconsole.log('titled')");
+        let game = Game::new_with_title(name, source).unwrap();
+        let mut port = SerialMock::new().game_slots(1);
+        assert_eq!(upload(&mut port, &game), Ok(UploadResult::AllGood));
+
+        let source = "/*\n@title: Inferred\n@author: someone\n@tags: ['puzzle', \"maze\"]\n*/";
+        let inferred = Game::from_source_with_inferred_name(source.to_owned()).unwrap();
+        assert_eq!(inferred.name().as_str(), "Inferred");
+        assert_eq!(
+            inferred.metadata(),
+            GameMetadata {
+                title: Some(String::from("Inferred")),
+                author: Some(String::from("someone")),
+                tags: vec![String::from("puzzle"), String::from("maze")],
+            }
+        );
+        assert_eq!(
+            Game::from_source_with_inferred_name(String::from("// @title: Not a header")),
+            Err(GameError::MissingTitle)
+        );
+
+        let path = std::env::temp_dir().join("spade-serial game test.js");
+        std::fs::write(&path, game.source()).unwrap();
+        let from_file = Game::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(from_file.unwrap().name().as_str(), "spade-serial game test");
+        assert_eq!(
+            Game::from_file(std::env::temp_dir().join("spade-serial missing.js")),
+            Err(GameError::IOError)
+        );
+    }
+
+    #[test]
+    fn binary_game_auto() {
+        // Not valid UTF-8.
+        let source: &[u8] = &[0xff, 0xfe, 0, 0x80, b'x', 0xc3];
+
+        let game = Game::new(GameName::new("binary").unwrap(), source).unwrap();
+        assert_eq!(game.source(), source);
+        assert_eq!(game.metadata(), GameMetadata::default());
+        let mut port = SerialMock::new().game_slots(1);
+        assert_eq!(upload(&mut port, &game), Ok(UploadResult::AllGood));
+        assert_eq!(port.games()[0].source(), source);
+
+        let mut port = SerialMock::new().game_slots(1);
+        assert_eq!(
+            upload_game_auto(&mut port, "binary", source),
             Ok(UploadResult::AllGood)
         );
+        assert_eq!(port.games()[0].source(), source);
 
-        let mut port = SerialMock::new(1, 1, false);
+        let mut port = SerialMock::new().firmware(Firmware::Legacy);
         assert_eq!(
-            upload_game(
-                &mut port,
-                &String::from("oo flash test"),
-                &String::from("console.log('barely any space')")
-            ),
-            Ok(UploadResult::OutOfFlash)
+            upload_game_auto(&mut port, "binary", game.source()),
+            Ok(UploadResult::Unconfirmed)
         );
+        assert_eq!(port.games()[0].source(), source);
+
+        let path = std::env::temp_dir().join("spade-serial binary test.bin");
+        std::fs::write(&path, source).unwrap();
+        let from_file = Game::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(from_file.unwrap().source(), source);
+    }
+
+    #[cfg(feature = "serialport")]
+    #[test]
+    fn firmware() {
+        use firmware::{copy_uf2, validate_uf2, FirmwareError};
+
+        let mut uf2 = vec![0; 1024];
+        for block in uf2.chunks_mut(512) {
+            block[..4].copy_from_slice(&0x0A324655u32.to_le_bytes());
+            block[4..8].copy_from_slice(&0x9E5D5157u32.to_le_bytes());
+        }
 
-        let mut port = SerialMock::new(0, 150, false);
+        assert_eq!(validate_uf2(&uf2), Ok(()));
+        assert_eq!(validate_uf2(&[]), Err(FirmwareError::InvalidImage));
+        assert_eq!(validate_uf2(&[0; 512]), Err(FirmwareError::InvalidImage));
+
+        let mount = std::env::temp_dir().join("spade-serial firmware test");
+        std::fs::create_dir_all(&mount).unwrap();
         assert_eq!(
-            upload_game(
-                &mut port,
-                &String::from("oo meta test"),
-                &String::from("console.log('too many games')")
-            ),
+            copy_uf2(&mount, &uf2, Duration::ZERO),
+            Err(FirmwareError::DriveNotFound)
+        );
+        assert_eq!(
+            copy_uf2(&mount, &uf2[..600], Duration::ZERO),
+            Err(FirmwareError::InvalidImage)
+        );
+
+        std::fs::write(mount.join("INFO_UF2.TXT"), "UF2 Bootloader").unwrap();
+        let copied = copy_uf2(&mount, &uf2, Duration::ZERO);
+        let written = std::fs::read(mount.join("SPADE.UF2"));
+        std::fs::remove_dir_all(&mount).unwrap();
+        assert_eq!(copied, Ok(()));
+        assert_eq!(written.unwrap(), uf2);
+    }
+
+    #[cfg(feature = "serialport")]
+    #[test]
+    fn fleet() {
+        let ports = ["full", "missing", "empty"].map(String::from);
+        let game = Game::new(GameName::new("fleet test").unwrap(), "console.log(1)").unwrap();
+        let results = fleet::upload_each(
+            &ports,
+            |path| match path {
+                "full" => Ok(SerialMock::new().game_slots(0)),
+                "empty" => Ok(SerialMock::new().game_slots(1)),
+                _ => Err(std::io::ErrorKind::NotFound.into()),
+            },
+            &game,
+        );
+        assert_eq!(
+            results,
+            [
+                (String::from("full"), Ok(UploadResult::OutOfMetadata)),
+                (String::from("missing"), Err(UploadError::IOError)),
+                (String::from("empty"), Ok(UploadResult::AllGood)),
+            ]
+        );
+    }
+
+    #[test]
+    fn game_name() {
+        assert!(GameName::new(&"a".repeat(100)).is_ok());
+        assert_eq!(GameName::new(&"a".repeat(101)), Err(GameNameError::TooLong));
+        assert_eq!(GameName::new("a\0b"), Err(GameNameError::ContainsNul));
+
+        // 'é' is two bytes, so the 100th byte falls in the middle of one.
+        let name = GameName::truncate_lossy(&"é".repeat(60));
+        assert_eq!(name.as_str(), "é".repeat(50));
+        assert_eq!(GameName::truncate_lossy("a\0b").as_str(), "ab");
+
+        let mut port = SerialMock::new().game_slots(1);
+        assert_eq!(
+            upload_game(&mut port, "nul\0name", "console.log('bad name')"),
+            Err(UploadError::InvalidName)
+        );
+    }
+
+    #[test]
+    fn console() {
+        let mut port = SerialMock::new().game_slots(1);
+        port.push_output("hello\r\nworld\n\npartial".as_bytes());
+
+        let mut lines = Vec::new();
+        attach_console(&mut port, &CancellationToken::new(), |line| {
+            lines.push(line.text().to_owned())
+        })
+        .unwrap();
+        assert_eq!(lines, ["hello", "world", "", "partial"]);
+    }
+
+    #[test]
+    fn runtime_status() {
+        let mut port = SerialMock::new().game_slots(1);
+        port.push_output("game started\n".as_bytes());
+        assert_eq!(
+            wait_for_runtime_status(&mut port, Duration::from_secs(1)).unwrap(),
+            RuntimeStatus::Started
+        );
+
+        let mut port = SerialMock::new().game_slots(1);
+        port.push_output("log\nUncaught TypeError: x is not a function\n".as_bytes());
+        port.push_output("    at update (game.js:12:5)\n".as_bytes());
+        assert_eq!(
+            wait_for_runtime_status(&mut port, Duration::from_secs(1)).unwrap(),
+            RuntimeStatus::Error(RuntimeError {
+                message: String::from("TypeError: x is not a function"),
+                line: Some(12),
+                column: Some(5),
+            })
+        );
+
+        let error = ConsoleLine::from_bytes(b"SyntaxError: unexpected token (line 3, column 7)")
+            .runtime_error()
+            .unwrap();
+        assert_eq!((error.line, error.column), (Some(3), Some(7)));
+        assert!(ConsoleLine::from_bytes(b"no errors: here")
+            .runtime_error()
+            .is_none());
+    }
+
+    /// A transport which is not `Read + Write`, counting the writes made.
+    struct CountingTransport {
+        port: SerialMock,
+        writes: usize,
+    }
+
+    impl SpadeTransport for CountingTransport {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            Read::read(&mut self.port, buf)
+        }
+
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.writes += 1;
+            Write::write(&mut self.port, buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A transport which records the timeouts it is set to, and fails to set
+    /// any once `accepted` were.
+    struct TimeoutTransport {
+        port: SerialMock,
+        timeout: Duration,
+        set: Vec<Duration>,
+        accepted: usize,
+    }
+
+    impl TimeoutTransport {
+        fn new(accepted: usize) -> Self {
+            Self {
+                port: SerialMock::new().game_slots(4),
+                timeout: Duration::from_secs(1),
+                set: Vec::new(),
+                accepted,
+            }
+        }
+    }
+
+    impl SpadeTransport for TimeoutTransport {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            Read::read(&mut self.port, buf)
+        }
+
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Write::write(&mut self.port, buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        fn set_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+            if self.set.len() == self.accepted {
+                return Err(std::io::Error::other("port closed"));
+            }
+            self.set.push(timeout);
+            self.timeout = timeout;
+            Ok(())
+        }
+
+        fn timeout(&self) -> Option<Duration> {
+            Some(self.timeout)
+        }
+    }
+
+    #[test]
+    fn upload_timeout() {
+        let game = Game::new(GameName::new("timeout test").unwrap(), "console.log(1)").unwrap();
+        let options = UploadOptions::new().timeout(Duration::from_secs(5));
+
+        let mut transport = TimeoutTransport::new(usize::MAX);
+        assert_eq!(
+            upload_game_with(&mut transport, &game, &options),
+            Ok(UploadResult::AllGood)
+        );
+        assert_eq!(
+            transport.set,
+            [Duration::from_secs(5), Duration::from_secs(1)]
+        );
+
+        // The upload succeeded even though the timeout was not restored.
+        let mut transport = TimeoutTransport::new(1);
+        assert_eq!(
+            upload_game_with(&mut transport, &game, &options),
+            Ok(UploadResult::AllGood)
+        );
+        assert_eq!(transport.timeout, Duration::from_secs(5));
+
+        let mut transport = TimeoutTransport::new(0);
+        assert_eq!(
+            upload_game_with(&mut transport, &game, &options),
+            Err(UploadError::IOError)
+        );
+        assert!(transport.port.games().is_empty());
+    }
+
+    #[test]
+    fn partial_writes() {
+        let mut port = SerialMock::new().fault(Fault::ShortWrites);
+        assert_eq!(
+            upload_game(&mut port, "trickle test", "console.log('slow')"),
+            Ok(UploadResult::AllGood)
+        );
+        assert_eq!(port.games()[0].source(), b"console.log('slow')");
+
+        let mut port = SerialMock::new()
+            .fault(Fault::ShortWrites)
+            .fault(Fault::StallAfter(120));
+        assert_eq!(
+            upload_game(&mut port, "trickle test", "console.log('stalled')"),
+            Err(UploadError::WriteStalled)
+        );
+    }
+
+    #[test]
+    fn mock_faults() {
+        let mut port = SerialMock::new().fault(Fault::Unresponsive);
+        assert_eq!(
+            upload_game(&mut port, "fault test", "console.log(1)"),
+            Err(UploadError::NoResponse)
+        );
+        assert_eq!(port.games().len(), 1);
+
+        let mut port = SerialMock::new().fault(Fault::DisconnectAfter(10));
+        assert_eq!(
+            upload_game(&mut port, "fault test", "console.log(1)"),
+            Err(UploadError::Disconnected)
+        );
+        assert_eq!(port.received().len(), 10);
+        assert!(is_running_legacy(&mut port).is_err());
+
+        let mut port = SerialMock::new().latency(Duration::from_millis(20));
+        let start = std::time::Instant::now();
+        assert!(!is_running_legacy(&mut port).unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn chaos() {
+        use mock::ChaosTransport;
+
+        let game = Game::new(
+            GameName::new("chaos test").unwrap(),
+            "console.log('chaos')".repeat(20),
+        )
+        .unwrap();
+        let options = UploadOptions::new().retries(10);
+
+        // Faults which only affect timing are survived with retries.
+        for seed in 0..20 {
+            let mut port = ChaosTransport::new(SerialMock::new(), seed)
+                .split_writes(0.5)
+                .delays(0.05, Duration::from_millis(1))
+                .timeouts(0.2);
+            assert_eq!(
+                upload_game_with(&mut port, &game, &options),
+                Ok(UploadResult::AllGood),
+                "seed {seed}"
+            );
+            let device = port.into_inner();
+            assert!(device
+                .games()
+                .iter()
+                .all(|stored| stored.source() == game.source()));
+        }
+
+        // Faults which corrupt the upload end in a result, rather than a hang
+        // or a panic.
+        for seed in 0..20 {
+            let mut port = ChaosTransport::new(SerialMock::new(), seed)
+                .drop_bytes(0.01)
+                .duplicate_bytes(0.01);
+            let result = upload_game_with(&mut port, &game, &options);
+            assert!(
+                matches!(result, Ok(_) | Err(UploadError::NoResponse)),
+                "seed {seed}: {result:?}"
+            );
+        }
+
+        // The same seed injects the same faults.
+        let received = |seed| {
+            let mut port = ChaosTransport::new(SerialMock::new(), seed)
+                .drop_bytes(0.1)
+                .split_writes(0.5);
+            let _ = upload(&mut port, &game);
+            port.into_inner().received().to_vec()
+        };
+        assert_eq!(received(7), received(7));
+        assert_ne!(received(7), received(8));
+    }
+
+    #[test]
+    fn demux() {
+        use transport::{Demux, Replay};
+
+        let mut port = SerialMock::new().firmware(Firmware::Legacy).game_slots(1);
+        port.push_output(b"score: 10\n");
+        let mut log = Vec::new();
+        let mut demux = Demux::new(port, |bytes: &[u8]| log.extend_from_slice(bytes));
+        assert!(is_running_legacy(&mut demux).unwrap());
+        drop(demux);
+        assert_eq!(log, b"score: 10\n");
+
+        let mut port = SerialMock::new().game_slots(1);
+        port.push_output(b"OO_FLAT\nALL");
+        let mut log = Vec::new();
+        let mut demux = Demux::new(port, |bytes: &[u8]| log.extend_from_slice(bytes));
+        assert_eq!(
+            upload_game(&mut demux, "demux test", "console.log('chatty')"),
+            Ok(UploadResult::AllGood)
+        );
+        drop(demux);
+        assert_eq!(log, b"OO_FLAT\nALL");
+
+        // Split across reads, with a partial response at the end.
+        let events = [&b"hi OO_"[..], b"FLASH bye OO", b"_"]
+            .iter()
+            .map(|bytes| transport::RecordedEvent {
+                elapsed: Duration::ZERO,
+                direction: transport::Direction::Read,
+                bytes: bytes.to_vec(),
+            })
+            .collect();
+        let mut log = Vec::new();
+        let mut demux = Demux::new(Replay::new(events), |bytes: &[u8]| {
+            log.extend_from_slice(bytes)
+        });
+        let mut output = [0; 16];
+        assert_eq!(SpadeTransport::read(&mut demux, &mut output).unwrap(), 8);
+        assert_eq!(
+            SpadeTransport::read(&mut demux, &mut output[8..]).unwrap(),
+            0
+        );
+        drop(demux);
+        assert_eq!(&output[..8], b"OO_FLASH");
+        assert_eq!(log, b"hi  bye OO_");
+    }
+
+    #[test]
+    fn response_tables() {
+        use transport::{Demux, Direction, RecordedEvent, Replay};
+
+        for (token, result) in &protocol::UPLOAD_RESPONSES {
+            let mut machine = UploadMachine::new("tables", "").unwrap();
+            assert_eq!(machine.feed_read(token), Some(result.clone()));
+        }
+
+        let responses = protocol::UPLOAD_RESPONSES.iter().map(|(token, _)| *token);
+        let startup = protocol::STARTUP_RESPONSES.iter().map(|(token, _)| *token);
+        for token in responses.chain(startup) {
+            let events = vec![RecordedEvent {
+                elapsed: Duration::ZERO,
+                direction: Direction::Read,
+                bytes: [b"log ", token].concat(),
+            }];
+            let mut log = Vec::new();
+            let mut demux = Demux::new(Replay::new(events), |bytes: &[u8]| {
+                log.extend_from_slice(bytes)
+            });
+            let mut output = [0; MAX_TOKEN_LEN];
+            let read = SpadeTransport::read(&mut demux, &mut output).unwrap();
+            drop(demux);
+            assert_eq!(&output[..read], token);
+            assert_eq!(log, b"log ");
+        }
+    }
+
+    #[test]
+    fn custom_transport() {
+        let mut transport = CountingTransport {
+            port: SerialMock::new().game_slots(1),
+            writes: 0,
+        };
+        assert_eq!(
+            upload_game(&mut transport, "transport test", "console.log('custom')"),
+            Ok(UploadResult::AllGood)
+        );
+        assert!(transport.writes > 0);
+        assert!(!is_running_legacy(&mut transport).unwrap());
+    }
+
+    #[test]
+    fn rfc2217() {
+        use std::net::TcpListener;
+        use std::thread;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // IAC DO COM-PORT-OPTION, data with an escaped 255, a modem state
+            // notification, and more data.
+            Write::write_all(&mut stream, &[255, 253, 44, b'A', 255, 255, b'B']).unwrap();
+            Write::write_all(&mut stream, &[255, 250, 44, 107, 0x30, 255, 240, b'C']).unwrap();
+
+            let mut received = vec![0; 256];
+            let mut len = 0;
+            while !received[..len].ends_with(&[b'x', 255, 255]) {
+                len += Read::read(&mut stream, &mut received[len..]).unwrap();
+            }
+            received.truncate(len);
+            received
+        });
+
+        let mut port = transport::Rfc2217::connect(addr).unwrap();
+        port.set_timeout(Duration::from_secs(5)).unwrap();
+        let mut data = Vec::new();
+        while data.len() < 4 {
+            let mut buf = [0; 16];
+            let bytes = SpadeTransport::read(&mut port, &mut buf).unwrap();
+            data.extend_from_slice(&buf[..bytes]);
+        }
+        assert_eq!(data, [b'A', 255, b'B', b'C']);
+
+        SpadeTransport::write_all(&mut port, &[b'x', 255]).unwrap();
+        let received = server.join().unwrap();
+        // IAC SB COM-PORT-OPTION SET-BAUDRATE 115200 IAC SE
+        let set_baud = [255, 250, 44, 1, 0, 1, 0xC2, 0, 255, 240];
+        assert!(received.windows(set_baud.len()).any(|w| w == set_baud));
+    }
+
+    /// Adapts the mock to `embedded-io`, which is not implemented for
+    /// `Read + Write`rs.
+    #[cfg(feature = "embedded-io")]
+    struct EmbeddedMock(SerialMock);
+
+    #[cfg(feature = "embedded-io")]
+    impl embedded_io::ErrorType for EmbeddedMock {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    #[cfg(feature = "embedded-io")]
+    impl embedded_io::Read for EmbeddedMock {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            Read::read(&mut self.0, buf).map_err(|_| embedded_io::ErrorKind::Other)
+        }
+    }
+
+    #[cfg(feature = "embedded-io")]
+    impl embedded_io::Write for EmbeddedMock {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            Write::write(&mut self.0, buf).map_err(|_| embedded_io::ErrorKind::Other)
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "embedded-io")]
+    #[test]
+    fn embedded() {
+        let mut port = EmbeddedMock(SerialMock::new().game_slots(1));
+        assert_eq!(
+            embedded::upload_game(&mut port, "embedded test", "console.log('no_std')"),
+            Ok(UploadResult::AllGood)
+        );
+
+        let mut port = EmbeddedMock(SerialMock::new().firmware(Firmware::Legacy).game_slots(0));
+        assert!(embedded::is_running_legacy(&mut port).unwrap());
+    }
+
+    #[test]
+    fn record_and_replay() {
+        use transport::{read_trace, write_transcript, ProtocolRecorder, Replay};
+
+        let mut recorder = ProtocolRecorder::new(SerialMock::new().game_slots(0));
+        assert_eq!(
+            upload_game(&mut recorder, "record test", "console.log('recorded')"),
+            Ok(UploadResult::OutOfMetadata)
+        );
+        assert!(recorder.events()[0].bytes.starts_with(b"UPLOAD"));
+
+        let mut trace = Vec::new();
+        recorder.write_trace(&mut trace).unwrap();
+        let events = read_trace(&trace[..]).unwrap();
+        assert_eq!(events.len(), recorder.events().len());
+        for (read, recorded) in events.iter().zip(recorder.events()) {
+            assert_eq!(read.direction, recorded.direction);
+            assert_eq!(read.bytes, recorded.bytes);
+            assert_eq!(read.elapsed.as_micros(), recorded.elapsed.as_micros());
+        }
+
+        let mut replay = Replay::new(events);
+        assert_eq!(
+            upload_game(&mut replay, "record test", "console.log('recorded')"),
             Ok(UploadResult::OutOfMetadata)
         );
+
+        assert!(read_trace(&b"12 X 00"[..]).is_err());
+        assert!(read_trace(&b"12 R 0"[..]).is_err());
+
+        let mut transcript = Vec::new();
+        write_transcript(&recorder.events()[..1], &mut transcript).unwrap();
+        let transcript = String::from_utf8(transcript).unwrap();
+        let first = transcript.lines().next().unwrap();
+        assert!(first.contains(" W 55 50 4c 4f 41 44 "));
+        assert!(first.ends_with("UPLOADrecord tes"));
+        assert_eq!(
+            transcript.lines().count(),
+            recorder.events()[0].bytes.len().div_ceil(16)
+        );
+
+        let recorded = recorder.events().len();
+        assert_eq!(recorder.take_events().len(), recorded);
+        assert!(recorder.events().is_empty());
     }
 
     #[test]
     fn legacy() {
-        let mut port = SerialMock::new(1, 150, false);
+        let mut port = SerialMock::new().game_slots(1);
         assert!(!is_running_legacy(&mut port).unwrap());
 
-        let mut port = SerialMock::new(1, 150, true);
+        let mut port = SerialMock::new().firmware(Firmware::Legacy).game_slots(1);
         assert!(is_running_legacy(&mut port).unwrap());
+
+        let mut port = SerialMock::new().game_slots(1);
+        assert_eq!(probe(&mut port).unwrap(), ProbeResult::Modern);
+        let mut port = SerialMock::new().firmware(Firmware::Legacy).game_slots(1);
+        assert_eq!(probe(&mut port).unwrap(), ProbeResult::Legacy);
+        let mut port = std::io::Cursor::new(Vec::new());
+        assert_eq!(probe(&mut port).unwrap(), ProbeResult::NotSpade);
+        assert!(ProbeResult::NotSpade.protocol().is_none());
+        let mut port = std::io::Cursor::new(Vec::new());
+        port.get_mut().resize(1024, b'x');
+        assert_eq!(probe(&mut port).unwrap(), ProbeResult::NotSpade);
+
+        let mut port = SerialMock::new().firmware(Firmware::Legacy);
+        assert_eq!(
+            upload_game_legacy(&mut port, "console.log('legacy')"),
+            Ok(UploadResult::Unconfirmed)
+        );
+        assert_eq!(port.games()[0].source(), b"console.log('legacy')");
+
+        let mut port = SerialMock::new().firmware(Firmware::Legacy);
+        assert_eq!(
+            upload_game_auto(
+                &mut port as &mut dyn SpadeTransport,
+                "auto test",
+                "console.log('auto')"
+            ),
+            Ok(UploadResult::Unconfirmed)
+        );
+        assert_eq!(port.games()[0].source(), b"console.log('auto')");
+
+        // A legacy device probed once, then uploaded to twice.
+        let mut port = SerialMock::new().firmware(Firmware::Legacy);
+        let mut protocol = probe(&mut port).unwrap().protocol().unwrap();
+        assert_eq!(protocol.name(), "legacy");
+        for game in ["console.log(1)", "console.log(2)"] {
+            assert_eq!(
+                protocol.upload(&mut port, "ignored", game.as_bytes()),
+                Ok(UploadResult::Unconfirmed)
+            );
+            assert_eq!(port.games().len(), 1);
+            assert_eq!(port.games()[0].source(), game.as_bytes());
+        }
+
+        let mut port = SerialMock::new().game_slots(1);
+        assert_eq!(
+            upload_game_auto(&mut port, "auto test", "console.log('auto')"),
+            Ok(UploadResult::AllGood)
+        );
+        assert_eq!(port.games()[0].name(), "auto test");
+
+        // Output of the running game, which is not UTF-8 and longer than the
+        // response, does not hide it.
+        let output = [&[0xff, 0xfe][..], &b"game output\n".repeat(4)].concat();
+        let mut port = SerialMock::new().firmware(Firmware::Legacy);
+        port.push_output(&output);
+        assert_eq!(
+            upload_game_auto(&mut port, String::from("auto test"), "console.log('auto')"),
+            Ok(UploadResult::Unconfirmed)
+        );
+        assert_eq!(port.games()[0].source(), b"console.log('auto')");
+
+        let mut port = SerialMock::new().game_slots(1);
+        port.push_output(&output);
+        assert_eq!(
+            upload_game_auto(&mut port, "auto test", "console.log('auto')"),
+            Ok(UploadResult::AllGood)
+        );
+
+        let mut port = SerialMock::new().fault(Fault::Unresponsive);
+        assert_eq!(
+            upload_game_auto(&mut port, "auto test", "console.log('auto')"),
+            Err(UploadError::NoResponse)
+        );
     }
 }
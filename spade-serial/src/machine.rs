@@ -0,0 +1,245 @@
+use core::convert::TryFrom;
+
+use crate::name;
+use crate::protocol::{HEADER_LEN, LENGTH_OFFSET, NAME_OFFSET, UPLOAD_COMMAND, UPLOAD_RESPONSES};
+use crate::rejection::RejectionScanner;
+#[cfg(feature = "tracing")]
+use crate::trace::event;
+use crate::{ResponseScanner, UploadError, UploadResult};
+
+/// The number of bytes of the game returned by each call to
+/// `UploadMachine::next_write`, unless changed with
+/// `UploadMachine::with_chunk_size`.
+pub(crate) const DEFAULT_CHUNK_SIZE: usize = 64;
+
+/// The part of the upload which `UploadMachine::next_write` returns next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    Header,
+    Body(usize),
+    Response,
+}
+
+/// How far an upload has got, from `UploadMachine::progress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UploadProgress {
+    /// The header has not been written yet.
+    Header,
+    /// The header has been written, and `written` of the `total` bytes of the
+    /// game have been written after it.
+    Game {
+        /// The bytes of the game written so far.
+        written: usize,
+        /// The size of the game in bytes.
+        total: usize,
+    },
+    /// Everything has been written, and the device has not responded yet.
+    Waiting,
+    /// The device responded.
+    Done,
+}
+
+/// The upload protocol, without any I/O.
+///
+/// This is what `spade_serial::upload_game` uses to upload a game. It can be
+/// used directly where blocking on a serial port is not possible, like in a
+/// GUI event loop: write the bytes from each call to `next_write` to the
+/// device until it returns `None`, then pass everything read from the device
+/// to `feed_read` until it returns a result.
+///
+/// The command, name and length of the game are returned as one write, as
+/// many small writes can stall for seconds on some USB serial drivers. The
+/// device should be flushed whenever `needs_flush` returns `true`.
+/// ```
+/// # use spade_serial::{UploadMachine, UploadResult};
+/// let mut machine = UploadMachine::new("game", "console.log('hi')")?;
+/// while let Some(bytes) = machine.next_write() {
+///     // Write `bytes` to the device.
+///     if machine.needs_flush() {
+///         // Flush the device.
+///     }
+/// }
+/// // Pass output from the device to `feed_read`.
+/// assert_eq!(machine.feed_read(b"ALL_GOOD"), Some(UploadResult::AllGood));
+/// # Ok::<(), spade_serial::UploadError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct UploadMachine<'a> {
+    header: [u8; HEADER_LEN],
+    game: &'a [u8],
+    chunk_size: usize,
+    step: Step,
+    scanner: ResponseScanner<'static, UploadResult>,
+    rejections: RejectionScanner,
+    result: Option<UploadResult>,
+}
+
+impl<'a> UploadMachine<'a> {
+    /// Prepares to upload `game` under `name`. The game can be a string or
+    /// bytes, like `&[u8]`, and is written as it is.
+    ///
+    /// ### Errors
+    /// Returns `UploadError::InvalidName` if `name` is over 100 bytes long or
+    /// contains a NUL character, or `UploadError::FailedConversion` if `game` is too large
+    /// for its length to fit in a `u32`.
+    pub fn new(name: &'a str, game: &'a (impl AsRef<[u8]> + ?Sized)) -> Result<Self, UploadError> {
+        let game = game.as_ref();
+        name::validate(name).map_err(|_| UploadError::InvalidName)?;
+        let length = u32::try_from(game.len())?.to_le_bytes();
+
+        // The name is padded with NUL bytes to fill its field.
+        let mut header = [0; HEADER_LEN];
+        header[..NAME_OFFSET].copy_from_slice(UPLOAD_COMMAND);
+        header[NAME_OFFSET..][..name.len()].copy_from_slice(name.as_bytes());
+        header[LENGTH_OFFSET..].copy_from_slice(&length);
+
+        Ok(Self {
+            header,
+            game,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            step: Step::Header,
+            scanner: ResponseScanner::new(&UPLOAD_RESPONSES),
+            rejections: RejectionScanner::new(),
+            result: None,
+        })
+    }
+
+    /// Sets how many bytes of the game are returned by each call to
+    /// `next_write`, which is 64 by default.
+    ///
+    /// Chunks smaller than that each need a flush, so the device receives the
+    /// game a little at a time. This paces devices which drop bytes when the
+    /// game arrives faster than they can write it to flash.
+    ///
+    /// ### Panics
+    /// Panics if `chunk_size` is 0.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must not be 0");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Returns the next bytes to write to the device, or `None` once the
+    /// whole game has been written.
+    ///
+    /// Every byte returned must be written before calling this again.
+    pub fn next_write(&mut self) -> Option<&[u8]> {
+        loop {
+            let step = self.step;
+            #[cfg(feature = "tracing")]
+            self.trace_step(step);
+
+            self.step = match step {
+                Step::Header => Step::Body(0),
+                Step::Body(offset) if offset < self.game.len() => {
+                    Step::Body(self.game.len().min(offset + self.chunk_size))
+                }
+                Step::Body(_) | Step::Response => {
+                    self.step = Step::Response;
+                    return None;
+                }
+            };
+
+            // Steps with nothing to write, like the body of an empty game,
+            // are skipped.
+            if !self.step_bytes(step).is_empty() {
+                return Some(self.step_bytes(step));
+            }
+        }
+    }
+
+    /// Emits an event when a new part of the upload starts.
+    #[cfg(feature = "tracing")]
+    fn trace_step(&self, step: Step) {
+        match step {
+            Step::Header => event!(debug, "writing header"),
+            Step::Body(0) if !self.game.is_empty() => {
+                event!(debug, bytes = self.game.len(), "writing game")
+            }
+            Step::Body(offset) if offset >= self.game.len() => {
+                event!(debug, "waiting for response")
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the bytes written for `step`.
+    fn step_bytes(&self, step: Step) -> &[u8] {
+        match step {
+            Step::Header => &self.header,
+            Step::Body(offset) => &self.game[offset..self.game.len().min(offset + self.chunk_size)],
+            Step::Response => &[],
+        }
+    }
+
+    /// Returns `true` if the bytes last returned by `next_write` end a part of
+    /// the upload, so the device should be flushed after writing them.
+    ///
+    /// This is the case after the header and after the last bytes of the
+    /// game, or after every chunk of the game if they are smaller than the
+    /// default. See `UploadMachine::with_chunk_size`.
+    pub fn needs_flush(&self) -> bool {
+        match self.step {
+            Step::Body(offset) => {
+                offset == 0 || offset >= self.game.len() || self.chunk_size < DEFAULT_CHUNK_SIZE
+            }
+            Step::Header | Step::Response => false,
+        }
+    }
+
+    /// Scans output read from the device for the response to the upload.
+    ///
+    /// Returns the `UploadResult` once a response has been found, and `None`
+    /// if more output is needed. Responses split across calls are found.
+    /// Output after the response is ignored, as are calls made after the
+    /// response was found.
+    ///
+    /// Responses this crate does not know are only returned once the output
+    /// ends without a known one, by `finish_read`.
+    pub fn feed_read(&mut self, bytes: &[u8]) -> Option<UploadResult> {
+        if self.result.is_none() {
+            self.rejections.feed(bytes);
+            self.result = self.scanner.feed(bytes).map(|(result, _)| result);
+        }
+
+        self.result.clone()
+    }
+
+    /// Tells the machine that the output of the device ended without a
+    /// known response, like when a read times out. Returns
+    /// `UploadResult::Rejected` with the last unknown rejection in the
+    /// output, if there was one. Returns the response already found, if any.
+    pub fn finish_read(&mut self) -> Option<UploadResult> {
+        if self.result.is_none() {
+            self.result = self.rejections.finish().map(UploadResult::Rejected);
+        }
+
+        self.result.clone()
+    }
+
+    /// Returns `true` once all bytes have been written and the machine is
+    /// waiting for a response from `feed_read`.
+    pub fn is_waiting(&self) -> bool {
+        self.step == Step::Response && self.result.is_none()
+    }
+
+    /// Returns how far the upload has got, counting the bytes returned by
+    /// `next_write` as written.
+    pub fn progress(&self) -> UploadProgress {
+        match (self.step, &self.result) {
+            (_, Some(_)) => UploadProgress::Done,
+            (Step::Header, None) => UploadProgress::Header,
+            (Step::Body(written), None) => UploadProgress::Game {
+                written,
+                total: self.game.len(),
+            },
+            (Step::Response, None) => UploadProgress::Waiting,
+        }
+    }
+
+    /// Returns the response found by `feed_read`, if any.
+    pub fn result(&self) -> Option<&UploadResult> {
+        self.result.as_ref()
+    }
+}
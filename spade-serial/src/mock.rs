@@ -0,0 +1,362 @@
+//! A mock device running Spade, for testing code which uses this crate
+//! without a physical device.
+//!
+//! `SerialMock` is a `Read + Write`r, so it can be passed anywhere a serial
+//! port can. It understands the upload command and the legacy startup
+//! sequence, and keeps the games it receives:
+//! ```
+//! use spade_serial::mock::SerialMock;
+//! use spade_serial::UploadResult;
+//!
+//! let mut device = SerialMock::new().game_slots(1);
+//! assert_eq!(
+//!     spade_serial::upload_game(&mut device, "game", "console.log(1)"),
+//!     Ok(UploadResult::AllGood)
+//! );
+//! assert_eq!(device.games()[0].name(), "game");
+//! assert_eq!(
+//!     spade_serial::upload_game(&mut device, "other", "console.log(2)"),
+//!     Ok(UploadResult::OutOfMetadata)
+//! );
+//! ```
+//! Reads return `Ok(0)` when the device has no output, instead of blocking or
+//! timing out, so a command waiting for a response fails with
+//! `UploadError::NoResponse` rather than hanging the test.
+//!
+//! `ChaosTransport` wraps the mock, or any other transport, to inject faults
+//! at random.
+
+mod chaos;
+
+pub use chaos::ChaosTransport;
+
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+use crate::protocol::{
+    ALL_GOOD, LEGACY_END_SEQ, LEGACY_RESPONSE, LEGACY_STARTUP_SEQ, LENGTH_BYTES, MAX_NAME_BYTES,
+    MODERN_RESPONSE, OO_FLASH, OO_METADATA, UPLOAD_COMMAND,
+};
+
+/// The generation of Spade firmware a `SerialMock` behaves like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Firmware {
+    /// A legacy version, which stores a single game sent after the legacy
+    /// startup sequence. See `spade_serial::upload_game_legacy`.
+    Legacy,
+    /// A current version, which stores named games sent with the `'UPLOAD'`
+    /// command.
+    Modern,
+}
+
+/// A problem a `SerialMock` can be set up to have, to test how code copes
+/// with unreliable devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The device never responds to commands, like a device whose firmware
+    /// has frozen. Output added with `SerialMock::push_output` is still read.
+    Unresponsive,
+    /// Each write accepts at most one byte.
+    ShortWrites,
+    /// The device stops accepting bytes once this many have been written to
+    /// it, returning `Ok(0)` from writes.
+    StallAfter(usize),
+    /// The device disconnects once this many bytes have been written to it.
+    /// Every read and write afterwards fails with `ErrorKind::BrokenPipe`,
+    /// like a serial port whose device was unplugged.
+    DisconnectAfter(usize),
+}
+
+/// A game stored by a `SerialMock`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StoredGame {
+    name: String,
+    source: Vec<u8>,
+}
+
+impl StoredGame {
+    /// Returns the name the game was uploaded with, without its NUL padding.
+    /// Games uploaded to legacy firmware do not have a name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the source of the game.
+    pub fn source(&self) -> &[u8] {
+        &self.source
+    }
+}
+
+/// The part of the protocol the mock expects next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Progress {
+    Command,
+    Name,
+    Length,
+    Body(usize),
+    LegacyGame,
+}
+
+/// A mock device running Spade.
+///
+/// By default, it behaves like `Firmware::Modern` with room for 8 games and
+/// 256 KiB of flash, and responds immediately. Each setting is changed with a
+/// method of the same name:
+/// ```
+/// # use std::time::Duration;
+/// use spade_serial::mock::{Fault, Firmware, SerialMock};
+///
+/// let device = SerialMock::new()
+///     .firmware(Firmware::Legacy)
+///     .latency(Duration::from_millis(5))
+///     .fault(Fault::ShortWrites);
+/// ```
+/// Everything written is parsed a byte at a time, so the mock does not depend
+/// on how writes are split. A game which does not fit in the remaining flash
+/// is rejected with `'OO_FLASH'`, and one uploaded once every slot is used
+/// with `'OO_METADATA'`.
+#[derive(Debug, Clone)]
+pub struct SerialMock {
+    firmware: Firmware,
+    game_slots: usize,
+    flash_size: usize,
+    latency: Duration,
+    faults: Vec<Fault>,
+    games: Vec<StoredGame>,
+    received: Vec<u8>,
+    output: VecDeque<u8>,
+    progress: Progress,
+    pending: Vec<u8>,
+    incoming: StoredGame,
+    disconnected: bool,
+}
+
+impl Default for SerialMock {
+    fn default() -> Self {
+        Self {
+            firmware: Firmware::Modern,
+            game_slots: 8,
+            flash_size: 256 * 1024,
+            latency: Duration::ZERO,
+            faults: Vec::new(),
+            games: Vec::new(),
+            received: Vec::new(),
+            output: VecDeque::new(),
+            progress: Progress::Command,
+            pending: Vec::new(),
+            incoming: StoredGame::default(),
+            disconnected: false,
+        }
+    }
+}
+
+impl SerialMock {
+    /// Creates a mock device with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the generation of firmware the device behaves like.
+    pub fn firmware(mut self, firmware: Firmware) -> Self {
+        self.firmware = firmware;
+        self
+    }
+
+    /// Sets how many more games the device can store.
+    pub fn game_slots(mut self, game_slots: usize) -> Self {
+        self.game_slots = game_slots;
+        self
+    }
+
+    /// Sets how many bytes of flash are free for games.
+    pub fn flash_size(mut self, flash_size: usize) -> Self {
+        self.flash_size = flash_size;
+        self
+    }
+
+    /// Sets how long each read waits before returning, like a device
+    /// connected over a slow link.
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Adds a fault to the device. Several faults can be combined.
+    pub fn fault(mut self, fault: Fault) -> Self {
+        self.faults.push(fault);
+        self
+    }
+
+    /// Adds output to be read from the device, like a running game printing
+    /// with `console.log`.
+    pub fn push_output(&mut self, output: &[u8]) {
+        self.output.extend(output);
+    }
+
+    /// Returns whether there is output which has not been read.
+    pub fn has_output(&self) -> bool {
+        !self.output.is_empty()
+    }
+
+    /// Returns the games the device stored, in the order they were uploaded.
+    pub fn games(&self) -> &[StoredGame] {
+        &self.games
+    }
+
+    /// Returns how many more games the device can store.
+    pub fn game_slots_left(&self) -> usize {
+        self.game_slots
+    }
+
+    /// Returns how many bytes of flash are still free for games.
+    pub fn flash_left(&self) -> usize {
+        self.flash_size
+    }
+
+    /// Returns every byte written to the device.
+    pub fn received(&self) -> &[u8] {
+        &self.received
+    }
+
+    /// Queues a response to a command, unless the device is unresponsive.
+    fn respond(&mut self, response: &[u8]) {
+        if !self.faults.contains(&Fault::Unresponsive) {
+            self.output.extend(response);
+        }
+    }
+
+    /// Stores the game being received, or rejects it if there is no room.
+    fn finish_upload(&mut self) {
+        self.progress = Progress::Command;
+        let game = std::mem::take(&mut self.incoming);
+
+        if game.source.len() > self.flash_size {
+            self.respond(OO_FLASH);
+        } else if self.game_slots == 0 {
+            self.respond(OO_METADATA);
+        } else {
+            self.game_slots -= 1;
+            self.flash_size -= game.source.len();
+            self.games.push(game);
+            self.respond(ALL_GOOD);
+        }
+    }
+
+    /// Parses one byte written to the device.
+    fn receive(&mut self, byte: u8) {
+        match self.progress {
+            Progress::Command => {
+                self.pending.push(byte);
+                if self.pending.ends_with(UPLOAD_COMMAND) && self.firmware == Firmware::Modern {
+                    self.pending.clear();
+                    self.progress = Progress::Name;
+                } else if self.pending.ends_with(&LEGACY_STARTUP_SEQ) {
+                    self.pending.clear();
+                    match self.firmware {
+                        Firmware::Legacy => {
+                            self.respond(LEGACY_RESPONSE);
+                            self.progress = Progress::LegacyGame;
+                        }
+                        Firmware::Modern => self.respond(MODERN_RESPONSE),
+                    }
+                }
+            }
+            Progress::Name => {
+                self.pending.push(byte);
+                if self.pending.len() == MAX_NAME_BYTES {
+                    let name = self.pending.split(|&byte| byte == 0).next().unwrap_or(&[]);
+                    self.incoming.name = String::from_utf8_lossy(name).into_owned();
+                    self.pending.clear();
+                    self.progress = Progress::Length;
+                }
+            }
+            Progress::Length => {
+                self.pending.push(byte);
+                if let Ok(length) = <[u8; LENGTH_BYTES]>::try_from(&self.pending[..]) {
+                    self.pending.clear();
+                    match u32::from_le_bytes(length) as usize {
+                        0 => self.finish_upload(),
+                        length => self.progress = Progress::Body(length),
+                    }
+                }
+            }
+            Progress::Body(length) => {
+                self.incoming.source.push(byte);
+                if self.incoming.source.len() == length {
+                    self.finish_upload();
+                }
+            }
+            Progress::LegacyGame => {
+                self.incoming.source.push(byte);
+                if self.incoming.source.ends_with(&LEGACY_END_SEQ) {
+                    let mut game = std::mem::take(&mut self.incoming);
+                    game.source
+                        .truncate(game.source.len() - LEGACY_END_SEQ.len());
+
+                    // Legacy versions only store one game.
+                    self.games = vec![game];
+                    self.progress = Progress::Command;
+                }
+            }
+        }
+    }
+}
+
+impl Read for SerialMock {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.disconnected {
+            return Err(std::io::Error::from(ErrorKind::BrokenPipe));
+        }
+        if !self.latency.is_zero() {
+            thread::sleep(self.latency);
+        }
+
+        let bytes = buf.len().min(self.output.len());
+        for (byte, output) in buf.iter_mut().zip(self.output.drain(..bytes)) {
+            *byte = output;
+        }
+
+        Ok(bytes)
+    }
+}
+
+impl Write for SerialMock {
+    fn write(&mut self, mut buf: &[u8]) -> std::io::Result<usize> {
+        if self.disconnected {
+            return Err(std::io::Error::from(ErrorKind::BrokenPipe));
+        }
+
+        for &fault in &self.faults {
+            match fault {
+                Fault::ShortWrites => buf = &buf[..buf.len().min(1)],
+                Fault::StallAfter(limit) => {
+                    buf = &buf[..buf.len().min(limit.saturating_sub(self.received.len()))];
+                }
+                Fault::DisconnectAfter(limit) => {
+                    if self.received.len() >= limit {
+                        self.disconnected = true;
+                        return Err(std::io::Error::from(ErrorKind::BrokenPipe));
+                    }
+                    buf = &buf[..buf.len().min(limit - self.received.len())];
+                }
+                Fault::Unresponsive => {}
+            }
+        }
+
+        for &byte in buf {
+            self.received.push(byte);
+            self.receive(byte);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.disconnected {
+            true => Err(std::io::Error::from(ErrorKind::BrokenPipe)),
+            false => Ok(()),
+        }
+    }
+}
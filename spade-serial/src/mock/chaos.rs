@@ -0,0 +1,175 @@
+use std::io::ErrorKind;
+use std::thread;
+use std::time::Duration;
+
+use crate::SpadeTransport;
+
+/// A small, seedable random number generator (SplitMix64), so runs of a
+/// `ChaosTransport` can be reproduced from their seed.
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns `true` with the probability `rate`.
+    fn chance(&mut self, rate: f64) -> bool {
+        // The top 53 bits fill the mantissa of an `f64` from 0 to 1.
+        let sample = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+        rate > 0.0 && sample < rate
+    }
+
+    /// Returns a number from 1 to `max`.
+    fn up_to(&mut self, max: usize) -> usize {
+        1 + (self.next() % max as u64) as usize
+    }
+}
+
+/// A transport which injects faults into another transport, to test how code
+/// copes with an unreliable connection.
+///
+/// Each kind of fault happens with a configurable probability, decided by a
+/// random number generator seeded with `seed`, so a failing run can be
+/// reproduced by using the same seed. No faults are injected by default; each
+/// is enabled with a method of the same name:
+/// ```
+/// # use std::time::Duration;
+/// use spade_serial::mock::{ChaosTransport, SerialMock};
+///
+/// let mut port = ChaosTransport::new(SerialMock::new(), 42)
+///     .split_writes(0.5)
+///     .timeouts(0.1)
+///     .delays(0.1, Duration::from_millis(1));
+/// let result = spade_serial::upload_game(&mut port, "game", "console.log(1)");
+/// ```
+/// Dropped and duplicated bytes corrupt what is written, so the device may
+/// store a different game than the one uploaded, or never respond.
+#[derive(Debug, Clone)]
+pub struct ChaosTransport<T> {
+    inner: T,
+    rng: Rng,
+    drop_rate: f64,
+    duplicate_rate: f64,
+    split_rate: f64,
+    delay_rate: f64,
+    delay: Duration,
+    timeout_rate: f64,
+}
+
+impl<T: SpadeTransport> ChaosTransport<T> {
+    /// Wraps `inner`, injecting faults decided by a random number generator
+    /// seeded with `seed`.
+    pub fn new(inner: T, seed: u64) -> Self {
+        Self {
+            inner,
+            rng: Rng(seed),
+            drop_rate: 0.0,
+            duplicate_rate: 0.0,
+            split_rate: 0.0,
+            delay_rate: 0.0,
+            delay: Duration::ZERO,
+            timeout_rate: 0.0,
+        }
+    }
+
+    /// Sets the probability of each byte written being lost.
+    pub fn drop_bytes(mut self, rate: f64) -> Self {
+        self.drop_rate = rate;
+        self
+    }
+
+    /// Sets the probability of each byte written being sent twice.
+    pub fn duplicate_bytes(mut self, rate: f64) -> Self {
+        self.duplicate_rate = rate;
+        self
+    }
+
+    /// Sets the probability of a write only accepting part of the bytes given
+    /// to it.
+    pub fn split_writes(mut self, rate: f64) -> Self {
+        self.split_rate = rate;
+        self
+    }
+
+    /// Sets the probability of a read or write waiting for `delay` first.
+    pub fn delays(mut self, rate: f64, delay: Duration) -> Self {
+        self.delay_rate = rate;
+        self.delay = delay;
+        self
+    }
+
+    /// Sets the probability of a read failing with `ErrorKind::TimedOut`
+    /// without reading anything.
+    pub fn timeouts(mut self, rate: f64) -> Self {
+        self.timeout_rate = rate;
+        self
+    }
+
+    /// Returns a reference to the wrapped transport.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the wrapped transport.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn maybe_delay(&mut self) {
+        if self.rng.chance(self.delay_rate) {
+            thread::sleep(self.delay);
+        }
+    }
+}
+
+impl<T: SpadeTransport> SpadeTransport for ChaosTransport<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        self.maybe_delay();
+        if self.rng.chance(self.timeout_rate) {
+            return Err(std::io::Error::from(ErrorKind::TimedOut));
+        }
+
+        self.inner.read(buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        self.maybe_delay();
+
+        let accepted = match buf.len() > 1 && self.rng.chance(self.split_rate) {
+            true => self.rng.up_to(buf.len() - 1),
+            false => buf.len(),
+        };
+
+        let mut sent = Vec::with_capacity(accepted);
+        for &byte in &buf[..accepted] {
+            if self.rng.chance(self.drop_rate) {
+                continue;
+            }
+            sent.push(byte);
+            if self.rng.chance(self.duplicate_rate) {
+                sent.push(byte);
+            }
+        }
+        self.inner.write_all(&sent)?;
+
+        Ok(accepted)
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.inner.flush()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), std::io::Error> {
+        self.inner.set_timeout(timeout)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.inner.timeout()
+    }
+}
@@ -0,0 +1,121 @@
+use core::fmt;
+
+use crate::protocol::MAX_NAME_BYTES;
+
+/// The name of a game as it is stored on the device.
+///
+/// Spade stores names in a 100 byte, NUL-padded field, so a valid name is at
+/// most `GameName::MAX_BYTES` bytes long and does not contain any NUL
+/// characters.
+///
+/// With the `serde` feature, it is serialized as a string, and checked like
+/// with `GameName::new` when deserialized.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+pub struct GameName(String);
+
+/// Represents the reasons a name can be rejected by `GameName::new` or
+/// `UploadMachine::new`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GameNameError {
+    /// The name is over 100 bytes long.
+    TooLong,
+    /// The name contains a NUL character, which the device would treat as the
+    /// end of the name.
+    ContainsNul,
+}
+
+impl fmt::Display for GameNameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameNameError::TooLong => {
+                write!(f, "game name is longer than {} bytes", MAX_NAME_BYTES)
+            }
+            GameNameError::ContainsNul => write!(f, "game name contains a NUL character"),
+        }
+    }
+}
+
+/// Checks that `name` can be stored on the device, without allocating.
+pub(crate) fn validate(name: &str) -> Result<(), GameNameError> {
+    if name.len() > MAX_NAME_BYTES {
+        Err(GameNameError::TooLong)
+    } else if name.contains('\0') {
+        Err(GameNameError::ContainsNul)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl GameName {
+    /// The maximum length of a name in bytes.
+    pub const MAX_BYTES: usize = MAX_NAME_BYTES;
+
+    /// Creates a name, checking that it can be stored on the device.
+    ///
+    /// ### Errors
+    /// Returns `GameNameError::TooLong` if `name` is over `MAX_BYTES` bytes
+    /// long, or `GameNameError::ContainsNul` if it contains a NUL character.
+    pub fn new(name: &str) -> Result<Self, GameNameError> {
+        validate(name).map(|_| Self(name.to_owned()))
+    }
+
+    /// Creates a name from any string by removing NUL characters and then
+    /// truncating it to fit in `MAX_BYTES` bytes.
+    ///
+    /// The name is truncated on a character boundary, so it may end up
+    /// slightly shorter than `MAX_BYTES` if a multi-byte character would be
+    /// split.
+    pub fn truncate_lossy(name: &str) -> Self {
+        let mut name = name.replace('\0', "");
+        let mut end = name.len().min(Self::MAX_BYTES);
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name.truncate(end);
+
+        Self(name)
+    }
+
+    /// Returns the name as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl AsRef<str> for GameName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl TryFrom<String> for GameName {
+    type Error = GameNameError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        validate(&value).map(|_| Self(value))
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<GameName> for String {
+    fn from(value: GameName) -> Self {
+        value.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for GameName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
@@ -0,0 +1,295 @@
+use std::fmt;
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::machine::DEFAULT_CHUNK_SIZE;
+use crate::trace::{event, span};
+use crate::{
+    drain, run_upload, CancellationToken, Game, SpadeTransport, UploadError, UploadMachine,
+    UploadProgress, UploadResult,
+};
+
+/// A callback set with `UploadOptions::on_progress`.
+#[derive(Clone)]
+struct ProgressCallback(Arc<dyn Fn(UploadProgress) + Send + Sync>);
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Options for `spade_serial::upload_game_with`.
+///
+/// The defaults upload like `spade_serial::upload`. Each option is set with a
+/// method of the same name:
+/// ```
+/// # use std::time::{Duration, Instant};
+/// # use spade_serial::UploadOptions;
+/// let options = UploadOptions::new()
+///     .timeout(Duration::from_secs(5))
+///     .retries(2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct UploadOptions {
+    timeout: Option<Duration>,
+    retries: u32,
+    max_timeouts: u32,
+    chunk_size: usize,
+    token: Option<CancellationToken>,
+    header_delay: Option<Duration>,
+    progress: Option<ProgressCallback>,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            retries: 0,
+            max_timeouts: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            token: None,
+            header_delay: None,
+            progress: None,
+        }
+    }
+}
+
+impl UploadOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the read timeout of the transport during the upload, restoring the
+    /// previous one afterwards. By default, the transport's timeout is used,
+    /// which is also the case for transports which do not support timeouts.
+    ///
+    /// Serial ports only support it when wrapped in `transport::Serial`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how many times the upload is tried again if the device does not
+    /// respond, a write stalls, or an I/O operation fails or times out, which
+    /// is 0 by default. Responses from the device, like
+    /// `UploadResult::OutOfFlash`, are not retried, and neither are uploads
+    /// to a device which was disconnected, since it cannot be written to
+    /// again. Use `transport::Reconnecting` to upload again once it is back.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets how many reads in a row may time out while waiting for the
+    /// response before the upload fails with `UploadError::Timeout`, which is
+    /// 0 by default. Any output from the device starts the count again.
+    ///
+    /// Devices can take a while to write large games to flash, so this lets
+    /// a short timeout notice problems with the connection quickly without
+    /// giving up on a device which is still busy.
+    pub fn max_timeouts(mut self, max_timeouts: u32) -> Self {
+        self.max_timeouts = max_timeouts;
+        self
+    }
+
+    /// Sets how many bytes of the game are written at a time, which is 64 by
+    /// default. See `UploadMachine::with_chunk_size`.
+    ///
+    /// Smaller chunks are each written and flushed on their own, for devices
+    /// which drop bytes when the game arrives too quickly. Progress is
+    /// reported after each chunk.
+    ///
+    /// ### Panics
+    /// Panics if `chunk_size` is 0.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must not be 0");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Sets a token to cancel the upload with, like with
+    /// `spade_serial::upload_game_cancellable`. Cancelled uploads are not
+    /// retried.
+    pub fn token(mut self, token: CancellationToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Sets how long to wait after writing the upload header before writing
+    /// the game. There is no delay by default.
+    ///
+    /// Spade does not acknowledge the header, and some devices drop the start
+    /// of the game if it arrives before they have started erasing flash,
+    /// corrupting the upload. Waiting gives them time to.
+    pub fn header_delay(mut self, delay: Duration) -> Self {
+        self.header_delay = Some(delay);
+        self
+    }
+
+    /// Sets a callback which is told how far the upload has got: before the
+    /// header is written, after each write, once the device is being waited
+    /// on, and when it responds. Retried uploads start again from
+    /// `UploadProgress::Header`.
+    ///
+    /// Large games take several seconds to write, so this can be used to show
+    /// a progress bar.
+    /// ```
+    /// # use spade_serial::{UploadOptions, UploadProgress};
+    /// let options = UploadOptions::new().on_progress(|progress| {
+    ///     if let UploadProgress::Game { written, total } = progress {
+    ///         println!("{written}/{total} bytes");
+    ///     }
+    /// });
+    /// ```
+    pub fn on_progress(
+        mut self,
+        callback: impl Fn(UploadProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(ProgressCallback(Arc::new(callback)));
+        self
+    }
+}
+
+/// How an upload with `spade_serial::upload_game_reported` went, for logging
+/// transfer performance.
+///
+/// A connection which gets slower over time, or needs retries where it did
+/// not before, is often a sign of a failing cable or USB hub.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UploadReport {
+    /// The response from the device.
+    pub result: UploadResult,
+    /// The bytes written to the device, including the header and those of
+    /// tries which failed.
+    pub bytes_sent: usize,
+    /// How long the upload took, from the first write to the response,
+    /// including any retries.
+    pub elapsed: Duration,
+    /// How many times the upload was tried again before the device
+    /// responded.
+    pub retries: u32,
+}
+
+impl UploadReport {
+    /// Returns the rate the upload went at in bits per second, counting 8
+    /// bits for each byte sent. Waiting for the response counts towards the
+    /// time, so this is lower than the baud rate of the port.
+    /// ```
+    /// # use std::time::Duration;
+    /// # use spade_serial::{UploadReport, UploadResult};
+    /// let report = UploadReport {
+    ///     result: UploadResult::AllGood,
+    ///     bytes_sent: 1000,
+    ///     elapsed: Duration::from_secs(2),
+    ///     retries: 0,
+    /// };
+    /// assert_eq!(report.effective_baud(), 4000.0);
+    /// ```
+    pub fn effective_baud(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+        self.bytes_sent as f64 * 8.0 / seconds
+    }
+}
+
+/// Uploads a `Game` to a device running Spade, with `options`.
+///
+/// With the default options, this behaves like `spade_serial::upload`.
+///
+/// ### Errors
+/// Returns the same errors as `spade_serial::upload_game_cancellable`, from
+/// the last try if the upload was retried. Setting the timeout also returns
+/// `UploadError::IOError` if the transport fails to change it.
+pub fn upload_game_with(
+    io: &mut (impl SpadeTransport + ?Sized),
+    game: &Game,
+    options: &UploadOptions,
+) -> Result<UploadResult, UploadError> {
+    upload_game_reported(io, game, options).map(|report| report.result)
+}
+
+/// Uploads a `Game` to a device running Spade, with `options`, and reports
+/// how much was sent, how long it took, and how many retries were needed.
+///
+/// This behaves like `spade_serial::upload_game_with`.
+///
+/// ### Errors
+/// Returns the same errors as `spade_serial::upload_game_with`.
+pub fn upload_game_reported(
+    io: &mut (impl SpadeTransport + ?Sized),
+    game: &Game,
+    options: &UploadOptions,
+) -> Result<UploadReport, UploadError> {
+    span!(
+        info_span,
+        "upload_game_with",
+        name = game.name().as_str(),
+        game_bytes = game.source().len()
+    );
+
+    let previous_timeout = io.timeout();
+    let set_timeout = match options.timeout.map(|timeout| io.set_timeout(timeout)) {
+        Some(Ok(())) => true,
+        None => false,
+        Some(Err(error)) if error.kind() == ErrorKind::Unsupported => {
+            event!(
+                debug,
+                "transport does not support timeouts, keeping its own"
+            );
+            false
+        }
+        Some(Err(_)) => return Err(UploadError::IOError),
+    };
+
+    let start = Instant::now();
+    let mut sent = 0;
+    let mut tries_left = options.retries;
+    let result = loop {
+        let machine = UploadMachine::new(game.name().as_str(), game.source())?
+            .with_chunk_size(options.chunk_size);
+        let progress = options.progress.as_ref().map(|callback| &*callback.0);
+        match run_upload(
+            io,
+            machine,
+            options.token.as_ref(),
+            options.header_delay,
+            progress,
+            options.max_timeouts,
+            &mut sent,
+        ) {
+            Err(
+                UploadError::IOError
+                | UploadError::NoResponse
+                | UploadError::WriteStalled
+                | UploadError::Timeout,
+            ) if tries_left > 0 => {
+                event!(info, tries_left, "retrying upload");
+                tries_left -= 1;
+                drain(io);
+            }
+            result => break result,
+        }
+    };
+    let elapsed = start.elapsed();
+
+    // The upload is over either way, so a timeout which could not be
+    // restored does not change its result.
+    if let (true, Some(timeout)) = (set_timeout, previous_timeout) {
+        if io.set_timeout(timeout).is_err() {
+            event!(warn, "could not restore the timeout");
+        }
+    }
+
+    Ok(UploadReport {
+        result: result?,
+        bytes_sent: sent,
+        elapsed,
+        retries: options.retries - tries_left,
+    })
+}
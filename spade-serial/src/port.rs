@@ -0,0 +1,156 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serialport::{ErrorKind, SerialPort, SerialPortBuilder, SerialPortInfo, SerialPortType};
+
+use crate::protocol::LEGACY_STARTUP_SEQ;
+use crate::SpadeTransport;
+
+/// The USB vendor ID of Raspberry Pi, used by the RP2040 in the Sprig.
+pub const SPADE_USB_VID: u16 = 0x2e8a;
+
+/// The USB product ID used by Spade's serial port, which is the Pico SDK's
+/// default for USB serial.
+pub const SPADE_USB_PID: u16 = 0x000a;
+
+/// The baud rate Spade communicates at.
+pub const DEFAULT_BAUD_RATE: u32 = 115200;
+
+/// The read timeout used by `spade_serial::open`.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Lists the serial ports which could be devices running Spade.
+///
+/// Ports are matched by the USB vendor and product ID of the RP2040 running
+/// the Pico SDK, so other RP2040 boards using the SDK's USB serial are also
+/// returned. Use `spade_serial::is_running_legacy` or similar on the opened
+/// port to check the device.
+///
+/// Available with the `serialport` feature.
+///
+/// ### Errors
+/// Returns any errors from `serialport::available_ports`.
+pub fn discover() -> Result<Vec<SerialPortInfo>, serialport::Error> {
+    Ok(serialport::available_ports()?
+        .into_iter()
+        .filter(|port| match &port.port_type {
+            SerialPortType::UsbPort(usb) => usb.vid == SPADE_USB_VID && usb.pid == SPADE_USB_PID,
+            _ => false,
+        })
+        .collect())
+}
+
+/// How often the serial ports are listed while waiting for a device.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Waits up to `wait` for `discover` to find at least one device, and returns
+/// the devices found, which may be none.
+pub(crate) fn wait_for_ports(wait: Duration) -> Result<Vec<SerialPortInfo>, serialport::Error> {
+    let deadline = Instant::now() + wait;
+    loop {
+        let ports = discover()?;
+        if !ports.is_empty() || Instant::now() >= deadline {
+            return Ok(ports);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Waits up to `wait` for the USB device with the serial number
+/// `serial_number` to be connected, and returns its serial port if it was.
+pub(crate) fn wait_for_serial_number(
+    serial_number: &str,
+    wait: Duration,
+) -> Result<Option<SerialPortInfo>, serialport::Error> {
+    let deadline = Instant::now() + wait;
+    loop {
+        let port = serialport::available_ports()?
+            .into_iter()
+            .find(|port| match &port.port_type {
+                SerialPortType::UsbPort(usb) => usb.serial_number.as_deref() == Some(serial_number),
+                _ => false,
+            });
+        if port.is_some() || Instant::now() >= deadline {
+            return Ok(port);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Opens the serial port at `path` with the settings Spade uses, at
+/// `DEFAULT_BAUD_RATE` with a read timeout of `DEFAULT_TIMEOUT`.
+///
+/// Available with the `serialport` feature.
+///
+/// ### Errors
+/// Returns any errors from opening the port with `serialport`.
+pub fn open(path: &str) -> Result<Box<dyn SerialPort>, serialport::Error> {
+    serialport::new(path, DEFAULT_BAUD_RATE)
+        .timeout(DEFAULT_TIMEOUT)
+        .open()
+}
+
+/// Opens the serial port described by `builder` at the first baud rate in
+/// `candidates` the device responds at, and returns it with that baud rate.
+///
+/// Some Sprig clones ship firmware listening at a baud rate other than
+/// `DEFAULT_BAUD_RATE`, such as 9600. Each baud rate is checked by sending the
+/// legacy startup sequence and waiting for readable text in response, which
+/// both legacy and current Spade versions print. Set a timeout on `builder`,
+/// as the response is read until the port times out.
+/// ```no_run
+/// use spade_serial::{negotiate_baud, DEFAULT_BAUD_RATE, DEFAULT_TIMEOUT};
+///
+/// let builder = serialport::new("/dev/ttyACM0", DEFAULT_BAUD_RATE).timeout(DEFAULT_TIMEOUT);
+/// let (mut port, baud_rate) = negotiate_baud(builder, &[DEFAULT_BAUD_RATE, 9600])?;
+/// # Ok::<(), serialport::Error>(())
+/// ```
+///
+/// Available with the `serialport` feature.
+///
+/// ### Errors
+/// Returns any errors from opening the port with `serialport`, or an error of
+/// `ErrorKind::NoDevice` if the device did not respond at any of the baud
+/// rates.
+pub fn negotiate_baud(
+    builder: SerialPortBuilder,
+    candidates: &[u32],
+) -> Result<(Box<dyn SerialPort>, u32), serialport::Error> {
+    for &baud_rate in candidates {
+        let mut port = builder.clone().baud_rate(baud_rate).open()?;
+        if responds(&mut port) {
+            return Ok((port, baud_rate));
+        }
+    }
+
+    Err(serialport::Error::new(
+        ErrorKind::NoDevice,
+        "device did not respond at any baud rate",
+    ))
+}
+
+/// Sends the legacy startup sequence and checks that the device responds with
+/// text, which it will not if the baud rate is wrong.
+fn responds(io: &mut (impl SpadeTransport + ?Sized)) -> bool {
+    if io
+        .write_all(&LEGACY_STARTUP_SEQ)
+        .and_then(|_| io.flush())
+        .is_err()
+    {
+        return false;
+    }
+
+    let mut response = [0; 64];
+    let mut len = 0;
+    while len < response.len() {
+        match io.read(&mut response[len..]) {
+            Ok(0) | Err(_) => break,
+            Ok(bytes) => len += bytes,
+        }
+    }
+
+    len > 0
+        && response[..len]
+            .iter()
+            .all(|byte| byte.is_ascii_graphic() || byte.is_ascii_whitespace())
+}
@@ -0,0 +1,72 @@
+use std::io::ErrorKind;
+
+use crate::protocol::{LEGACY_STARTUP_SEQ, STARTUP_RESPONSES};
+use crate::trace::{event, span};
+use crate::{ResponseScanner, SpadeTransport};
+
+/// How much output is read while looking for a response before giving up.
+const MAX_PROBE_OUTPUT: usize = 256;
+
+/// Represents what `spade_serial::probe` found the device to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProbeResult {
+    /// The device did not respond like any Spade version.
+    NotSpade,
+    /// The device is running a legacy Spade version. See
+    /// `spade_serial::is_running_legacy`.
+    Legacy,
+    /// The device is running a current Spade version. Spade does not report
+    /// which version it is running.
+    Modern,
+}
+
+/// Checks if the device is running Spade, and which protocol it supports,
+/// before anything else is written to it.
+///
+/// This sends the legacy startup sequence, `[0, 1, 2, 3, 4]`, which both
+/// legacy and current Spade versions respond to, and classifies the response.
+/// The device is found not to be running Spade if the output ends or the read
+/// times out before either response, or if neither is found in the first 256
+/// bytes. Like with `spade_serial::is_running_legacy`, a legacy device waits
+/// for a game afterwards.
+///
+/// The transport should have a read timeout, or this may block forever on a
+/// device which does not respond.
+///
+/// ### Errors
+/// Returns any IO errors from `SpadeTransport::write_all`,
+/// `SpadeTransport::flush`, or `SpadeTransport::read`, except for reads
+/// timing out.
+pub fn probe(io: &mut (impl SpadeTransport + ?Sized)) -> Result<ProbeResult, std::io::Error> {
+    span!(debug_span, "probe");
+
+    io.write_all(&LEGACY_STARTUP_SEQ)?;
+    io.flush()?;
+
+    let mut scanner = ResponseScanner::new(&STARTUP_RESPONSES);
+    let mut buf = [0; 64];
+    let mut read = 0;
+    let result = loop {
+        if read >= MAX_PROBE_OUTPUT {
+            break ProbeResult::NotSpade;
+        }
+
+        let bytes = match io.read(&mut buf) {
+            Ok(0) => break ProbeResult::NotSpade,
+            Ok(bytes) => bytes,
+            Err(error) if matches!(error.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+                break ProbeResult::NotSpade
+            }
+            Err(error) => return Err(error),
+        };
+        read += bytes;
+
+        if let Some((result, _)) = scanner.feed(&buf[..bytes]) {
+            break result;
+        }
+    };
+
+    event!(debug, ?result, "probed device");
+    Ok(result)
+}
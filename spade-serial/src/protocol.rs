@@ -0,0 +1,198 @@
+//! The protocols used by different Spade versions.
+//!
+//! Each `Protocol` encapsulates how one generation of Spade firmware encodes
+//! commands and responds to them. `spade_serial::probe` finds which generation
+//! a device is running, and `ProbeResult::protocol` returns its protocol, so
+//! code written against `Protocol` works with either:
+//! ```no_run
+//! # let mut port = std::io::Cursor::new(Vec::new());
+//! if let Some(mut protocol) = spade_serial::probe(&mut port)?.protocol() {
+//!     protocol.upload(&mut port, "game", b"console.log(1)");
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//! Supporting a new firmware version means adding an implementation here.
+//!
+//! The constants in this module are the bytes which make up each command and
+//! response, for tools which need to speak the protocol themselves, like
+//! simulators and firmware tests. They are available without `std`.
+//!
+//! The responses each version can give are listed in tables, like
+//! `UPLOAD_RESPONSES`, which the functions talking to devices and
+//! `transport::Demux` scan output with. Recognizing a new response means
+//! adding it to its table.
+
+#[cfg(feature = "std")]
+use core::fmt;
+
+#[cfg(feature = "std")]
+use crate::legacy::write_legacy_game;
+use crate::UploadResult;
+#[cfg(feature = "std")]
+use crate::{upload_game_legacy, upload_named, ProbeResult, SpadeTransport, UploadError};
+
+/// The command which starts an upload.
+pub const UPLOAD_COMMAND: &[u8] = b"UPLOAD";
+
+/// The maximum length of the name of a game in bytes.
+pub const MAX_NAME_BYTES: usize = 100;
+
+/// The number of bytes used for the length of the game, which is sent as a
+/// little-endian `u32`.
+pub const LENGTH_BYTES: usize = 4;
+
+/// The offset of the name in the upload header. The name is padded with NUL
+/// bytes to fill `MAX_NAME_BYTES`.
+pub const NAME_OFFSET: usize = UPLOAD_COMMAND.len();
+
+/// The offset of the length of the game in the upload header.
+pub const LENGTH_OFFSET: usize = NAME_OFFSET + MAX_NAME_BYTES;
+
+/// The length of the upload header: the command, name and length of the game.
+/// The game follows it.
+pub const HEADER_LEN: usize = LENGTH_OFFSET + LENGTH_BYTES;
+
+/// The response to an upload which was accepted.
+pub const ALL_GOOD: &[u8] = b"ALL_GOOD";
+
+/// The response to an upload rejected for not fitting in flash.
+pub const OO_FLASH: &[u8] = b"OO_FLASH";
+
+/// The response to an upload rejected for there being no room to store
+/// another game.
+pub const OO_METADATA: &[u8] = b"OO_METADATA";
+
+/// The sequence which starts communication with legacy Spade versions.
+pub const LEGACY_STARTUP_SEQ: [u8; 5] = [0, 1, 2, 3, 4];
+
+/// The sequence which ends a game uploaded to legacy Spade versions.
+pub const LEGACY_END_SEQ: [u8; 5] = [4, 3, 2, 1, 0];
+
+/// The response of legacy Spade versions to `LEGACY_STARTUP_SEQ`.
+pub const LEGACY_RESPONSE: &[u8; 18] = b"found startup seq!";
+
+/// The response of current Spade versions to `LEGACY_STARTUP_SEQ`.
+pub const MODERN_RESPONSE: &[u8] = b"legacy startup detected";
+
+/// The responses of current Spade versions which end an upload, and the
+/// result each represents. Legacy versions do not respond to uploads.
+pub static UPLOAD_RESPONSES: [(&[u8], UploadResult); 3] = [
+    (ALL_GOOD, UploadResult::AllGood),
+    (OO_FLASH, UploadResult::OutOfFlash),
+    (OO_METADATA, UploadResult::OutOfMetadata),
+];
+
+/// The responses of each Spade version to `LEGACY_STARTUP_SEQ`, and what
+/// each means the device is running.
+#[cfg(feature = "std")]
+pub static STARTUP_RESPONSES: [(&[u8], ProbeResult); 2] = [
+    (LEGACY_RESPONSE, ProbeResult::Legacy),
+    (MODERN_RESPONSE, ProbeResult::Modern),
+];
+
+/// A way of issuing commands to a device, used by a generation of Spade
+/// firmware.
+#[cfg(feature = "std")]
+pub trait Protocol: fmt::Debug {
+    /// A short name for the protocol, for logs and messages.
+    fn name(&self) -> &'static str;
+
+    /// Uploads a game to the device.
+    ///
+    /// Protocols which do not store names ignore `name`.
+    ///
+    /// ### Errors
+    /// Returns an `UploadError` if an error occurs before the upload
+    /// completes, like `spade_serial::upload_game`.
+    fn upload(
+        &mut self,
+        io: &mut dyn SpadeTransport,
+        name: &str,
+        game: &[u8],
+    ) -> Result<UploadResult, UploadError>;
+}
+
+/// The protocol of legacy Spade versions, which start each upload with the
+/// legacy startup sequence and store a single game without a name. See
+/// `spade_serial::upload_game_legacy`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub struct V1Legacy {
+    /// Whether the startup sequence was already sent, such as by
+    /// `spade_serial::probe`, so the device is waiting for a game.
+    started: bool,
+}
+
+#[cfg(feature = "std")]
+impl V1Legacy {
+    /// Returns the protocol for a device which has not been sent the legacy
+    /// startup sequence.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the protocol for a device which has just been sent the legacy
+    /// startup sequence, like by `spade_serial::probe` or
+    /// `spade_serial::is_running_legacy`, and is waiting for a game. The
+    /// next upload does not send it again.
+    pub fn started() -> Self {
+        Self { started: true }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Protocol for V1Legacy {
+    fn name(&self) -> &'static str {
+        "legacy"
+    }
+
+    fn upload(
+        &mut self,
+        io: &mut dyn SpadeTransport,
+        _name: &str,
+        game: &[u8],
+    ) -> Result<UploadResult, UploadError> {
+        match core::mem::take(&mut self.started) {
+            true => write_legacy_game(io, game),
+            false => upload_game_legacy(io, game),
+        }
+    }
+}
+
+/// The protocol of current Spade versions, which upload named games with the
+/// 'UPLOAD' command. See `spade_serial::upload_game`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct V2;
+
+#[cfg(feature = "std")]
+impl Protocol for V2 {
+    fn name(&self) -> &'static str {
+        "v2"
+    }
+
+    fn upload(
+        &mut self,
+        io: &mut dyn SpadeTransport,
+        name: &str,
+        game: &[u8],
+    ) -> Result<UploadResult, UploadError> {
+        upload_named(io, name, game, None)
+    }
+}
+
+#[cfg(feature = "std")]
+impl ProbeResult {
+    /// Returns the protocol of the Spade version found, or `None` if the
+    /// device is not running Spade.
+    ///
+    /// A legacy device is waiting for a game after being probed, so its
+    /// protocol is `V1Legacy::started`.
+    pub fn protocol(&self) -> Option<Box<dyn Protocol>> {
+        match self {
+            ProbeResult::NotSpade => None,
+            ProbeResult::Legacy => Some(Box::new(V1Legacy::started())),
+            ProbeResult::Modern => Some(Box::new(V2)),
+        }
+    }
+}
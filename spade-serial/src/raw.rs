@@ -0,0 +1,151 @@
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+use crate::trace::{event, span};
+use crate::{ResponseScanner, SpadeTransport};
+
+/// How `spade_serial::send_raw_command` decides the response is complete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseMatcher<'a> {
+    /// The response ends with the first of these tokens to be read, like
+    /// `b"ALL_GOOD"`. Each can be up to `MAX_TOKEN_LEN` bytes long.
+    Tokens(&'a [&'a [u8]]),
+    /// The response is this many bytes long.
+    Length(usize),
+    /// The response is everything read until the timeout, or until the
+    /// output ends.
+    UntilTimeout,
+}
+
+/// Sends `command` to the device as is, and returns its response.
+///
+/// This is for firmware commands this crate does not support yet. The
+/// response is read in chunks until `expect` matches, so other output, like
+/// logs from the running game, is included in it. Output read in the same
+/// chunk after a token or the expected length is discarded.
+///
+/// The transport's read timeout is set to `timeout` while reading, and
+/// restored afterwards. Reading also stops once `timeout` has passed since
+/// the command was sent, even if the device keeps printing. Transports which
+/// do not support timeouts are used as they are.
+/// ```
+/// # use std::io::Cursor;
+/// # use std::time::Duration;
+/// # use spade_serial::{send_raw_command, ResponseMatcher};
+/// # let mut port = Cursor::new(b"\0\0\0\0\0log\nlegacy startup detected".to_vec());
+/// let response = send_raw_command(
+///     &mut port,
+///     &[0, 1, 2, 3, 4],
+///     ResponseMatcher::Tokens(&[b"found startup seq!", b"legacy startup detected"]),
+///     Duration::from_secs(1),
+/// )?;
+/// assert!(response.ends_with(b"legacy startup detected"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// ### Errors
+/// Returns any IO errors from `SpadeTransport::write_all`,
+/// `SpadeTransport::flush`, or `SpadeTransport::read`. Unless `expect` is
+/// `ResponseMatcher::UntilTimeout`, an error of `ErrorKind::TimedOut` is
+/// returned if the response did not complete in time, and an error of
+/// `ErrorKind::UnexpectedEof` if the output ended before it did.
+///
+/// ### Panics
+/// Panics if `expect` has no tokens, or a token longer than `MAX_TOKEN_LEN`
+/// bytes.
+pub fn send_raw_command(
+    io: &mut (impl SpadeTransport + ?Sized),
+    command: &[u8],
+    expect: ResponseMatcher,
+    timeout: Duration,
+) -> Result<Vec<u8>, std::io::Error> {
+    if let ResponseMatcher::Tokens(tokens) = expect {
+        assert!(
+            !tokens.is_empty(),
+            "expected response tokens must not be empty"
+        );
+    }
+    span!(
+        debug_span,
+        "send_raw_command",
+        command_bytes = command.len()
+    );
+
+    let previous_timeout = io.timeout();
+    let set_timeout = match io.set_timeout(timeout) {
+        Ok(()) => true,
+        Err(error) if error.kind() == ErrorKind::Unsupported => false,
+        Err(error) => return Err(error),
+    };
+
+    let result = write_and_capture(io, command, expect, timeout);
+
+    if let (true, Some(timeout)) = (set_timeout, previous_timeout) {
+        io.set_timeout(timeout)?;
+    }
+
+    event!(debug, ok = result.is_ok(), "raw command finished");
+    result
+}
+
+fn write_and_capture(
+    io: &mut (impl SpadeTransport + ?Sized),
+    command: &[u8],
+    expect: ResponseMatcher,
+    timeout: Duration,
+) -> Result<Vec<u8>, std::io::Error> {
+    io.write_all(command)?;
+    io.flush()?;
+
+    let tokens: Vec<_> = match expect {
+        ResponseMatcher::Tokens(tokens) => tokens.iter().map(|token| (*token, ())).collect(),
+        _ => Vec::new(),
+    };
+    let mut scanner = match expect {
+        ResponseMatcher::Tokens(_) => Some(ResponseScanner::new(&tokens)),
+        _ => None,
+    };
+
+    let deadline = Instant::now() + timeout;
+    let mut response = Vec::new();
+    let mut buf = [0; 64];
+    loop {
+        if let ResponseMatcher::Length(length) = expect {
+            if response.len() >= length {
+                return Ok(response);
+            }
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+
+        let limit = match expect {
+            ResponseMatcher::Length(length) => buf.len().min(length - response.len()),
+            _ => buf.len(),
+        };
+        let bytes = match io.read(&mut buf[..limit]) {
+            Ok(0) if expect == ResponseMatcher::UntilTimeout => return Ok(response),
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == ErrorKind::TimedOut => break,
+            Err(error) => return Err(error),
+        };
+        event!(trace, output = %String::from_utf8_lossy(&buf[..bytes]), "read");
+
+        match scanner
+            .as_mut()
+            .and_then(|scanner| scanner.feed(&buf[..bytes]))
+        {
+            Some(((), end)) => {
+                response.extend_from_slice(&buf[..end]);
+                return Ok(response);
+            }
+            None => response.extend_from_slice(&buf[..bytes]),
+        }
+    }
+
+    match expect {
+        ResponseMatcher::UntilTimeout => Ok(response),
+        _ => Err(ErrorKind::TimedOut.into()),
+    }
+}
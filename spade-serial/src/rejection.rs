@@ -0,0 +1,174 @@
+use core::fmt;
+
+/// The prefix of the responses which reject an upload, like `'OO_FLASH'`.
+const PREFIX: &[u8] = b"OO_";
+
+/// The longest response rejecting an upload which can be recognized without
+/// knowing it, in bytes.
+pub const MAX_REJECTION_LEN: usize = 32;
+
+/// A response rejecting an upload which this crate does not know, like
+/// `'OO_NAME'` from a newer Spade version. See `UploadResult::Rejected`.
+///
+/// It is `'OO_'` followed by uppercase letters, digits and underscores, at
+/// most `MAX_REJECTION_LEN` bytes in all. With the `serde` feature, it is
+/// serialized as a string.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rejection {
+    bytes: [u8; MAX_REJECTION_LEN],
+    len: usize,
+}
+
+impl Rejection {
+    /// Returns the rejection `response`, or `None` if it does not look like
+    /// one.
+    /// ```
+    /// # use spade_serial::Rejection;
+    /// assert_eq!(Rejection::new("OO_NAME").unwrap().as_str(), "OO_NAME");
+    /// assert!(Rejection::new("ALL_GOOD").is_none());
+    /// ```
+    pub fn new(response: &str) -> Option<Self> {
+        let response = response.as_bytes();
+        let valid = response.len() > PREFIX.len()
+            && response.len() <= MAX_REJECTION_LEN
+            && response.starts_with(PREFIX)
+            && response.iter().all(|&byte| is_token_byte(byte));
+        if !valid {
+            return None;
+        }
+
+        let mut bytes = [0; MAX_REJECTION_LEN];
+        bytes[..response.len()].copy_from_slice(response);
+        Some(Self {
+            bytes,
+            len: response.len(),
+        })
+    }
+
+    /// Returns the response as it was read, like `"OO_NAME"`.
+    pub fn as_str(&self) -> &str {
+        // Only ASCII is accepted by `new` and `RejectionScanner`.
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+}
+
+impl fmt::Debug for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Rejection").field(&self.as_str()).finish()
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Rejection {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Rejection {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Rejection;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a response rejecting an upload, like \"OO_NAME\"")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Rejection, E> {
+                Rejection::new(value)
+                    .ok_or_else(|| E::invalid_value(serde::de::Unexpected::Str(value), &self))
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+/// Returns `true` if `byte` can be part of a rejection.
+fn is_token_byte(byte: u8) -> bool {
+    byte.is_ascii_uppercase() || byte.is_ascii_digit() || byte == b'_'
+}
+
+/// Finds responses which look like they reject an upload, like `'OO_NAME'`,
+/// in output read from the device.
+///
+/// Unlike with `ResponseScanner`, the responses are not known in advance,
+/// so one only ends at a byte which cannot be part of it, or at the end of
+/// the output. It has to start the output or follow a byte which cannot be
+/// part of a word, so logs like `'FOO_BAR'` are not mistaken for one. The
+/// last one found is returned by `finish`, once the output has ended.
+#[derive(Debug, Clone)]
+pub(crate) struct RejectionScanner {
+    /// The rejection read so far, if the output since the last boundary
+    /// could be one.
+    bytes: [u8; MAX_REJECTION_LEN],
+    len: usize,
+    /// Whether the output since the last boundary cannot be a rejection.
+    skipping: bool,
+    /// The last rejection which ended.
+    last: Option<Rejection>,
+}
+
+impl RejectionScanner {
+    /// Creates a scanner which has not read any output.
+    pub(crate) fn new() -> Self {
+        Self {
+            bytes: [0; MAX_REJECTION_LEN],
+            len: 0,
+            skipping: false,
+            last: None,
+        }
+    }
+
+    /// Scans the next chunk of output.
+    pub(crate) fn feed(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if !byte.is_ascii_alphanumeric() && byte != b'_' {
+                self.end_word();
+                continue;
+            }
+
+            let fits = self.len < MAX_REJECTION_LEN && is_token_byte(byte);
+            let prefixed = self.len >= PREFIX.len() || PREFIX[self.len] == byte;
+            if self.skipping || !fits || !prefixed {
+                self.skipping = true;
+                self.len = 0;
+                continue;
+            }
+
+            self.bytes[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    /// Returns the last rejection in the output, once it has ended.
+    pub(crate) fn finish(&mut self) -> Option<Rejection> {
+        self.end_word();
+        self.last
+    }
+
+    /// Records the rejection read since the last boundary, if any, and starts
+    /// again as if at the start of the output.
+    fn end_word(&mut self) {
+        let len = core::mem::take(&mut self.len);
+        self.skipping = false;
+        if len <= PREFIX.len() {
+            return;
+        }
+
+        // Bytes left from a longer candidate are cleared, so equal
+        // rejections compare equal.
+        let mut bytes = [0; MAX_REJECTION_LEN];
+        bytes[..len].copy_from_slice(&self.bytes[..len]);
+        self.last = Some(Rejection { bytes, len });
+    }
+}
@@ -0,0 +1,95 @@
+/// The longest token a `ResponseScanner` can find, in bytes.
+pub const MAX_TOKEN_LEN: usize = 64;
+
+/// Finds response tokens, like `'ALL_GOOD'`, in output read from the device.
+///
+/// Output is passed to `feed` as it is read, in chunks of any size. Tokens
+/// split across chunks are found, as are tokens surrounded by other output,
+/// like logs from the running game.
+///
+/// Each token is given with a value, which is returned when it is found. The
+/// most recent output is kept in a ring buffer as long as the longest token,
+/// so scanning takes constant memory no matter how much output is read.
+/// ```
+/// use spade_serial::ResponseScanner;
+///
+/// let mut scanner = ResponseScanner::new(&[(b"YES", true), (b"NO", false)]);
+/// assert_eq!(scanner.feed(b"log output\nY"), None);
+/// assert_eq!(scanner.feed(b"ES\nmore"), Some((true, 2)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ResponseScanner<'t, T> {
+    tokens: &'t [(&'t [u8], T)],
+    window: [u8; MAX_TOKEN_LEN],
+    /// How much of `window` is used, which is the length of the longest
+    /// token.
+    capacity: usize,
+    /// The index in `window` where the next byte is stored.
+    head: usize,
+    /// How many bytes in `window` were read since the last token or reset.
+    len: usize,
+}
+
+impl<'t, T: Clone> ResponseScanner<'t, T> {
+    /// Creates a scanner for `tokens`. If one token ends with another, like
+    /// `'OO_FLASH'` and `'FLASH'`, the one listed first is found.
+    ///
+    /// ### Panics
+    /// Panics if a token is empty or longer than `MAX_TOKEN_LEN`.
+    pub fn new(tokens: &'t [(&'t [u8], T)]) -> Self {
+        assert!(
+            tokens
+                .iter()
+                .all(|(token, _)| !token.is_empty() && token.len() <= MAX_TOKEN_LEN),
+            "response tokens must be between 1 and {} bytes",
+            MAX_TOKEN_LEN
+        );
+
+        Self {
+            tokens,
+            window: [0; MAX_TOKEN_LEN],
+            capacity: tokens
+                .iter()
+                .map(|(token, _)| token.len())
+                .max()
+                .unwrap_or(1),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Scans the next chunk of output for a token.
+    ///
+    /// Returns the value of the first token found and the number of bytes of
+    /// `bytes` up to the end of it, or `None` if no token ended in `bytes`.
+    /// Output after the token is not scanned; pass it to `feed` again to find
+    /// the next token.
+    pub fn feed(&mut self, bytes: &[u8]) -> Option<(T, usize)> {
+        for (index, &byte) in bytes.iter().enumerate() {
+            self.window[self.head] = byte;
+            self.head = (self.head + 1) % self.capacity;
+            self.len = self.capacity.min(self.len + 1);
+
+            if let Some((_, value)) = self.tokens.iter().find(|(token, _)| self.ends_with(token)) {
+                self.len = 0;
+                return Some((value.clone(), index + 1));
+            }
+        }
+
+        None
+    }
+
+    /// Returns `true` if the most recent bytes in the window are `token`.
+    fn ends_with(&self, token: &[u8]) -> bool {
+        token.len() <= self.len
+            && token.iter().rev().enumerate().all(|(age, &byte)| {
+                self.window[(self.head + self.capacity - 1 - age) % self.capacity] == byte
+            })
+    }
+
+    /// Forgets any output passed to `feed`, so a token which started in it
+    /// will not be found.
+    pub fn reset(&mut self) {
+        self.len = 0;
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::{
+    is_running_legacy, probe, upload, upload_named, Game, ProbeResult, SpadeTransport, UploadError,
+    UploadResult,
+};
+
+/// A device which can be shared between threads.
+///
+/// The functions in this crate take the transport by mutable reference, so
+/// only one command can use it at a time. `SyncSpadeDevice` holds the
+/// transport behind a lock, so commands from different threads, like the
+/// requests of a server, are run one after another instead of interleaving
+/// their bytes. Share it with an `Arc` or a scoped thread.
+/// ```
+/// # use spade_serial::SyncSpadeDevice;
+/// # let port = std::io::Cursor::new(Vec::new());
+/// let device = SyncSpadeDevice::new(port);
+/// std::thread::scope(|scope| {
+///     scope.spawn(|| device.upload_game("first", "console.log(1)"));
+///     scope.spawn(|| device.upload_game("second", "console.log(2)"));
+/// });
+/// ```
+///
+/// If a thread panics while running a command, the lock is not poisoned for
+/// the others, but the device may have been left partway through it.
+#[derive(Debug, Default)]
+pub struct SyncSpadeDevice<T> {
+    io: Mutex<T>,
+}
+
+impl<T: SpadeTransport> SyncSpadeDevice<T> {
+    /// Wraps `io` so it can be shared between threads.
+    pub fn new(io: T) -> Self {
+        Self { io: Mutex::new(io) }
+    }
+
+    /// Runs `command` with the transport, waiting for any other command to
+    /// finish first.
+    ///
+    /// This can run any function of this crate, or several of them without
+    /// another thread's command in between.
+    pub fn with<R>(&self, command: impl FnOnce(&mut T) -> R) -> R {
+        command(&mut self.lock())
+    }
+
+    /// Checks if the device is running a legacy Spade version, like
+    /// `spade_serial::is_running_legacy`.
+    ///
+    /// ### Errors
+    /// Returns the same errors as `spade_serial::is_running_legacy`.
+    pub fn is_running_legacy(&self) -> Result<bool, std::io::Error> {
+        self.with(is_running_legacy)
+    }
+
+    /// Checks what the device is running, like `spade_serial::probe`.
+    ///
+    /// ### Errors
+    /// Returns the same errors as `spade_serial::probe`.
+    pub fn probe(&self) -> Result<ProbeResult, std::io::Error> {
+        self.with(probe)
+    }
+
+    /// Uploads a game to the device, like `spade_serial::upload_game`.
+    ///
+    /// ### Errors
+    /// Returns the same errors as `spade_serial::upload_game`.
+    pub fn upload_game(
+        &self,
+        name: &str,
+        game: impl AsRef<[u8]>,
+    ) -> Result<UploadResult, UploadError> {
+        self.with(|io| upload_named(io, name, game.as_ref(), None))
+    }
+
+    /// Uploads a `Game` to the device, like `spade_serial::upload`.
+    ///
+    /// ### Errors
+    /// Returns the same errors as `spade_serial::upload`.
+    pub fn upload(&self, game: &Game) -> Result<UploadResult, UploadError> {
+        self.with(|io| upload(io, game))
+    }
+
+    /// Returns the transport, once it is no longer shared.
+    pub fn into_inner(self) -> T {
+        self.io.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, T> {
+        self.io.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
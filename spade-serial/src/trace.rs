@@ -0,0 +1,27 @@
+//! Instrumentation with [tracing](https://crates.io/crates/tracing), which
+//! compiles to nothing without the `tracing` feature.
+
+/// Emits a `tracing` event at the given level, like
+/// `event!(debug, bytes = 5, "wrote header")`.
+#[allow(unused_macros)]
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+    }};
+}
+
+/// Enters a `tracing` span at the given level until the end of the enclosing
+/// scope, like `span!(info_span, "upload_game", name)`.
+#[cfg(feature = "std")]
+macro_rules! span {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::$level!($($arg)+).entered();
+    };
+}
+
+#[allow(unused_imports)]
+pub(crate) use event;
+#[cfg(feature = "std")]
+pub(crate) use span;
@@ -0,0 +1,202 @@
+//! Transports used to communicate with the device.
+//!
+//! Functions in this crate accept any `SpadeTransport`. It is implemented for
+//! every `Read + Write`r, so serial ports from the
+//! [serialport](https://crates.io/crates/serialport) crate can be used
+//! directly. Their read timeout can only be changed through the transport
+//! once they are wrapped in `Serial`, with the `serialport` feature. Other
+//! transports can implement it themselves.
+//!
+//! This module also provides transports for devices which are not connected
+//! directly, like `Rfc2217` for serial ports shared over the network, and
+//! `ProtocolRecorder` and `Replay` to record communication with a device and
+//! play it back later. `Reconnecting` wraps another transport to reopen it
+//! when the device resets, and `Demux` wraps another transport to separate
+//! the output of the running game from the responses of the device.
+
+mod demux;
+mod reconnect;
+mod record;
+mod rfc2217;
+#[cfg(feature = "serialport")]
+mod serial;
+
+pub use demux::Demux;
+pub use reconnect::{ReconnectPolicy, Reconnecting};
+pub use record::{
+    load_trace, read_trace, write_transcript, Direction, ProtocolRecorder, RecordedEvent, Replay,
+};
+pub use rfc2217::Rfc2217;
+#[cfg(feature = "serialport")]
+pub use serial::Serial;
+
+use std::io::{ErrorKind, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A connection to a device running Spade.
+///
+/// This mirrors the methods of `Read` and `Write` which this crate uses, plus
+/// control over the read timeout. Implementations for types which are not
+/// `Read + Write` only need to provide `read`, `write`, and `flush`.
+///
+/// Because the method names are the same, calling them on a `Read + Write`r
+/// while this trait is in scope requires naming the trait, like
+/// `Read::read(&mut port, &mut buf)`.
+pub trait SpadeTransport {
+    /// Reads bytes from the device into `buf`, returning how many were read.
+    ///
+    /// Like `Read::read`, returning `Ok(0)` means no more bytes can be read.
+    /// Transports with a timeout should return an error of
+    /// `ErrorKind::TimedOut` if no bytes were received in time.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error>;
+
+    /// Writes bytes from `buf` to the device, returning how many were
+    /// written.
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error>;
+
+    /// Waits until all written bytes were sent to the device.
+    fn flush(&mut self) -> Result<(), std::io::Error>;
+
+    /// Sets how long a read waits for bytes before timing out.
+    ///
+    /// ### Errors
+    /// The default implementation returns an error of `ErrorKind::Unsupported`.
+    /// So does the implementation for every type which is `Read + Write`,
+    /// including serialport's `Box<dyn SerialPort>`; wrap it in
+    /// `transport::Serial` for its timeout to be set.
+    fn set_timeout(&mut self, _timeout: Duration) -> Result<(), std::io::Error> {
+        Err(std::io::Error::from(ErrorKind::Unsupported))
+    }
+
+    /// Returns how long a read waits for bytes before timing out, or `None`
+    /// if it is not known.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Writes all of `buf` to the device, calling `write` until every byte was
+    /// written.
+    ///
+    /// Writes which only accept part of `buf` are continued from where they
+    /// stopped. While `write` makes no progress, by returning `Ok(0)` or an
+    /// error of `ErrorKind::WouldBlock`, it is tried again for up to the
+    /// transport's timeout, or `DEFAULT_STALL_TIMEOUT` if it is not known.
+    ///
+    /// ### Errors
+    /// Returns the first error from `write` other than `ErrorKind::Interrupted`
+    /// and `ErrorKind::WouldBlock`, or an error of `ErrorKind::WriteZero` if
+    /// no progress was made in time.
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<(), std::io::Error> {
+        let stall_timeout = self.timeout().unwrap_or(DEFAULT_STALL_TIMEOUT);
+        let mut last_progress = Instant::now();
+
+        while !buf.is_empty() {
+            match self.write(buf) {
+                Ok(0) => {}
+                Ok(bytes) => {
+                    buf = &buf[bytes..];
+                    last_progress = Instant::now();
+                    continue;
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err),
+            }
+
+            if last_progress.elapsed() >= stall_timeout {
+                return Err(std::io::Error::new(
+                    ErrorKind::WriteZero,
+                    "no bytes could be written to the device",
+                ));
+            }
+            thread::sleep(STALL_POLL_INTERVAL);
+        }
+
+        Ok(())
+    }
+}
+
+/// How long `SpadeTransport::write_all` waits for a write to make progress
+/// on transports which do not know their timeout.
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long `SpadeTransport::write_all` waits before trying a write which
+/// made no progress again.
+const STALL_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The size of a USB packet to the device, which `PacketWriter` buffers
+/// writes to.
+const USB_PACKET_SIZE: usize = 64;
+
+/// Combines small writes to a transport into full USB packets, without
+/// allocating. Writes of at least a packet, like the upload header, are
+/// passed through as they are.
+pub(crate) struct PacketWriter<'a, T: ?Sized> {
+    io: &'a mut T,
+    buf: [u8; USB_PACKET_SIZE],
+    len: usize,
+}
+
+impl<'a, T: SpadeTransport + ?Sized> PacketWriter<'a, T> {
+    pub(crate) fn new(io: &'a mut T) -> Self {
+        Self {
+            io,
+            buf: [0; USB_PACKET_SIZE],
+            len: 0,
+        }
+    }
+
+    pub(crate) fn write_all(&mut self, mut bytes: &[u8]) -> Result<(), std::io::Error> {
+        if self.len == 0 && bytes.len() >= USB_PACKET_SIZE {
+            return self.io.write_all(bytes);
+        }
+
+        while !bytes.is_empty() {
+            let copied = bytes.len().min(USB_PACKET_SIZE - self.len);
+            self.buf[self.len..][..copied].copy_from_slice(&bytes[..copied]);
+            self.len += copied;
+            bytes = &bytes[copied..];
+
+            if self.len == USB_PACKET_SIZE {
+                self.write_buffered()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes any buffered bytes and flushes the transport.
+    pub(crate) fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.write_buffered()?;
+        self.io.flush()
+    }
+
+    /// Returns the transport, discarding any buffered bytes.
+    pub(crate) fn into_inner(self) -> &'a mut T {
+        self.io
+    }
+
+    fn write_buffered(&mut self) -> Result<(), std::io::Error> {
+        if self.len > 0 {
+            self.io.write_all(&self.buf[..self.len])?;
+            self.len = 0;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Read + Write + ?Sized> SpadeTransport for T {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        Read::read(self, buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        Write::write(self, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        Write::flush(self)
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use super::SpadeTransport;
+use crate::protocol::{STARTUP_RESPONSES, UPLOAD_RESPONSES};
+
+/// Returns the responses which `Demux` passes on to commands: those in every
+/// table of responses in `spade_serial::protocol`.
+fn tokens() -> impl Iterator<Item = &'static [u8]> {
+    let upload = UPLOAD_RESPONSES.iter().map(|(token, _)| *token);
+    let startup = STARTUP_RESPONSES.iter().map(|(token, _)| *token);
+
+    upload.chain(startup)
+}
+
+/// A transport which separates the responses of the device from the output
+/// of the running game.
+///
+/// Spade prints the output of `console.log` to the same stream as its
+/// responses to commands. Reading from a `Demux` only returns the responses
+/// it recognizes, like `'ALL_GOOD'` or `'found startup seq!'`; everything else
+/// is passed to `on_log` as it is read. This lets commands which read a fixed
+/// number of bytes, like `spade_serial::is_running_legacy`, work while a game
+/// is printing.
+/// ```no_run
+/// use spade_serial::transport::Demux;
+///
+/// # let port = spade_serial::transport::Replay::new(Vec::new());
+/// let mut demux = Demux::new(port, |log: &[u8]| {
+///     print!("{}", String::from_utf8_lossy(log));
+/// });
+/// let legacy = spade_serial::is_running_legacy(&mut demux)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+/// Output which could be the start of a response is held back until it is
+/// known not to be one, so it may be passed to `on_log` a read later. Output
+/// which contains a response, like a game printing `'ALL_GOOD'`, is
+/// indistinguishable from it.
+pub struct Demux<T, F> {
+    inner: T,
+    on_log: F,
+    /// Output which is the start of a response.
+    pending: Vec<u8>,
+    /// Responses which were read but not yet returned.
+    ready: VecDeque<u8>,
+}
+
+impl<T: SpadeTransport, F: FnMut(&[u8])> Demux<T, F> {
+    /// Separates the output of `inner`, passing everything other than
+    /// responses to `on_log`.
+    pub fn new(inner: T, on_log: F) -> Self {
+        Self {
+            inner,
+            on_log,
+            pending: Vec::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Returns a reference to the transport being read.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the transport being read. Any output which was read but not
+    /// yet returned or passed to `on_log` is lost.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Sorts output read from the device into responses and log output.
+    fn route(&mut self, bytes: &[u8]) {
+        let mut log = Vec::new();
+
+        for &byte in bytes {
+            self.pending.push(byte);
+
+            // Keep the longest end of the pending output which could still
+            // become a response.
+            let start = (0..=self.pending.len())
+                .find(|&start| tokens().any(|token| token.starts_with(&self.pending[start..])))
+                .unwrap_or(self.pending.len());
+            log.extend(self.pending.drain(..start));
+
+            if tokens().any(|token| token == self.pending) {
+                self.ready.extend(self.pending.drain(..));
+            }
+        }
+
+        if !log.is_empty() {
+            (self.on_log)(&log);
+        }
+    }
+}
+
+impl<T: SpadeTransport, F: FnMut(&[u8])> SpadeTransport for Demux<T, F> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        loop {
+            if !self.ready.is_empty() {
+                let bytes = buf.len().min(self.ready.len());
+                for (byte, ready) in buf.iter_mut().zip(self.ready.drain(..bytes)) {
+                    *byte = ready;
+                }
+
+                return Ok(bytes);
+            }
+
+            let mut chunk = [0; 64];
+            match self.inner.read(&mut chunk)? {
+                0 => {
+                    if !self.pending.is_empty() {
+                        (self.on_log)(&self.pending);
+                        self.pending.clear();
+                    }
+
+                    return Ok(0);
+                }
+                bytes => self.route(&chunk[..bytes]),
+            }
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.inner.flush()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), std::io::Error> {
+        self.inner.set_timeout(timeout)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.inner.timeout()
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), std::io::Error> {
+        self.inner.write_all(buf)
+    }
+}
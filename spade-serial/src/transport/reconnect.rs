@@ -0,0 +1,228 @@
+use std::io::ErrorKind;
+use std::time::Duration;
+
+#[cfg(feature = "serialport")]
+use serialport::SerialPort;
+
+use crate::trace::event;
+use crate::SpadeTransport;
+
+/// What `Reconnecting::run` does when the device reconnects during a command.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReconnectPolicy {
+    /// Return the command's error.
+    #[default]
+    Fail,
+    /// Run the command again from the start, up to `attempts` more times.
+    ///
+    /// The device forgets a command when it resets, so it cannot be resumed
+    /// where it stopped. Only use this for commands which are safe to repeat,
+    /// like uploading a game.
+    Restart {
+        /// The number of times the command is run again.
+        attempts: u32,
+    },
+}
+
+/// A transport which reopens the connection when the device disappears, such
+/// as when it resets and re-enumerates over USB.
+///
+/// When reading, writing, or flushing fails because the device is gone, with
+/// an error of `ErrorKind::BrokenPipe`, `ErrorKind::NotFound`, or
+/// `ErrorKind::NotConnected`, the connection is reopened and the operation
+/// returns an error of `ErrorKind::ConnectionReset`. The command which was
+/// running is lost, so `Reconnecting::run` can restart it.
+/// ```no_run
+/// use spade_serial::transport::{ReconnectPolicy, Reconnecting};
+///
+/// let open = || Ok(serialport::new("/dev/ttyACM0", 115200).open()?);
+/// let mut port = Reconnecting::new(open()?, open);
+/// let result = port.run(ReconnectPolicy::Restart { attempts: 2 }, |port| {
+///     spade_serial::upload_game(port, "game", "console.log(1)")
+/// });
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// With the `serialport` feature, `Reconnecting::open` reopens serial ports
+/// which come back under a different path, and
+/// `Reconnecting::by_serial_number` follows a device by its USB serial
+/// number.
+pub struct Reconnecting<T> {
+    io: T,
+    reopen: Box<dyn FnMut() -> Result<T, std::io::Error> + Send>,
+    reconnected: bool,
+}
+
+impl<T: SpadeTransport> Reconnecting<T> {
+    /// Wraps `io`, calling `reopen` to get a new connection when the device
+    /// disappears.
+    ///
+    /// The read timeout of `io` is applied to each new connection.
+    pub fn new(io: T, reopen: impl FnMut() -> Result<T, std::io::Error> + Send + 'static) -> Self {
+        Self {
+            io,
+            reopen: Box::new(reopen),
+            reconnected: false,
+        }
+    }
+
+    /// Runs `command`, and runs it again according to `policy` if it fails
+    /// after the device reconnected.
+    ///
+    /// ### Errors
+    /// Returns the error of the last time `command` was run.
+    pub fn run<R, E>(
+        &mut self,
+        policy: ReconnectPolicy,
+        mut command: impl FnMut(&mut Self) -> Result<R, E>,
+    ) -> Result<R, E> {
+        let mut attempts = match policy {
+            ReconnectPolicy::Fail => 0,
+            ReconnectPolicy::Restart { attempts } => attempts,
+        };
+
+        loop {
+            self.reconnected = false;
+            match command(self) {
+                Err(_) if self.reconnected && attempts > 0 => {
+                    event!(info, attempts, "restarting command after reconnecting");
+                    attempts -= 1;
+                }
+                result => break result,
+            }
+        }
+    }
+
+    /// Returns a reference to the current connection.
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    /// Returns the current connection.
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+
+    /// Reopens the connection if `result` failed because the device is gone.
+    fn check<V>(&mut self, result: Result<V, std::io::Error>) -> Result<V, std::io::Error> {
+        match result {
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    ErrorKind::BrokenPipe | ErrorKind::NotFound | ErrorKind::NotConnected
+                ) =>
+            {
+                event!(warn, %error, "device disconnected, reopening");
+                let mut io = (self.reopen)().map_err(|_| error)?;
+                if let Some(timeout) = self.io.timeout() {
+                    io.set_timeout(timeout)?;
+                }
+
+                self.io = io;
+                self.reconnected = true;
+                Err(std::io::Error::new(
+                    ErrorKind::ConnectionReset,
+                    "device reconnected",
+                ))
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(feature = "serialport")]
+impl Reconnecting<Box<dyn SerialPort>> {
+    /// Opens the serial port at `path` with `spade_serial::open`, reopening
+    /// it when the device disappears.
+    ///
+    /// When reconnecting, up to `wait` is spent waiting for a device with
+    /// Spade's USB vendor and product ID to appear, since some operating
+    /// systems give it a new path. `path` is used if it appears again;
+    /// otherwise the first device found is.
+    ///
+    /// Available with the `serialport` feature.
+    ///
+    /// ### Errors
+    /// Returns any errors from opening the port with `serialport`.
+    pub fn open(path: &str, wait: Duration) -> Result<Self, serialport::Error> {
+        let port = crate::open(path)?;
+        let path = path.to_owned();
+
+        Ok(Self::new(port, move || {
+            let ports = crate::port::wait_for_ports(wait)?;
+            let port = ports
+                .iter()
+                .find(|port| port.port_name == path)
+                .or(ports.first())
+                .ok_or(ErrorKind::NotFound)?;
+            Ok(crate::open(&port.port_name)?)
+        }))
+    }
+
+    /// Opens the serial port of the USB device with the serial number
+    /// `serial_number` with `spade_serial::open`, reopening it when the
+    /// device disappears.
+    ///
+    /// The serial port is looked up again each time, so the device is found
+    /// even if it comes back under a different path, and another device
+    /// which gets its old path is not mistaken for it. Up to `wait` is spent
+    /// waiting for it to appear, both when opening and when reconnecting.
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use spade_serial::transport::Reconnecting;
+    ///
+    /// let mut port = Reconnecting::by_serial_number("E6612483CB1B242A", Duration::from_secs(5))?;
+    /// # Ok::<(), serialport::Error>(())
+    /// ```
+    ///
+    /// Available with the `serialport` feature.
+    ///
+    /// ### Errors
+    /// Returns an error of `serialport::ErrorKind::NoDevice` if the device
+    /// was not connected in time, or any errors from listing serial ports or
+    /// opening the port with `serialport`.
+    pub fn by_serial_number(
+        serial_number: &str,
+        wait: Duration,
+    ) -> Result<Self, serialport::Error> {
+        let find = move |serial_number: &str| {
+            crate::port::wait_for_serial_number(serial_number, wait)?.ok_or_else(|| {
+                serialport::Error::new(
+                    serialport::ErrorKind::NoDevice,
+                    "no device with the serial number is connected",
+                )
+            })
+        };
+        let port = crate::open(&find(serial_number)?.port_name)?;
+        let serial_number = serial_number.to_owned();
+
+        Ok(Self::new(port, move || {
+            Ok(crate::open(&find(&serial_number)?.port_name)?)
+        }))
+    }
+}
+
+impl<T: SpadeTransport> SpadeTransport for Reconnecting<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        let result = self.io.read(buf);
+        self.check(result)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        let result = self.io.write(buf);
+        self.check(result)
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        let result = self.io.flush();
+        self.check(result)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), std::io::Error> {
+        self.io.set_timeout(timeout)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.io.timeout()
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::SpadeTransport;
+
+/// Which way bytes in a `RecordedEvent` were sent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Direction {
+    /// The bytes were written to the device.
+    Written,
+    /// The bytes were read from the device.
+    Read,
+}
+
+/// Bytes written to or read from the device by one call, recorded by
+/// `ProtocolRecorder`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecordedEvent {
+    /// The time since recording started.
+    pub elapsed: Duration,
+    /// Whether the bytes were written or read.
+    pub direction: Direction,
+    /// The bytes written or read.
+    pub bytes: Vec<u8>,
+}
+
+/// A transport which records every byte written to and read from another
+/// transport.
+///
+/// This is useful to reproduce problems with a device offline. Save the trace
+/// with `save`, then load it with `load_trace` and pass it to `Replay` to run
+/// the same command against the recorded output.
+/// ```no_run
+/// use spade_serial::transport::ProtocolRecorder;
+///
+/// # let port = spade_serial::transport::Replay::new(Vec::new());
+/// let mut recorder = ProtocolRecorder::new(port);
+/// let result = spade_serial::upload_game(
+///     &mut recorder,
+///     "game",
+///     "console.log('hi')",
+/// );
+/// recorder.save("upload.trace")?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct ProtocolRecorder<T> {
+    inner: T,
+    start: Instant,
+    events: Vec<RecordedEvent>,
+}
+
+impl<T: SpadeTransport> ProtocolRecorder<T> {
+    /// Starts recording everything sent through `inner`.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            start: Instant::now(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Returns the events recorded so far.
+    pub fn events(&self) -> &[RecordedEvent] {
+        &self.events
+    }
+
+    /// Returns the events recorded since this was last called, and forgets
+    /// them.
+    ///
+    /// This is useful to write events out as they are recorded during long
+    /// sessions, instead of keeping all of them. Events taken are no longer
+    /// returned by `events` or written by `write_trace`.
+    pub fn take_events(&mut self) -> Vec<RecordedEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Returns the transport being recorded and the events recorded.
+    pub fn into_parts(self) -> (T, Vec<RecordedEvent>) {
+        (self.inner, self.events)
+    }
+
+    /// Writes the events recorded so far to `out` in the format read by
+    /// `read_trace`.
+    ///
+    /// Each event is one line with the time since recording started in
+    /// microseconds, `W` for written or `R` for read, and the bytes in
+    /// hexadecimal, like `1520 R 414c4c5f474f4f44`.
+    ///
+    /// ### Errors
+    /// Returns any IO errors from writing to `out`.
+    pub fn write_trace(&self, out: &mut impl Write) -> Result<(), std::io::Error> {
+        for event in &self.events {
+            let mut hex = String::with_capacity(event.bytes.len() * 2);
+            for byte in &event.bytes {
+                let _ = write!(hex, "{:02x}", byte);
+            }
+
+            let direction = match event.direction {
+                Direction::Written => 'W',
+                Direction::Read => 'R',
+            };
+            writeln!(out, "{} {} {}", event.elapsed.as_micros(), direction, hex)?;
+        }
+
+        Ok(())
+    }
+
+    /// Saves the events recorded so far to the file at `path`, replacing it
+    /// if it exists. See `write_trace` for the format.
+    ///
+    /// ### Errors
+    /// Returns any IO errors from creating or writing to the file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), std::io::Error> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write_trace(&mut file)?;
+        file.flush()
+    }
+
+    fn record(&mut self, direction: Direction, bytes: &[u8]) {
+        if !bytes.is_empty() {
+            self.events.push(RecordedEvent {
+                elapsed: self.start.elapsed(),
+                direction,
+                bytes: bytes.to_vec(),
+            });
+        }
+    }
+}
+
+impl<T: SpadeTransport> SpadeTransport for ProtocolRecorder<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        let bytes = self.inner.read(buf)?;
+        self.record(Direction::Read, &buf[..bytes]);
+
+        Ok(bytes)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        let bytes = self.inner.write(buf)?;
+        self.record(Direction::Written, &buf[..bytes]);
+
+        Ok(bytes)
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.inner.flush()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), std::io::Error> {
+        self.inner.set_timeout(timeout)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.inner.timeout()
+    }
+}
+
+/// How many bytes are shown on each line of a transcript.
+const TRANSCRIPT_WIDTH: usize = 16;
+
+/// Writes `events` to `out` as a transcript for people to read, unlike
+/// `ProtocolRecorder::write_trace`.
+///
+/// Each event starts with the time since recording started in seconds, and
+/// `W` for written or `R` for read. Its bytes follow in hexadecimal and as
+/// ASCII, 16 to a line, like:
+/// ```text
+///     0.001520 R 41 4c 4c 5f 47 4f 4f 44 0d 0a                    ALL_GOOD..
+/// ```
+/// Bytes which are not printable ASCII are shown as `.`.
+///
+/// ### Errors
+/// Returns any IO errors from writing to `out`.
+pub fn write_transcript(
+    events: &[RecordedEvent],
+    out: &mut impl Write,
+) -> Result<(), std::io::Error> {
+    for event in events {
+        let direction = match event.direction {
+            Direction::Written => 'W',
+            Direction::Read => 'R',
+        };
+
+        for (index, chunk) in event.bytes.chunks(TRANSCRIPT_WIDTH).enumerate() {
+            let mut hex = String::with_capacity(TRANSCRIPT_WIDTH * 3);
+            for byte in chunk {
+                let _ = write!(hex, "{:02x} ", byte);
+            }
+            let ascii: String = chunk
+                .iter()
+                .map(|&byte| match byte {
+                    b' '..=b'~' => byte as char,
+                    _ => '.',
+                })
+                .collect();
+
+            match index {
+                0 => write!(out, "{:>12.6} {} ", event.elapsed.as_secs_f64(), direction)?,
+                _ => write!(out, "{:15}", "")?,
+            }
+            writeln!(out, "{:width$}{}", hex, ascii, width = TRANSCRIPT_WIDTH * 3)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a trace written by `ProtocolRecorder::write_trace`. Empty lines are
+/// skipped.
+///
+/// ### Errors
+/// Returns any IO errors from reading `input`, or an error of
+/// `ErrorKind::InvalidData` if a line is not a valid event.
+pub fn read_trace(input: impl BufRead) -> Result<Vec<RecordedEvent>, std::io::Error> {
+    let invalid = || std::io::Error::from(ErrorKind::InvalidData);
+    let mut events = Vec::new();
+
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let elapsed = fields
+            .next()
+            .and_then(|micros| micros.parse().ok())
+            .map(Duration::from_micros)
+            .ok_or_else(invalid)?;
+        let direction = match fields.next() {
+            Some("W") => Direction::Written,
+            Some("R") => Direction::Read,
+            _ => return Err(invalid()),
+        };
+        let hex = fields.next().ok_or_else(invalid)?;
+        if hex.len() % 2 != 0 {
+            return Err(invalid());
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+
+        events.push(RecordedEvent {
+            elapsed,
+            direction,
+            bytes,
+        });
+    }
+
+    Ok(events)
+}
+
+/// Loads a trace saved by `ProtocolRecorder::save`.
+///
+/// ### Errors
+/// Returns the same errors as `read_trace`, and any IO errors from opening
+/// the file.
+pub fn load_trace(path: impl AsRef<Path>) -> Result<Vec<RecordedEvent>, std::io::Error> {
+    read_trace(BufReader::new(File::open(path)?))
+}
+
+/// A transport which plays back the output of a recorded trace.
+///
+/// Reads return the bytes which were read in the trace, in order, and return
+/// `Ok(0)` once all of them were read. Writes are accepted and discarded.
+/// Running the same command on a `Replay` as the one recorded shows how this
+/// crate handles the device's output, without the device.
+pub struct Replay {
+    output: VecDeque<u8>,
+}
+
+impl Replay {
+    /// Creates a transport which plays back the `Direction::Read` events in
+    /// `events`.
+    pub fn new(events: Vec<RecordedEvent>) -> Self {
+        Self {
+            output: events
+                .into_iter()
+                .filter(|event| event.direction == Direction::Read)
+                .flat_map(|event| event.bytes)
+                .collect(),
+        }
+    }
+}
+
+impl SpadeTransport for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        let bytes = buf.len().min(self.output.len());
+        for (byte, output) in buf.iter_mut().zip(self.output.drain(..bytes)) {
+            *byte = output;
+        }
+
+        Ok(bytes)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        Ok(())
+    }
+}
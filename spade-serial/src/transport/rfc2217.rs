@@ -0,0 +1,218 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use super::SpadeTransport;
+
+/// Interpret as command.
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+/// Subnegotiation begin.
+const SB: u8 = 250;
+/// Subnegotiation end.
+const SE: u8 = 240;
+
+const OPTION_BINARY: u8 = 0;
+const OPTION_SUPPRESS_GO_AHEAD: u8 = 3;
+const OPTION_COM_PORT: u8 = 44;
+
+const COM_PORT_SET_BAUDRATE: u8 = 1;
+const COM_PORT_SET_DATASIZE: u8 = 2;
+const COM_PORT_SET_PARITY: u8 = 3;
+const COM_PORT_SET_STOPSIZE: u8 = 4;
+
+/// Where the reader is in the telnet stream, kept between reads so commands
+/// split across reads are handled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReadState {
+    Data,
+    Iac,
+    Negotiation(u8),
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+/// A serial port shared over the network with the telnet COM port control
+/// option, described in [RFC 2217](https://www.rfc-editor.org/rfc/rfc2217).
+///
+/// Servers like ser2net and `rfc2217_server.py` from pySerial expose serial
+/// ports this way, so a device attached to another computer can be used like
+/// a local serial port:
+/// ```no_run
+/// use spade_serial::transport::Rfc2217;
+///
+/// let mut port = Rfc2217::connect("raspberrypi.local:2217")?;
+/// let legacy = spade_serial::is_running_legacy(&mut port)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct Rfc2217 {
+    stream: TcpStream,
+    state: ReadState,
+    timeout: Option<Duration>,
+}
+
+impl Rfc2217 {
+    /// Connects to the server at `addr` and configures the serial port for
+    /// Spade, at 115200 baud with 8 data bits, no parity, and 1 stop bit.
+    ///
+    /// ### Errors
+    /// Returns any IO errors from connecting to or writing to the server.
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, std::io::Error> {
+        let mut port = Self {
+            stream: TcpStream::connect(addr)?,
+            state: ReadState::Data,
+            timeout: None,
+        };
+
+        Write::write_all(
+            &mut port.stream,
+            &[
+                IAC,
+                WILL,
+                OPTION_COM_PORT,
+                IAC,
+                WILL,
+                OPTION_BINARY,
+                IAC,
+                DO,
+                OPTION_BINARY,
+                IAC,
+                DO,
+                OPTION_SUPPRESS_GO_AHEAD,
+            ],
+        )?;
+        port.set_baud_rate(115200)?;
+        port.com_port_command(COM_PORT_SET_DATASIZE, &[8])?;
+        // 1 means no parity and 1 stop bit.
+        port.com_port_command(COM_PORT_SET_PARITY, &[1])?;
+        port.com_port_command(COM_PORT_SET_STOPSIZE, &[1])?;
+
+        Ok(port)
+    }
+
+    /// Asks the server to change the baud rate of the serial port.
+    ///
+    /// ### Errors
+    /// Returns any IO errors from writing to the server.
+    pub fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), std::io::Error> {
+        self.com_port_command(COM_PORT_SET_BAUDRATE, &baud_rate.to_be_bytes())
+    }
+
+    /// Sends a COM port control subnegotiation, escaping `IAC` in `value`.
+    fn com_port_command(&mut self, command: u8, value: &[u8]) -> Result<(), std::io::Error> {
+        let mut message = vec![IAC, SB, OPTION_COM_PORT, command];
+        escape_into(value, &mut message);
+        message.extend_from_slice(&[IAC, SE]);
+
+        Write::write_all(&mut self.stream, &message)
+    }
+
+    /// Answers a request from the server to enable or disable an option.
+    /// Only the options requested in `connect` are accepted.
+    fn negotiate(&mut self, command: u8, option: u8) -> Result<(), std::io::Error> {
+        let reply = match command {
+            DO if matches!(option, OPTION_BINARY | OPTION_COM_PORT) => return Ok(()),
+            WILL if matches!(option, OPTION_BINARY | OPTION_SUPPRESS_GO_AHEAD) => return Ok(()),
+            DO => WONT,
+            WILL => DONT,
+            // The server refused or disabled an option; nothing to answer.
+            _ => return Ok(()),
+        };
+
+        Write::write_all(&mut self.stream, &[IAC, reply, option])
+    }
+}
+
+/// Copies `data` to `out`, doubling any `IAC` bytes so they are not
+/// interpreted as commands.
+fn escape_into(data: &[u8], out: &mut Vec<u8>) {
+    for &byte in data {
+        if byte == IAC {
+            out.push(IAC);
+        }
+        out.push(byte);
+    }
+}
+
+impl SpadeTransport for Rfc2217 {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut received = vec![0; buf.len()];
+        loop {
+            let bytes = match Read::read(&mut self.stream, &mut received) {
+                Ok(0) => return Ok(0),
+                Ok(bytes) => bytes,
+                // Unix reports timeouts on sockets as WouldBlock.
+                Err(err) if err.kind() == ErrorKind::WouldBlock && self.timeout.is_some() => {
+                    return Err(std::io::Error::from(ErrorKind::TimedOut))
+                }
+                Err(err) => return Err(err),
+            };
+
+            let mut len = 0;
+            for &byte in &received[..bytes] {
+                self.state = match (self.state, byte) {
+                    (ReadState::Data, IAC) => ReadState::Iac,
+                    (ReadState::Data, _) => {
+                        buf[len] = byte;
+                        len += 1;
+                        ReadState::Data
+                    }
+                    (ReadState::Iac, IAC) => {
+                        buf[len] = IAC;
+                        len += 1;
+                        ReadState::Data
+                    }
+                    (ReadState::Iac, WILL | WONT | DO | DONT) => ReadState::Negotiation(byte),
+                    (ReadState::Iac, SB) => ReadState::Subnegotiation,
+                    (ReadState::Iac, _) => ReadState::Data,
+                    (ReadState::Negotiation(command), option) => {
+                        self.negotiate(command, option)?;
+                        ReadState::Data
+                    }
+                    // Notifications from the server, like line or modem state
+                    // changes, are not needed.
+                    (ReadState::Subnegotiation, IAC) => ReadState::SubnegotiationIac,
+                    (ReadState::Subnegotiation, _) => ReadState::Subnegotiation,
+                    (ReadState::SubnegotiationIac, SE) => ReadState::Data,
+                    (ReadState::SubnegotiationIac, _) => ReadState::Subnegotiation,
+                };
+            }
+
+            // Only return once data was received, since returning 0 would
+            // mean the connection was closed.
+            if len > 0 {
+                return Ok(len);
+            }
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        let mut escaped = Vec::with_capacity(buf.len());
+        escape_into(buf, &mut escaped);
+        Write::write_all(&mut self.stream, &escaped)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        Write::flush(&mut self.stream)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), std::io::Error> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        self.timeout = Some(timeout);
+
+        Ok(())
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
@@ -0,0 +1,81 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use serialport::SerialPort;
+
+use crate::SpadeTransport;
+
+/// A serial port from the [serialport](https://crates.io/crates/serialport)
+/// crate, whose read timeout can be changed through the transport.
+///
+/// `Box<dyn SerialPort>` can be used as a transport on its own, since it is
+/// `Read + Write`, but `SpadeTransport::set_timeout` is not supported on it.
+/// Wrapping it lets options like `UploadOptions::timeout` change the timeout
+/// of the port:
+/// ```no_run
+/// use std::time::Duration;
+/// use spade_serial::transport::Serial;
+/// use spade_serial::{Game, GameName, UploadOptions};
+///
+/// let mut port = Serial::new(spade_serial::open("/dev/ttyACM0")?);
+/// let game = Game::new(GameName::new("game").unwrap(), "console.log(1)").unwrap();
+/// let options = UploadOptions::new().timeout(Duration::from_secs(5));
+/// let result = spade_serial::upload_game_with(&mut port, &game, &options);
+/// # Ok::<(), serialport::Error>(())
+/// ```
+///
+/// Available with the `serialport` feature.
+pub struct Serial {
+    port: Box<dyn SerialPort>,
+}
+
+impl Serial {
+    /// Wraps `port`.
+    pub fn new(port: Box<dyn SerialPort>) -> Self {
+        Self { port }
+    }
+
+    /// Returns a reference to the serial port.
+    pub fn get_ref(&self) -> &dyn SerialPort {
+        &*self.port
+    }
+
+    /// Returns a mutable reference to the serial port, to change settings
+    /// like the baud rate.
+    pub fn get_mut(&mut self) -> &mut dyn SerialPort {
+        &mut *self.port
+    }
+
+    /// Returns the serial port.
+    pub fn into_inner(self) -> Box<dyn SerialPort> {
+        self.port
+    }
+}
+
+impl From<Box<dyn SerialPort>> for Serial {
+    fn from(port: Box<dyn SerialPort>) -> Self {
+        Self::new(port)
+    }
+}
+
+impl SpadeTransport for Serial {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        Read::read(&mut self.port, buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        Write::write(&mut self.port, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        Write::flush(&mut self.port)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), std::io::Error> {
+        Ok(SerialPort::set_timeout(&mut *self.port, timeout)?)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        Some(SerialPort::timeout(&*self.port))
+    }
+}
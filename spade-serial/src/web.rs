@@ -0,0 +1,104 @@
+//! Communication over the
+//! [Web Serial API](https://developer.mozilla.org/en-US/docs/Web/API/Web_Serial_API),
+//! for uploading games from a browser.
+//!
+//! These functions behave like the ones at the root of the crate, but are
+//! `async`, since the Web Serial API is built on promises. Compile for
+//! `wasm32-unknown-unknown` with
+//! [wasm-bindgen](https://crates.io/crates/wasm-bindgen), and set
+//! `RUSTFLAGS=--cfg=web_sys_unstable_apis`, as web-sys only provides the Web
+//! Serial API when it is set.
+//!
+//! The port has to be requested from the user and opened at 115200 baud
+//! first, which can only be done from JavaScript in response to a click or
+//! similar.
+
+use js_sys::{Reflect, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{ReadableStreamDefaultReader, SerialPort, WritableStreamDefaultWriter};
+
+use crate::{UploadError, UploadMachine, UploadResult};
+
+/// Uploads a game to a device running Spade over an open `port`.
+///
+/// See `spade_serial::upload_game`, which this behaves like, including
+/// taking the game as a string or bytes. Errors from the streams of `port`
+/// are returned as `UploadError::IOError`, including when the device is
+/// disconnected.
+///
+/// Reads over the Web Serial API do not time out, so this waits until the
+/// device responds or `port` is closed. The streams of `port` are locked
+/// while uploading, and unlocked afterwards.
+pub async fn upload_game(
+    port: &SerialPort,
+    name: &str,
+    game: &(impl AsRef<[u8]> + ?Sized),
+) -> Result<UploadResult, UploadError> {
+    let mut machine = UploadMachine::new(name, game)?;
+
+    let writer: WritableStreamDefaultWriter = port.writable().get_writer().map_err(io_error)?;
+    let written = write_upload(&writer, &mut machine).await;
+    writer.release_lock();
+    written?;
+
+    let reader: ReadableStreamDefaultReader = port.readable().get_reader().unchecked_into();
+    let result = read_response(&reader, &mut machine).await;
+    reader.release_lock();
+    result
+}
+
+/// Writes everything from `machine` to `writer`, waiting for each write to
+/// be sent.
+async fn write_upload(
+    writer: &WritableStreamDefaultWriter,
+    machine: &mut UploadMachine<'_>,
+) -> Result<(), UploadError> {
+    while let Some(bytes) = machine.next_write() {
+        // The bytes are copied, since the memory of the module may move
+        // before the write is done with them.
+        let chunk = Uint8Array::from(bytes);
+        JsFuture::from(writer.write_with_chunk(&chunk))
+            .await
+            .map_err(io_error)?;
+    }
+
+    Ok(())
+}
+
+/// Passes the output read from `reader` to `machine` until it finds the
+/// response, or the stream ends.
+async fn read_response(
+    reader: &ReadableStreamDefaultReader,
+    machine: &mut UploadMachine<'_>,
+) -> Result<UploadResult, UploadError> {
+    let mut buf = [0; 64];
+    loop {
+        let read = JsFuture::from(reader.read()).await.map_err(io_error)?;
+        let done = Reflect::get(&read, &JsValue::from_str("done")).map_err(io_error)?;
+        if done.is_truthy() {
+            break machine.finish_read().ok_or(UploadError::NoResponse);
+        }
+
+        let value: Uint8Array = Reflect::get(&read, &JsValue::from_str("value"))
+            .map_err(io_error)?
+            .unchecked_into();
+
+        // Chunks can be any size, so they are copied out a piece at a time.
+        let mut start = 0;
+        while start < value.length() {
+            let end = value.length().min(start + buf.len() as u32);
+            let len = (end - start) as usize;
+            value.subarray(start, end).copy_to(&mut buf[..len]);
+            if let Some(result) = machine.feed_read(&buf[..len]) {
+                return Ok(result);
+            }
+            start = end;
+        }
+    }
+}
+
+/// Converts an error from the Web Serial API.
+fn io_error(_error: JsValue) -> UploadError {
+    UploadError::IOError
+}
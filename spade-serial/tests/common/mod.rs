@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use spade_serial::transport::Rfc2217;
+use spade_serial::SpadeTransport;
+
+/// Opens the device in the `TEST_DEVICE` environment variable. Devices shared
+/// over the network, like by the simulator example, are given as
+/// `rfc2217://host:port`.
+pub fn open_test_device() -> Box<dyn SpadeTransport> {
+    let device = std::env::var("TEST_DEVICE").unwrap();
+
+    match device.strip_prefix("rfc2217://") {
+        Some(addr) => {
+            let mut port = Rfc2217::connect(addr).unwrap();
+            port.set_timeout(Duration::from_millis(1000)).unwrap();
+            Box::new(port)
+        }
+        None => Box::new(
+            serialport::new(device, 115200)
+                .timeout(Duration::from_millis(1000))
+                .open()
+                .unwrap(),
+        ),
+    }
+}
@@ -1,4 +1,4 @@
-use std::time::Duration;
+mod common;
 
 use serial_test::serial;
 use spade_serial::is_running_legacy;
@@ -7,12 +7,7 @@ use spade_serial::is_running_legacy;
 #[test]
 #[serial]
 fn test_legacy() {
-    let device = std::env::var("TEST_DEVICE").unwrap();
+    let mut port = common::open_test_device();
 
-    let mut port = serialport::new(device, 115200)
-        .timeout(Duration::from_millis(1000))
-        .open()
-        .unwrap();
-
-    assert!(!is_running_legacy(&mut port).unwrap());
+    assert!(!is_running_legacy(&mut *port).unwrap());
 }
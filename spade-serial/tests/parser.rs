@@ -0,0 +1,162 @@
+use proptest::collection::vec;
+use proptest::prelude::*;
+use spade_serial::protocol::{
+    ALL_GOOD, HEADER_LEN, LENGTH_OFFSET, NAME_OFFSET, OO_FLASH, OO_METADATA, UPLOAD_COMMAND,
+};
+use spade_serial::{ResponseScanner, UploadMachine, UploadResult};
+
+/// The tokens the scanner is tested with, including one which ends with
+/// another.
+const TOKENS: [(&[u8], u8); 4] = [
+    (ALL_GOOD, 0),
+    (OO_FLASH, 1),
+    (OO_METADATA, 2),
+    (b"FLASH", 3),
+];
+
+/// The responses which end an upload.
+const RESPONSES: [(&[u8], UploadResult); 3] = [
+    (ALL_GOOD, UploadResult::AllGood),
+    (OO_FLASH, UploadResult::OutOfFlash),
+    (OO_METADATA, UploadResult::OutOfMetadata),
+];
+
+/// Finds every token in `output` the slow way: after each byte, check whether
+/// the output since the last token ends with one. Returns the value of each
+/// token and the offset of its end.
+fn expected_tokens<T: Clone>(tokens: &[(&[u8], T)], output: &[u8]) -> Vec<(T, usize)> {
+    let mut found = Vec::new();
+    let mut start = 0;
+    for end in 1..=output.len() {
+        if let Some((_, value)) = tokens
+            .iter()
+            .find(|(token, _)| output[start..end].ends_with(token))
+        {
+            found.push((value.clone(), end));
+            start = end;
+        }
+    }
+
+    found
+}
+
+/// Splits `output` into chunks with the lengths in `splits`, repeating them
+/// as needed.
+fn chunks<'a>(output: &'a [u8], splits: &[usize]) -> Vec<&'a [u8]> {
+    let mut chunks = Vec::new();
+    let mut rest = output;
+    for &split in splits.iter().cycle() {
+        if rest.is_empty() {
+            break;
+        }
+        let (chunk, after) = rest.split_at(split.min(rest.len()));
+        chunks.push(chunk);
+        rest = after;
+    }
+
+    chunks
+}
+
+/// Feeds `output` to a scanner in chunks, returning the tokens found and
+/// their end offsets in `output`.
+fn scanned_tokens(output: &[u8], splits: &[usize]) -> Vec<(u8, usize)> {
+    let mut scanner = ResponseScanner::new(&TOKENS);
+    let mut found = Vec::new();
+    let mut offset = 0;
+    for mut chunk in chunks(output, splits) {
+        while let Some((value, consumed)) = scanner.feed(chunk) {
+            assert!(consumed > 0 && consumed <= chunk.len());
+            offset += consumed;
+            found.push((value, offset));
+            chunk = &chunk[consumed..];
+        }
+        offset += chunk.len();
+    }
+
+    found
+}
+
+/// Checks that the writes of an upload are the header followed by the game,
+/// and that writing ends.
+fn check_writes(name: &str, game: &str, chunk_size: usize) {
+    let mut machine = UploadMachine::new(name, game)
+        .unwrap()
+        .with_chunk_size(chunk_size);
+    let mut written = Vec::new();
+    let mut writes = 0;
+    while let Some(bytes) = machine.next_write() {
+        assert!(!bytes.is_empty());
+        written.extend_from_slice(bytes);
+        writes += 1;
+        assert!(writes <= game.len() / chunk_size + 2, "too many writes");
+    }
+
+    assert_eq!(written.len(), HEADER_LEN + game.len());
+    assert!(written.starts_with(UPLOAD_COMMAND));
+    assert!(written[NAME_OFFSET..].starts_with(name.as_bytes()));
+    assert_eq!(
+        written[LENGTH_OFFSET..HEADER_LEN],
+        (game.len() as u32).to_le_bytes()
+    );
+    assert_eq!(&written[HEADER_LEN..], game.as_bytes());
+    assert!(machine.is_waiting());
+}
+
+proptest! {
+    #[test]
+    fn scanner_finds_tokens_however_output_is_split(
+        pieces in vec(
+            prop_oneof![
+                vec(any::<u8>(), 0..16),
+                prop::sample::select(TOKENS.map(|(token, _)| token.to_vec()).to_vec()),
+            ],
+            0..16,
+        ),
+        splits in vec(1usize..32, 1..8),
+    ) {
+        let output = pieces.concat();
+        prop_assert_eq!(scanned_tokens(&output, &splits), expected_tokens(&TOKENS, &output));
+    }
+
+    #[test]
+    fn scanner_handles_arbitrary_output(
+        output in vec(any::<u8>(), 0..1024),
+        splits in vec(1usize..128, 1..8),
+    ) {
+        prop_assert_eq!(scanned_tokens(&output, &splits), expected_tokens(&TOKENS, &output));
+    }
+
+    #[test]
+    fn machine_writes_header_then_game(
+        // At most 4 bytes a character, so at most 100 bytes.
+        name in "[^\\x00]{0,25}",
+        game in ".{0,300}",
+        chunk_size in 1usize..200,
+    ) {
+        check_writes(&name, &game, chunk_size);
+    }
+
+    #[test]
+    fn machine_finds_response_after_noise(
+        noise in vec(any::<u8>(), 0..256),
+        response in 0usize..3,
+        splits in vec(1usize..32, 1..8),
+    ) {
+        let (token, result) = RESPONSES[response].clone();
+        // Noise which contains a response ends the upload there instead.
+        let expected = expected_tokens(&RESPONSES, &noise)
+            .into_iter()
+            .next()
+            .map_or(result, |(found, _)| found);
+
+        let mut machine = UploadMachine::new("proptest", "").unwrap();
+        while machine.next_write().is_some() {}
+
+        let output = [&noise[..], token].concat();
+        let found = chunks(&output, &splits)
+            .into_iter()
+            .find_map(|chunk| machine.feed_read(chunk));
+        prop_assert_eq!(found, Some(expected));
+        prop_assert!(!machine.is_waiting());
+    }
+}
@@ -1,4 +1,4 @@
-use std::time::Duration;
+mod common;
 
 use serial_test::serial;
 use spade_serial::{upload_game, UploadResult};
@@ -7,18 +7,13 @@ use spade_serial::{upload_game, UploadResult};
 #[test]
 #[serial]
 fn upload() {
-    let device = std::env::var("TEST_DEVICE").unwrap();
-
-    let mut port = serialport::new(device, 115200)
-        .timeout(Duration::from_millis(1000))
-        .open()
-        .unwrap();
+    let mut port = common::open_test_device();
 
     assert_eq!(
         upload_game(
-            &mut port,
-            &String::from("tests/upload.rs"),
-            &String::from("console.log('from spade-serial tests')")
+            &mut *port,
+            "tests/upload.rs",
+            "console.log('from spade-serial tests')"
         ),
         Ok(UploadResult::AllGood)
     );
@@ -0,0 +1,188 @@
+use clap::{Args, Subcommand};
+use serialport::SerialPortType;
+
+use crate::config::{set_alias, Alias, Config};
+use crate::failure::Failure;
+use crate::report::Report;
+
+/// The subcommands of the `alias` command.
+#[derive(Subcommand)]
+pub enum AliasCommand {
+    /// Name a device, so the name can be passed in place of its serial port.
+    /// A device connected over USB is named by its serial number, so the name
+    /// still works when it gets another serial port.
+    Add(AddArgs),
+    /// Remove the name of a device.
+    Remove {
+        /// The name to remove.
+        name: String,
+    },
+    /// List the names of devices.
+    List,
+}
+
+/// The arguments of the `alias add` command.
+#[derive(Args)]
+pub struct AddArgs {
+    /// The name of the device. It cannot be "auto" or "all", or contain
+    /// commas.
+    pub name: String,
+    /// The serial port the device is connected to.
+    #[arg(required_unless_present = "serial_number")]
+    pub port: Option<String>,
+    /// Name the device with this USB serial number, instead of the one
+    /// connected to a serial port.
+    #[arg(long, conflicts_with = "port")]
+    pub serial_number: Option<String>,
+    /// Name whichever device is connected to the serial port, instead of
+    /// following the one connected now by its serial number.
+    #[arg(long, requires = "port")]
+    pub by_port: bool,
+}
+
+/// Runs the `alias` command with `command` and the aliases in `config`,
+/// recording the aliases listed in `report`. Prints what was done unless
+/// `json` is `true`.
+pub fn run(
+    command: &AliasCommand,
+    config: &Config,
+    report: &mut Report,
+    json: bool,
+) -> Result<(), Failure> {
+    match command {
+        AliasCommand::Add(args) => {
+            check_name(&args.name)?;
+            let alias = alias_for(args)?;
+            set_alias(&args.name, Some(&alias))?;
+            if !json {
+                match &alias {
+                    Alias::SerialNumber(serial_number) => println!(
+                        "{} is now the device with the serial number {serial_number}.",
+                        args.name
+                    ),
+                    Alias::Port(port) => {
+                        println!("{} is now the device connected to {port}.", args.name)
+                    }
+                }
+            }
+        }
+        AliasCommand::Remove { name } => {
+            if set_alias(name, None)?.is_none() {
+                return Err(Failure::Usage(format!("There is no alias named {name}.")));
+            }
+            if !json {
+                println!("Removed {name}.");
+            }
+        }
+        AliasCommand::List => {
+            if !json {
+                print_aliases(config);
+            }
+            report.aliases = Some(config.aliases.clone());
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that `name` can be used as an alias, without being mistaken for
+/// something else passed as a device.
+fn check_name(name: &str) -> Result<(), Failure> {
+    if name.is_empty() || name == "auto" || name == "all" || name.contains(',') {
+        return Err(Failure::Usage(format!(
+            "{name:?} cannot be used as an alias, since it has another meaning as a device."
+        )));
+    }
+
+    Ok(())
+}
+
+/// Returns the alias to add for `args`: by the serial number passed, or by
+/// that of the device connected to the port passed if it has one and
+/// `--by-port` is not passed.
+fn alias_for(args: &AddArgs) -> Result<Alias, Failure> {
+    if let Some(serial_number) = &args.serial_number {
+        return Ok(Alias::SerialNumber(serial_number.clone()));
+    }
+    let port = args
+        .port
+        .as_ref()
+        .expect("clap requires a serial number or port");
+    if args.by_port {
+        return Ok(Alias::Port(port.clone()));
+    }
+
+    let ports = serialport::available_ports()
+        .map_err(|err| Failure::DeviceNotFound(format!("Could not list serial ports: {err}")))?;
+    let connected = ports
+        .into_iter()
+        .find(|candidate| candidate.port_name == *port)
+        .ok_or_else(|| Failure::DeviceNotFound(format!("No device is connected to {port}.")))?;
+
+    Ok(match connected.port_type {
+        SerialPortType::UsbPort(usb) => match usb.serial_number {
+            Some(serial_number) => Alias::SerialNumber(serial_number),
+            None => Alias::Port(port.clone()),
+        },
+        _ => Alias::Port(port.clone()),
+    })
+}
+
+/// Prints the aliases in `config` and the devices they name.
+fn print_aliases(config: &Config) {
+    if config.aliases.is_empty() {
+        println!("There are no aliases. Add one with `spade-upload alias add`.");
+        return;
+    }
+
+    let width = config.aliases.keys().map(String::len).max().unwrap_or(0);
+    for (name, alias) in &config.aliases {
+        match alias {
+            Alias::SerialNumber(serial_number) => {
+                println!("{name:width$}  serial number {serial_number}")
+            }
+            Alias::Port(port) => println!("{name:width$}  port {port}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        for name in ["classroom-3", "desk", "Sprig 2", "automatic"] {
+            assert!(check_name(name).is_ok(), "{name}");
+        }
+        // These would be taken as finding devices, or as several devices.
+        for name in ["", "auto", "all", "a,b", ","] {
+            assert!(matches!(check_name(name), Err(Failure::Usage(_))), "{name}");
+        }
+    }
+
+    #[test]
+    fn aliases_for_arguments() {
+        let args = AddArgs {
+            name: String::from("desk"),
+            port: None,
+            serial_number: Some(String::from("E6614C311B4A5A29")),
+            by_port: false,
+        };
+        assert_eq!(
+            alias_for(&args).unwrap(),
+            Alias::SerialNumber(String::from("E6614C311B4A5A29"))
+        );
+
+        let args = AddArgs {
+            name: String::from("desk"),
+            port: Some(String::from("/dev/ttyACM2")),
+            serial_number: None,
+            by_port: true,
+        };
+        assert_eq!(
+            alias_for(&args).unwrap(),
+            Alias::Port(String::from("/dev/ttyACM2"))
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use clap::Args;
+use serde::Deserialize;
+use spade_serial::{upload_many, BatchOptions, Game, GameError, GameName};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::config::Config;
+use crate::failure::{check_result, Failure};
+use crate::report::{millis, outcome, print_summary, BatchEntry, ErrorReport, Report};
+use crate::upload::connect;
+
+/// The arguments of the `batch` command.
+#[derive(Args)]
+pub struct BatchArgs {
+    /// The serial port or alias of the Sprig device, or "auto" to find the
+    /// connected Sprig.
+    pub device: String,
+    /// Path to a TOML manifest listing the games to upload.
+    pub manifest: PathBuf,
+}
+
+/// A manifest listing games to upload with `batch`, like:
+/// ```toml
+/// [[games]]
+/// name = "Pond"
+/// path = "pond.js"
+///
+/// [[games]]
+/// glob = "games/*.js"
+/// ```
+#[derive(Deserialize)]
+struct Manifest {
+    games: Vec<Entry>,
+}
+
+/// An entry in a `Manifest`. Paths are relative to the manifest.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    /// A game at `path`, named `name` or after the file without its
+    /// extension.
+    Game { name: Option<String>, path: PathBuf },
+    /// Every file matching `glob`, each named after the file without its
+    /// extension.
+    Glob { glob: String },
+}
+
+/// Runs the `batch` command with `args` and the defaults in `config`,
+/// recording what happens in `report`. Prints a summary of the results unless
+/// `json` is `true`.
+pub fn run(
+    args: &BatchArgs,
+    config: &Config,
+    report: &mut Report,
+    json: bool,
+) -> Result<(), Failure> {
+    let games = read_manifest(&args.manifest)?;
+    let mut device = connect(&args.device, config, report)?;
+
+    let start = Instant::now();
+    let options = BatchOptions {
+        retries: config.retries(),
+        ..Default::default()
+    };
+    let results = upload_many(&mut *device.port, &games, &options);
+    report.timings.upload_ms = Some(millis(start.elapsed()));
+
+    let mut first_failure = None;
+    let mut entries = Vec::with_capacity(games.len());
+    for (index, game) in games.iter().enumerate() {
+        let (result, failure) = match results.get(index) {
+            Some((_, Ok(result))) => (Some(result.clone()), check_result(result.clone()).err()),
+            Some((_, Err(err))) => (None, Some(Failure::from(err.clone()))),
+            // Games after one which stopped the batch are skipped.
+            None => (None, None),
+        };
+        entries.push(BatchEntry {
+            name: game.name().as_str().to_owned(),
+            result,
+            error: failure.as_ref().map(ErrorReport::from),
+        });
+        if let Some(failure) = failure {
+            first_failure.get_or_insert(failure);
+        }
+    }
+
+    if !json {
+        let rows = entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), outcome(&entry.result, &entry.error)));
+        print_summary("GAME", rows);
+    }
+    report.games = Some(entries);
+
+    match first_failure {
+        Some(failure) => Err(failure),
+        None => Ok(()),
+    }
+}
+
+/// Reads the games listed in the manifest at `path`.
+fn read_manifest(path: &Path) -> Result<Vec<Game>, Failure> {
+    let manifest = fs::read_to_string(path)
+        .map_err(|err| Failure::Manifest(format!("Could not read {}: {err}", path.display())))?;
+    let manifest: Manifest = toml::from_str(&manifest)
+        .map_err(|err| Failure::Manifest(format!("{} is not valid: {err}", path.display())))?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    let mut games = Vec::new();
+    for entry in manifest.games {
+        match entry {
+            Entry::Game { name, path } => games.push(read_game(&directory.join(path), name)?),
+            Entry::Glob { glob } => {
+                let pattern = directory.join(&glob);
+                let paths = glob::glob(&pattern.to_string_lossy()).map_err(|err| {
+                    Failure::Manifest(format!("{glob} is not a valid glob: {err}"))
+                })?;
+                for path in paths {
+                    let path = path.map_err(|err| Failure::Manifest(err.to_string()))?;
+                    games.push(read_game(&path, None)?);
+                }
+            }
+        }
+    }
+
+    Ok(games)
+}
+
+/// Reads the game at `path`, named `name` or after the file without its
+/// extension.
+fn read_game(path: &Path, name: Option<String>) -> Result<Game, Failure> {
+    let game = match name {
+        Some(name) => GameName::new(&name)
+            .map_err(GameError::from)
+            .and_then(|name| Game::new(name, fs::read(path)?)),
+        None => Game::from_file(path),
+    };
+
+    game.map_err(|err| Failure::Manifest(format!("Could not read {}: {err}", path.display())))
+}
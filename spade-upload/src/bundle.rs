@@ -0,0 +1,322 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::failure::Failure;
+
+/// Bundles the game whose entry point is the file at `entry` into one source,
+/// since Spade only runs one file.
+///
+/// Each local file imported with `import` or a bare `require` statement is
+/// inlined once, before the first file importing it, and the import is
+/// removed. `export` keywords are removed too, so everything the files declare
+/// ends up in one scope, like in a game written as one file. The header
+/// comment of the entry point stays at the start.
+///
+/// Importing under another name, with `as` or a default import, is not
+/// supported, nor is assigning the result of `require` or importing packages.
+pub fn bundle(entry: &Path) -> Result<String, Failure> {
+    let source = read(entry)?;
+    let (header, body) = split_header(&source);
+
+    let mut bundler = Bundler {
+        included: HashSet::from([canonical(entry)?]),
+        out: header.to_owned(),
+    };
+    if !header.is_empty() {
+        bundler.out.push('\n');
+    }
+    bundler.include(entry, body)?;
+
+    Ok(bundler.out)
+}
+
+struct Bundler {
+    /// The canonical paths of the files included so far.
+    included: HashSet<PathBuf>,
+    out: String,
+}
+
+impl Bundler {
+    /// Writes the files imported by `source`, which was read from `path`,
+    /// then `source` without its imports and exports.
+    fn include(&mut self, path: &Path, source: &str) -> Result<(), Failure> {
+        let (imports, body) = strip_modules(path, source)?;
+
+        for import in imports {
+            let import = resolve(path, &import)?;
+            if self.included.insert(canonical(&import)?) {
+                let source = read(&import)?;
+                self.include(&import, &source)?;
+            }
+        }
+
+        self.out.push_str(&body);
+        Ok(())
+    }
+}
+
+/// Splits `source` into the block comment it starts with, which holds the
+/// header of a Sprig game, and the rest.
+fn split_header(source: &str) -> (&str, &str) {
+    let start = source.len() - source.trim_start().len();
+    if !source[start..].starts_with("/*") {
+        return ("", source);
+    }
+
+    match source[start..].find("*/") {
+        Some(end) => source.split_at(start + end + 2),
+        None => ("", source),
+    }
+}
+
+/// Removes the imports and exports from `source`, which was read from `path`.
+/// Returns the paths imported, as written, and what is left of the source.
+fn strip_modules(path: &Path, source: &str) -> Result<(Vec<String>, String), Failure> {
+    let unsupported = |statement: &str, problem: &str| {
+        Failure::InvalidSource(format!(
+            "{} cannot be bundled, since {problem}:\n{}",
+            path.display(),
+            statement.trim()
+        ))
+    };
+
+    let mut imports = Vec::new();
+    let mut body = String::with_capacity(source.len());
+    let mut lines = source.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+
+        let reexport = trimmed.starts_with("export *")
+            || (trimmed.starts_with("export {") && trimmed.contains(" from "));
+        if starts_with_word(trimmed, "import") || reexport {
+            // Imports can span several lines, and end with the quoted path.
+            let mut statement = line.to_owned();
+            while statement.matches(['\'', '"']).count() < 2 {
+                let Some(line) = lines.next() else {
+                    return Err(unsupported(&statement, "the import is not finished"));
+                };
+                statement.push('\n');
+                statement.push_str(line);
+            }
+
+            let clause = statement
+                .trim_start()
+                .trim_start_matches("import")
+                .trim_start_matches("export")
+                .trim_start();
+            let named = clause.starts_with('{') || (clause.starts_with('*') && reexport);
+            if !(named || clause.starts_with(['\'', '"'])) || clause.contains(" as ") {
+                return Err(unsupported(
+                    &statement,
+                    "only names can be imported, as they are",
+                ));
+            }
+            imports.push(quoted(&statement).to_owned());
+        } else if trimmed.starts_with("require(") {
+            imports.push(quoted(trimmed).to_owned());
+        } else if trimmed.contains("require(\"./")
+            || trimmed.contains("require('./")
+            || trimmed.contains("require(\"../")
+            || trimmed.contains("require('../")
+        {
+            return Err(unsupported(
+                line,
+                "the result of require can only be ignored",
+            ));
+        } else if trimmed.starts_with("export {") {
+            // The names are declared elsewhere in the file.
+            let mut statement = line.to_owned();
+            while !statement.contains('}') {
+                let Some(line) = lines.next() else { break };
+                statement.push('\n');
+                statement.push_str(line);
+            }
+            // Renaming would need the name to be declared again.
+            if statement.split_whitespace().any(|word| word == "as") {
+                return Err(unsupported(
+                    &statement,
+                    "only names can be exported, as they are",
+                ));
+            }
+        } else if let Some(declaration) = trimmed.strip_prefix("export default ") {
+            if !["function", "class", "async"]
+                .iter()
+                .any(|keyword| starts_with_word(declaration, keyword))
+            {
+                return Err(unsupported(line, "only declarations can be exported"));
+            }
+            body.push_str(declaration);
+            body.push('\n');
+        } else if let Some(declaration) = trimmed.strip_prefix("export ") {
+            body.push_str(declaration);
+            body.push('\n');
+        } else {
+            body.push_str(line);
+            body.push('\n');
+        }
+    }
+
+    Ok((imports, body))
+}
+
+/// Whether `text` starts with the word `word`, not just a longer word
+/// starting the same way.
+fn starts_with_word(text: &str, word: &str) -> bool {
+    text.strip_prefix(word).is_some_and(|rest| {
+        !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '$')
+    })
+}
+
+/// Returns the last quoted string in `statement`, without its quotes.
+fn quoted(statement: &str) -> &str {
+    let Some(end) = statement.rfind(['\'', '"']) else {
+        return "";
+    };
+    let quote = &statement[end..=end];
+    let start = statement[..end].rfind(quote).map_or(0, |start| start + 1);
+
+    &statement[start..end]
+}
+
+/// Returns the path of the file imported as `import` by the file at `path`.
+/// Like in browsers, imports without an extension are tried with `.js` too.
+fn resolve(path: &Path, import: &str) -> Result<PathBuf, Failure> {
+    if !import.starts_with("./") && !import.starts_with("../") {
+        return Err(Failure::InvalidSource(format!(
+            "{} imports {import}, but only local files starting with ./ or ../ can be bundled.",
+            path.display()
+        )));
+    }
+
+    let resolved = path.parent().unwrap_or(Path::new("")).join(import);
+    if !resolved.is_file() && resolved.extension().is_none() {
+        return Ok(resolved.with_extension("js"));
+    }
+
+    Ok(resolved)
+}
+
+/// Reads the file at `path`.
+fn read(path: &Path) -> Result<String, Failure> {
+    fs::read_to_string(path)
+        .map_err(|err| Failure::InvalidSource(format!("Could not read {}: {err}", path.display())))
+}
+
+/// Returns the canonical form of `path`, to tell whether it was included.
+fn canonical(path: &Path) -> Result<PathBuf, Failure> {
+    fs::canonicalize(path)
+        .map_err(|err| Failure::InvalidSource(format!("Could not read {}: {err}", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Writes `files`, pairs of paths and sources, to a new directory.
+    fn files(files: &[(&str, &str)]) -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, source) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn order() {
+        let dir = files(&[
+            (
+                "game.js",
+                "/*\n@title: Pond\n*/\nimport { player } from './player.js'\nimport './levels'\nsetMap(levels[0])\n",
+            ),
+            ("player.js", "export const player = 'p'\n"),
+            (
+                "levels.js",
+                "require('./lib/map.js')\nexport const levels = [map`p`]\n",
+            ),
+            ("lib/map.js", "export function map(strings) {}\n"),
+        ]);
+
+        // Imports are included in the order they are written, each before the
+        // file importing it. The line the header ended on is kept.
+        assert_eq!(
+            bundle(&dir.path().join("game.js")).unwrap(),
+            "/*\n@title: Pond\n*/\nconst player = 'p'\nfunction map(strings) {}\nconst levels = [map`p`]\n\nsetMap(levels[0])\n"
+        );
+    }
+
+    #[test]
+    fn duplicate_imports() {
+        let dir = files(&[
+            (
+                "game.js",
+                "import { a } from './a.js'\nimport { b } from './b.js'\nimport { a } from \"./a\"\n",
+            ),
+            ("a.js", "export const a = 1\n"),
+            ("b.js", "import { a } from './a.js'\nconst b = a\n"),
+        ]);
+        assert_eq!(
+            bundle(&dir.path().join("game.js")).unwrap(),
+            "const a = 1\nconst b = a\n"
+        );
+    }
+
+    #[test]
+    fn cyclic_imports() {
+        let dir = files(&[
+            ("game.js", "import { a } from './a.js'\ngame()\n"),
+            ("a.js", "import { b } from './b.js'\nconst a = 1\n"),
+            (
+                "b.js",
+                "import { a } from './a.js'\nimport './game.js'\nconst b = 2\n",
+            ),
+        ]);
+
+        // Each file is included once, the first time it is imported.
+        assert_eq!(
+            bundle(&dir.path().join("game.js")).unwrap(),
+            "const b = 2\nconst a = 1\ngame()\n"
+        );
+    }
+
+    #[test]
+    fn missing_files() {
+        let dir = files(&[("game.js", "import { a } from './missing.js'\n")]);
+        match bundle(&dir.path().join("game.js")) {
+            Err(Failure::InvalidSource(problem)) => assert!(problem.contains("missing.js")),
+            other => panic!("{other:?}"),
+        }
+
+        assert!(matches!(
+            bundle(&dir.path().join("entry.js")),
+            Err(Failure::InvalidSource(_))
+        ));
+    }
+
+    #[test]
+    fn unsupported_imports() {
+        for source in [
+            "import game from './a.js'\n",
+            "import * as a from './a.js'\n",
+            "import { a } from 'sprig'\n",
+            "const a = require('./a.js')\n",
+            "import { a }\n",
+            "export default 1\n",
+            "const a = 1\nexport { a as b }\n",
+            "const a = 1\nexport {\n    a\n    as b,\n}\n",
+        ] {
+            let dir = files(&[("game.js", source), ("a.js", "")]);
+            assert!(
+                matches!(
+                    bundle(&dir.path().join("game.js")),
+                    Err(Failure::InvalidSource(_))
+                ),
+                "{source}"
+            );
+        }
+    }
+}
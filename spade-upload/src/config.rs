@@ -0,0 +1,424 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use spade_serial::{DEFAULT_BAUD_RATE, DEFAULT_TIMEOUT};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::failure::Failure;
+use crate::transcript::TranscriptFile;
+
+/// Defaults for the arguments of every command, read from the config file and
+/// the environment. The config file is `spade-upload/config.toml` in the user's
+/// config directory, like:
+/// ```toml
+/// device = "/dev/ttyACM0"
+/// baud = 115200
+/// timeout = 1000
+/// retries = 2
+/// timeout_profile = "patient"
+/// name = "{title} (dev)"
+///
+/// [aliases]
+/// classroom-3 = { serial_number = "E6614C311B4A5A29" }
+/// desk = { port = "/dev/ttyACM2" }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The serial port used when the device is `"auto"`, instead of finding
+    /// the connected device.
+    pub device: Option<String>,
+    /// The baud rate serial ports are opened at.
+    pub baud: Option<u32>,
+    /// The read timeout of serial ports, in milliseconds.
+    pub timeout: Option<u64>,
+    /// How many times an upload is tried again if the device stops
+    /// responding or communication fails.
+    pub retries: Option<u32>,
+    /// The preset for the settings of uploads which are not set otherwise.
+    pub timeout_profile: Option<TimeoutProfile>,
+    /// The name games are uploaded under when none is passed, where
+    /// `{title}` is replaced with the `@title:` header of the game and
+    /// `{file}` with its file name without the extension.
+    pub name: Option<String>,
+    /// Names which can be passed in place of a serial port for the device,
+    /// managed with the `alias` command.
+    pub aliases: BTreeMap<String, Alias>,
+    /// Whether the user can be asked to choose between several devices found
+    /// with `"auto"`. This is set from the arguments, not the config file.
+    #[serde(skip)]
+    pub interactive: bool,
+    /// The file everything sent to and received from devices is written to.
+    /// This is set from the arguments, not the config file.
+    #[serde(skip)]
+    pub transcript: Option<TranscriptFile>,
+    /// Whether the progress of uploads is printed as lines of JSON. This is
+    /// set from the arguments, not the config file.
+    #[serde(skip)]
+    pub porcelain: bool,
+    /// How long to wait for a device to be connected if there is none yet.
+    /// This is set from the arguments, not the config file.
+    #[serde(skip)]
+    pub device_wait: Option<Duration>,
+}
+
+/// A preset for the settings of uploads, tuned for how reliable the
+/// connection to the device is. The settings set on their own take precedence.
+///
+/// The baud rate is the same for every preset, since Sprigs connect over USB
+/// where it makes no difference, and boards which need another one need it
+/// whatever the connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeoutProfile {
+    /// For a Sprig connected directly: fails quickly if the device does not
+    /// respond, without trying again.
+    Fast,
+    /// The defaults of each setting.
+    #[default]
+    Default,
+    /// For USB hubs, long cables and unreliable devices: waits longer, also
+    /// while the device writes the game to flash, writes less at a time and
+    /// tries again several times.
+    Patient,
+    /// For unattended uploads, like in continuous integration: tries again,
+    /// but gives up soon enough that a stuck device does not hold up the job.
+    Ci,
+}
+
+/// The settings of a `TimeoutProfile`.
+struct ProfileSettings {
+    /// The read timeout of serial ports.
+    timeout: Duration,
+    /// How many times an upload is tried again.
+    retries: u32,
+    /// How many reads in a row may time out while waiting for the response
+    /// to an upload.
+    max_timeouts: u32,
+    /// How many bytes of a game are written at a time, or `None` for the
+    /// default of `UploadOptions`.
+    chunk_size: Option<usize>,
+    /// How long to wait after the upload header before writing the game.
+    header_delay: Option<Duration>,
+}
+
+impl TimeoutProfile {
+    /// Returns the settings of this preset.
+    fn settings(self) -> ProfileSettings {
+        match self {
+            TimeoutProfile::Fast => ProfileSettings {
+                timeout: Duration::from_millis(250),
+                retries: 0,
+                max_timeouts: 0,
+                chunk_size: None,
+                header_delay: None,
+            },
+            TimeoutProfile::Default => ProfileSettings {
+                timeout: DEFAULT_TIMEOUT,
+                retries: 0,
+                max_timeouts: 0,
+                chunk_size: None,
+                header_delay: None,
+            },
+            TimeoutProfile::Patient => ProfileSettings {
+                timeout: Duration::from_secs(5),
+                retries: 3,
+                max_timeouts: 6,
+                chunk_size: Some(32),
+                header_delay: Some(Duration::from_millis(100)),
+            },
+            TimeoutProfile::Ci => ProfileSettings {
+                timeout: Duration::from_secs(2),
+                retries: 2,
+                max_timeouts: 4,
+                chunk_size: None,
+                header_delay: Some(Duration::from_millis(50)),
+            },
+        }
+    }
+}
+
+/// A device named in the `[aliases]` table of the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Alias {
+    /// The device with this USB serial number, whichever serial port it is
+    /// connected to.
+    SerialNumber(String),
+    /// The device connected to this serial port.
+    Port(String),
+}
+
+impl Config {
+    /// Reads the config file, if there is one, then overrides it with the
+    /// `SPADE_DEVICE`, `SPADE_BAUD`, `SPADE_TIMEOUT` and `SPADE_RETRIES`
+    /// environment variables.
+    pub fn load() -> Result<Self, Failure> {
+        let mut config = match path() {
+            Some(path) => read(path)?,
+            None => Config::default(),
+        };
+        config.merge(Config::from_env(|name| env::var(name).ok())?);
+
+        Ok(config)
+    }
+
+    /// Returns the settings set by the environment variables `var` looks up.
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Result<Self, Failure> {
+        Ok(Config {
+            device: env_var(&var, "SPADE_DEVICE")?,
+            baud: env_var(&var, "SPADE_BAUD")?,
+            timeout: env_var(&var, "SPADE_TIMEOUT")?,
+            retries: env_var(&var, "SPADE_RETRIES")?,
+            ..Config::default()
+        })
+    }
+
+    /// Overrides the device, baud rate, timeout, retries and timeout profile
+    /// with those set in `other`, like by the arguments.
+    pub fn merge(&mut self, other: Config) {
+        self.device = other.device.or(self.device.take());
+        self.baud = other.baud.or(self.baud);
+        self.timeout = other.timeout.or(self.timeout);
+        self.retries = other.retries.or(self.retries);
+        self.timeout_profile = other.timeout_profile.or(self.timeout_profile);
+    }
+
+    /// Returns the baud rate to open serial ports at.
+    pub fn baud_rate(&self) -> u32 {
+        self.baud.unwrap_or(DEFAULT_BAUD_RATE)
+    }
+
+    /// Returns the read timeout of serial ports.
+    pub fn port_timeout(&self) -> Duration {
+        self.timeout
+            .map(Duration::from_millis)
+            .unwrap_or(self.profile().timeout)
+    }
+
+    /// Returns how many times an upload is tried again.
+    pub fn retries(&self) -> u32 {
+        self.retries.unwrap_or(self.profile().retries)
+    }
+
+    /// Returns how many reads in a row may time out while waiting for the
+    /// response to an upload.
+    pub fn max_timeouts(&self) -> u32 {
+        self.profile().max_timeouts
+    }
+
+    /// Returns how many bytes of a game are written at a time, or `None` for
+    /// the default.
+    pub fn chunk_size(&self) -> Option<usize> {
+        self.profile().chunk_size
+    }
+
+    /// Returns how long to wait after the upload header before writing the
+    /// game, if at all.
+    pub fn header_delay(&self) -> Option<Duration> {
+        self.profile().header_delay
+    }
+
+    /// Returns the settings of the preset in use.
+    fn profile(&self) -> ProfileSettings {
+        self.timeout_profile.unwrap_or_default().settings()
+    }
+}
+
+/// Returns the path of the config file, if the user's config directory is
+/// known.
+fn path() -> Option<PathBuf> {
+    let directory = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        #[cfg(windows)]
+        None => PathBuf::from(env::var_os("APPDATA")?),
+        #[cfg(not(windows))]
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(directory.join("spade-upload").join("config.toml"))
+}
+
+/// Sets the alias `name` in the config file to `alias`, or removes it if
+/// `alias` is `None`. Returns the alias it replaced, if there was one.
+///
+/// The rest of the config file is kept, except for its comments and
+/// formatting.
+pub fn set_alias(name: &str, alias: Option<&Alias>) -> Result<Option<Alias>, Failure> {
+    let path = path()
+        .ok_or_else(|| Failure::Config(String::from("The config directory could not be found.")))?;
+    let mut config: toml::Table = match fs::read_to_string(&path) {
+        Ok(config) => toml::from_str(&config)
+            .map_err(|err| Failure::Config(format!("{} is not valid: {err}", path.display())))?,
+        Err(err) if err.kind() == ErrorKind::NotFound => toml::Table::new(),
+        Err(err) => {
+            return Err(Failure::Config(format!(
+                "Could not read {}: {err}",
+                path.display()
+            )))
+        }
+    };
+
+    let mut aliases: BTreeMap<String, Alias> = match config.remove("aliases") {
+        Some(aliases) => aliases.try_into().map_err(|err| {
+            Failure::Config(format!(
+                "The aliases in {} are not valid: {err}",
+                path.display()
+            ))
+        })?,
+        None => BTreeMap::new(),
+    };
+    let previous = match alias {
+        Some(alias) => aliases.insert(name.to_owned(), alias.clone()),
+        None => aliases.remove(name),
+    };
+    if !aliases.is_empty() {
+        let aliases =
+            toml::Value::try_from(aliases).map_err(|err| Failure::Config(err.to_string()))?;
+        config.insert(String::from("aliases"), aliases);
+    }
+
+    let contents = toml::to_string(&config).map_err(|err| Failure::Config(err.to_string()))?;
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).map_err(|err| {
+            Failure::Config(format!("Could not create {}: {err}", directory.display()))
+        })?;
+    }
+    fs::write(&path, contents)
+        .map_err(|err| Failure::Config(format!("Could not write {}: {err}", path.display())))?;
+
+    Ok(previous)
+}
+
+/// Reads the config file at `path`. A missing file is the same as an empty
+/// one.
+fn read(path: PathBuf) -> Result<Config, Failure> {
+    let config = match fs::read_to_string(&path) {
+        Ok(config) => config,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+        Err(err) => {
+            return Err(Failure::Config(format!(
+                "Could not read {}: {err}",
+                path.display()
+            )))
+        }
+    };
+
+    toml::from_str(&config)
+        .map_err(|err| Failure::Config(format!("{} is not valid: {err}", path.display())))
+}
+
+/// Parses the environment variable `name`, as looked up by `var`, if it is
+/// set and not empty.
+fn env_var<T>(var: impl Fn(&str) -> Option<String>, name: &str) -> Result<Option<T>, Failure>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let Some(value) = var(name).filter(|value| !value.is_empty()) else {
+        return Ok(None);
+    };
+
+    value
+        .parse()
+        .map(Some)
+        .map_err(|err| Failure::Config(format!("{name} is not valid: {err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Looks up environment variables in `vars` instead of the environment.
+    fn vars<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| (*value).to_owned())
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        let mut config: Config = toml::from_str(
+            r#"
+            device = "/dev/file"
+            baud = 9600
+            timeout = 100
+            retries = 1
+            timeout_profile = "patient"
+            "#,
+        )
+        .unwrap();
+        config.merge(
+            Config::from_env(vars(&[
+                ("SPADE_DEVICE", "/dev/env"),
+                ("SPADE_BAUD", "19200"),
+                ("SPADE_TIMEOUT", ""),
+            ]))
+            .unwrap(),
+        );
+        config.merge(Config {
+            baud: Some(38400),
+            timeout_profile: Some(TimeoutProfile::Fast),
+            ..Config::default()
+        });
+
+        // Arguments override the environment, which overrides the file.
+        assert_eq!(config.device.as_deref(), Some("/dev/env"));
+        assert_eq!(config.baud, Some(38400));
+        assert_eq!(config.timeout, Some(100));
+        assert_eq!(config.retries, Some(1));
+        assert_eq!(config.timeout_profile, Some(TimeoutProfile::Fast));
+    }
+
+    #[test]
+    fn profile_defaults() {
+        let config = Config {
+            timeout_profile: Some(TimeoutProfile::Patient),
+            retries: Some(0),
+            ..Config::default()
+        };
+        assert_eq!(config.port_timeout(), Duration::from_secs(5));
+        assert_eq!(config.retries(), 0);
+        assert_eq!(config.max_timeouts(), 6);
+        assert_eq!(config.chunk_size(), Some(32));
+
+        let config = Config::default();
+        assert_eq!(config.baud_rate(), DEFAULT_BAUD_RATE);
+        assert_eq!(config.port_timeout(), DEFAULT_TIMEOUT);
+    }
+
+    #[test]
+    fn invalid_env() {
+        for (name, value) in [
+            ("SPADE_BAUD", "fast"),
+            ("SPADE_BAUD", "-1"),
+            ("SPADE_TIMEOUT", "1.5"),
+            ("SPADE_RETRIES", "many"),
+        ] {
+            match Config::from_env(vars(&[(name, value)])) {
+                Err(Failure::Config(problem)) => {
+                    assert!(problem.starts_with(&format!("{name} is not valid")))
+                }
+                other => panic!("{name}={value} gave {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_file() {
+        assert!(toml::from_str::<Config>("colour = true").is_err());
+        assert!(toml::from_str::<Config>("timeout_profile = \"slow\"").is_err());
+        assert!(read(PathBuf::from("does/not/exist.toml"))
+            .unwrap()
+            .device
+            .is_none());
+    }
+}
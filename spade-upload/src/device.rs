@@ -0,0 +1,287 @@
+use dialoguer::Select;
+use serialport::SerialPortType;
+use spade_serial::transport::Serial;
+use spade_serial::{discover, is_running_legacy, probe, ProbeResult, SpadeTransport};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{Alias, Config};
+use crate::failure::Failure;
+use crate::transcript::Transcript;
+
+/// How often the serial ports are listed while waiting for a device to be
+/// connected.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A connected device running Spade.
+pub struct Device {
+    /// The serial port the device is connected to.
+    pub name: String,
+    pub port: Box<dyn SpadeTransport + Send>,
+    /// Whether the device is running a legacy Spade version.
+    pub legacy: bool,
+}
+
+impl Device {
+    /// Connects to the device at the serial port `name` with the settings in
+    /// `config`. `name` can be an alias in `config`. If it is `"auto"`, the
+    /// device in `config` is used, or the connected device is found if there
+    /// is none, like `find_device`.
+    pub fn connect(name: &str, config: &Config) -> Result<Self, Failure> {
+        match (name, &config.device) {
+            ("auto", Some(device)) => open_device(&resolve_alias(device, config)?, config),
+            ("auto", None) => find_device(config),
+            (name, _) => open_device(&resolve_alias(name, config)?, config),
+        }
+    }
+}
+
+/// Finds the one connected device running Spade, checking each serial port
+/// which could be one. If there are several, the user is asked to choose one
+/// if `config` allows it.
+fn find_device(config: &Config) -> Result<Device, Failure> {
+    let mut found = find_all(config)?;
+    if found.len() == 1 {
+        return Ok(found.remove(0));
+    }
+
+    let names: Vec<_> = found.iter().map(|device| device.name.clone()).collect();
+    if !config.interactive {
+        return Err(Failure::AmbiguousDevice(names));
+    }
+
+    let choices: Vec<_> = found
+        .iter()
+        .map(|device| {
+            let version = match device.legacy {
+                true => "legacy Spade version",
+                false => "Spade",
+            };
+            describe(&device.name, version)
+        })
+        .collect();
+    let index = choose(&choices, names)?;
+
+    Ok(found.swap_remove(index))
+}
+
+/// Finds every connected device running Spade, checking each serial port
+/// which could be one. It is an error if none are found.
+pub fn find_all(config: &Config) -> Result<Vec<Device>, Failure> {
+    let mut found = Vec::new();
+    for name in candidate_ports(config)? {
+        let Ok(mut port) = open_port(&name, config) else {
+            continue;
+        };
+        match probe(&mut *port) {
+            Ok(ProbeResult::NotSpade) | Err(_) => {}
+            Ok(result) => found.push(Device {
+                name,
+                port,
+                legacy: result == ProbeResult::Legacy,
+            }),
+        }
+    }
+
+    match found.is_empty() {
+        true => Err(Failure::DeviceNotFound(String::from(
+            "No device running Spade was found.",
+        ))),
+        false => Ok(found),
+    }
+}
+
+/// Opens the device connected to the serial port `name` with the settings in
+/// `config`.
+fn open_device(name: &str, config: &Config) -> Result<Device, Failure> {
+    let mut port = open_port(name, config)?;
+    let legacy = is_running_legacy(&mut *port)?;
+
+    Ok(Device {
+        name: name.to_owned(),
+        port,
+        legacy,
+    })
+}
+
+/// Opens the serial port `name` with the settings in `config` without writing
+/// anything to it, like `resolve_port`. Returns the name of the port and the
+/// port.
+///
+/// Unlike `Device::connect`, the device is not checked, since a legacy
+/// device would wait for a game afterwards.
+pub fn open_quietly(
+    name: &str,
+    config: &Config,
+) -> Result<(String, Box<dyn SpadeTransport + Send>), Failure> {
+    let name = resolve_port(name, config)?;
+    let port = open_port(&name, config)?;
+
+    Ok((name, port))
+}
+
+/// Returns the name of the serial port `name` without opening it. `name` can
+/// be an alias in `config`. If it is `"auto"`, the device in `config` is used, or the one serial port which
+/// could be a device running Spade if there is none. If there are several,
+/// the user is asked to choose one if `config` allows it.
+pub fn resolve_port(name: &str, config: &Config) -> Result<String, Failure> {
+    Ok(match (name, &config.device) {
+        ("auto", Some(device)) => resolve_alias(device, config)?,
+        ("auto", None) => {
+            let mut candidates = candidate_ports(config)?;
+            match candidates.len() {
+                0 => {
+                    return Err(Failure::DeviceNotFound(String::from(
+                        "No serial port which could be a Sprig was found.",
+                    )))
+                }
+                1 => candidates.remove(0),
+                _ if config.interactive => {
+                    let choices: Vec<_> = candidates
+                        .iter()
+                        .map(|name| describe(name, "not checked"))
+                        .collect();
+                    let index = choose(&choices, candidates.clone())?;
+                    candidates.swap_remove(index)
+                }
+                _ => return Err(Failure::AmbiguousDevice(candidates)),
+            }
+        }
+        (name, _) => resolve_alias(name, config)?,
+    })
+}
+
+/// Returns the serial port named by `name`: the port of the alias `name` in
+/// `config`, or `name` itself if there is no such alias. An alias by USB
+/// serial number is looked up among the connected devices, waiting for it to
+/// be connected for as long as `config` allows.
+pub fn resolve_alias(name: &str, config: &Config) -> Result<String, Failure> {
+    let serial_number = match config.aliases.get(name) {
+        Some(Alias::SerialNumber(serial_number)) => serial_number,
+        Some(Alias::Port(port)) => return Ok(port.clone()),
+        None => return Ok(name.to_owned()),
+    };
+
+    let mut found = None;
+    wait_for_device(config, || {
+        found = port_with_serial_number(serial_number)?;
+        Ok(found.is_some())
+    })?;
+
+    found.ok_or_else(|| {
+        Failure::DeviceNotFound(format!(
+            "{name} is the device with the serial number {serial_number}, which is not connected."
+        ))
+    })
+}
+
+/// Returns the serial port of the USB device with the serial number
+/// `serial_number`, if it is connected.
+pub fn port_with_serial_number(serial_number: &str) -> Result<Option<String>, Failure> {
+    let ports = serialport::available_ports()
+        .map_err(|err| Failure::DeviceNotFound(format!("Could not list serial ports: {err}")))?;
+
+    Ok(ports.into_iter().find_map(|port| match port.port_type {
+        SerialPortType::UsbPort(usb) if usb.serial_number.as_deref() == Some(serial_number) => {
+            Some(port.port_name)
+        }
+        _ => None,
+    }))
+}
+
+/// Describes the serial port `name` for the user to choose between devices,
+/// with its USB product and `status`.
+fn describe(name: &str, status: &str) -> String {
+    let product = discover().ok().and_then(|ports| {
+        ports.into_iter().find_map(|port| match port.port_type {
+            SerialPortType::UsbPort(usb) if port.port_name == name => usb.product,
+            _ => None,
+        })
+    });
+
+    match product {
+        Some(product) => format!("{name} ({product}, {status})"),
+        None => format!("{name} ({status})"),
+    }
+}
+
+/// Asks the user which of `choices` to use, returning its index. If the user
+/// does not choose, fails as ambiguous between `names`.
+fn choose(choices: &[String], names: Vec<String>) -> Result<usize, Failure> {
+    Select::new()
+        .with_prompt("More than one device was found. Which one should be used?")
+        .items(choices)
+        .default(0)
+        .interact_opt()
+        .map_err(|err| Failure::Io(err.to_string()))?
+        .ok_or(Failure::AmbiguousDevice(names))
+}
+
+/// Returns the names of the serial ports which could be devices running
+/// Spade. If there are none, waits for one to be connected for as long as
+/// `config` allows.
+fn candidate_ports(config: &Config) -> Result<Vec<String>, Failure> {
+    let mut candidates = Vec::new();
+    wait_for_device(config, || {
+        candidates = discover()
+            .map_err(|err| Failure::DeviceNotFound(format!("Could not list serial ports: {err}")))?
+            .into_iter()
+            .map(|candidate| candidate.port_name)
+            .collect();
+        Ok(!candidates.is_empty())
+    })?;
+
+    Ok(candidates)
+}
+
+/// Calls `connected` until it returns `true`, for up to the time `config`
+/// allows waiting for a device to be connected. Without a wait, it is only
+/// called once.
+fn wait_for_device(
+    config: &Config,
+    mut connected: impl FnMut() -> Result<bool, Failure>,
+) -> Result<(), Failure> {
+    if connected()? {
+        return Ok(());
+    }
+    let Some(wait) = config.device_wait else {
+        return Ok(());
+    };
+
+    eprintln!("Waiting for the device to be connected.");
+    let deadline = Instant::now() + wait;
+    while Instant::now() < deadline {
+        thread::sleep(POLL_INTERVAL);
+        if connected()? {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+/// Opens the serial port `name` at the baud rate and timeout in `config`,
+/// first waiting for it to appear for as long as `config` allows.
+fn open_port(name: &str, config: &Config) -> Result<Box<dyn SpadeTransport + Send>, Failure> {
+    if config.device_wait.is_some() {
+        wait_for_device(config, || {
+            Ok(serialport::available_ports()
+                .is_ok_and(|ports| ports.iter().any(|port| port.port_name == name)))
+        })?;
+    }
+
+    let port = spade_serial::open(name)
+        .and_then(|mut port| {
+            port.set_baud_rate(config.baud_rate())?;
+            // Through the `SerialPort`, since `SpadeTransport::set_timeout`
+            // has the same name.
+            (*port).set_timeout(config.port_timeout())?;
+            Ok(Serial::new(port))
+        })
+        .map_err(|err| Failure::DeviceNotFound(format!("Could not open {name}: {err}")))?;
+
+    Ok(match &config.transcript {
+        Some(file) => Box::new(Transcript::new(name, port, file.clone())),
+        None => Box::new(port),
+    })
+}
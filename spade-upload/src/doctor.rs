@@ -0,0 +1,309 @@
+use clap::Args;
+use serialport::SerialPortType;
+use spade_serial::{discover, probe, ProbeResult};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::device::{open_quietly, resolve_alias};
+use crate::failure::Failure;
+use crate::report::{millis, CheckReport, CheckStatus, Report};
+
+/// Responses slower than this are reported, since they make uploads slow
+/// and may time out.
+const SLOW_RESPONSE: Duration = Duration::from_millis(500);
+
+/// The arguments of the `doctor` command.
+#[derive(Args)]
+pub struct DoctorArgs {
+    /// The serial port or alias of the Sprig device to check. If not
+    /// specified, the configured device or every serial port which could be a
+    /// Sprig is checked.
+    pub device: Option<String>,
+}
+
+/// Runs the `doctor` command with `args` and the defaults in `config`,
+/// recording each check in `report`. Each check is printed as it finishes
+/// unless `json` is `true`.
+///
+/// Checking a device running a legacy Spade version leaves it waiting for a
+/// game, so it has to be reset afterwards.
+pub fn run(
+    args: &DoctorArgs,
+    config: &Config,
+    report: &mut Report,
+    json: bool,
+) -> Result<(), Failure> {
+    // The checks are of what is connected now.
+    let config = &Config {
+        device_wait: None,
+        ..config.clone()
+    };
+    let mut checks = Vec::new();
+    let mut record = |check: CheckReport| {
+        if !json {
+            print_check(&check);
+        }
+        checks.push(check);
+    };
+
+    let ports = match (args.device.as_deref(), &config.device) {
+        (None | Some("auto"), Some(device)) => vec![resolve_alias(device, config)?],
+        (None | Some("auto"), None) => check_ports(&mut record),
+        (Some(device), _) => vec![resolve_alias(device, config)?],
+    };
+
+    for port in ports {
+        #[cfg(target_os = "linux")]
+        record(permissions::check_access(&port));
+        check_device(&port, config, &mut record);
+    }
+
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Fail)
+        .count();
+    report.checks = Some(checks);
+
+    match failed {
+        0 => Ok(()),
+        failed => Err(Failure::ChecksFailed(failed)),
+    }
+}
+
+/// Lists the serial ports which could be devices running Spade, recording
+/// what was found with `record`. Returns the names of the ports.
+fn check_ports(record: &mut impl FnMut(CheckReport)) -> Vec<String> {
+    let ports = match discover() {
+        Ok(ports) => ports,
+        Err(err) => {
+            record(check(
+                "ports",
+                None,
+                CheckStatus::Fail,
+                format!("Could not list serial ports: {err}"),
+                None,
+            ));
+            return Vec::new();
+        }
+    };
+
+    if ports.is_empty() {
+        record(check(
+            "ports",
+            None,
+            CheckStatus::Fail,
+            String::from("No serial port which could be a Sprig was found."),
+            Some("Connect the Sprig with a USB cable which carries data, not just power, and turn it on."),
+        ));
+        return Vec::new();
+    }
+
+    let found: Vec<_> = ports
+        .iter()
+        .map(|port| match &port.port_type {
+            SerialPortType::UsbPort(usb) => match &usb.serial_number {
+                Some(serial) => format!("{} (serial number {serial})", port.port_name),
+                None => port.port_name.clone(),
+            },
+            _ => port.port_name.clone(),
+        })
+        .collect();
+    record(check(
+        "ports",
+        None,
+        CheckStatus::Pass,
+        format!("Found {}.", found.join(", ")),
+        None,
+    ));
+
+    ports.into_iter().map(|port| port.port_name).collect()
+}
+
+/// Opens the serial port `port` with the settings in `config` and probes the
+/// device connected to it, recording what was found with `record`.
+fn check_device(port: &str, config: &Config, record: &mut impl FnMut(CheckReport)) {
+    let mut device = match open_quietly(port, config) {
+        Ok((_, device)) => device,
+        Err(failure) => {
+            let message = match failure {
+                Failure::DeviceNotFound(problem) => problem,
+                failure => failure.to_string(),
+            };
+            record(check(
+                "open",
+                Some(port),
+                CheckStatus::Fail,
+                message,
+                Some("Close other programs using the serial port, like a serial monitor or the Sprig editor in a browser."),
+            ));
+            return;
+        }
+    };
+    record(check(
+        "open",
+        Some(port),
+        CheckStatus::Pass,
+        format!("Opened {port}."),
+        None,
+    ));
+
+    let start = Instant::now();
+    let (status, message, hint) = match probe(&mut *device) {
+        Ok(ProbeResult::Modern) if start.elapsed() > SLOW_RESPONSE => (
+            CheckStatus::Warn,
+            format!(
+                "The device is running Spade, but took {} ms to respond.",
+                millis(start.elapsed())
+            ),
+            Some("Connect the Sprig directly instead of through a USB hub, or pass a longer --timeout."),
+        ),
+        Ok(ProbeResult::Modern) => (
+            CheckStatus::Pass,
+            format!(
+                "The device is running Spade and responded in {} ms.",
+                millis(start.elapsed())
+            ),
+            None,
+        ),
+        Ok(ProbeResult::Legacy) => (
+            CheckStatus::Warn,
+            String::from("The device is running a legacy Spade version, which spade-upload cannot upload to."),
+            Some("Update the Sprig's firmware with `spade-upload flash-firmware`. Reset the Sprig first, since it is now waiting for a game."),
+        ),
+        Ok(ProbeResult::NotSpade) => (
+            CheckStatus::Fail,
+            String::from("The device did not respond like Spade."),
+            Some("Reset the Sprig and check that it is running Spade. Clone boards may need a different --baud, like 9600."),
+        ),
+        Err(err) => (
+            CheckStatus::Fail,
+            format!("Communication with the device failed: {err}"),
+            Some("Reconnect the Sprig and try again."),
+        ),
+    };
+    record(check("probe", Some(port), status, message, hint));
+}
+
+/// Creates the report of a check.
+fn check(
+    name: &'static str,
+    port: Option<&str>,
+    status: CheckStatus,
+    message: String,
+    hint: Option<&str>,
+) -> CheckReport {
+    CheckReport {
+        check: name,
+        port: port.map(str::to_owned),
+        status,
+        message,
+        hint: hint.map(str::to_owned),
+    }
+}
+
+/// Prints the result of `check`, followed by its hint.
+fn print_check(check: &CheckReport) {
+    let status = match check.status {
+        CheckStatus::Pass => "PASS",
+        CheckStatus::Warn => "WARN",
+        CheckStatus::Fail => "FAIL",
+    };
+    println!("{status}  {}", check.message);
+    if let Some(hint) = &check.hint {
+        println!("      {hint}");
+    }
+}
+
+/// Checking access to serial ports on Linux, where they usually belong to a
+/// group like `dialout` which users have to be added to.
+#[cfg(target_os = "linux")]
+mod permissions {
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+
+    use super::check;
+    use crate::report::{CheckReport, CheckStatus};
+
+    /// Checks that this process can read and write the serial port `port`.
+    pub fn check_access(port: &str) -> CheckReport {
+        let metadata = match fs::metadata(port) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                return check(
+                    "permissions",
+                    Some(port),
+                    CheckStatus::Fail,
+                    format!("Could not read the permissions of {port}: {err}"),
+                    None,
+                )
+            }
+        };
+        let Some((uid, groups)) = credentials() else {
+            return check(
+                "permissions",
+                Some(port),
+                CheckStatus::Warn,
+                String::from("Could not read the groups of this process."),
+                None,
+            );
+        };
+
+        let mode = metadata.mode();
+        let allowed = uid == 0
+            || (metadata.uid() == uid && mode & 0o600 == 0o600)
+            || (groups.contains(&metadata.gid()) && mode & 0o060 == 0o060)
+            || mode & 0o006 == 0o006;
+        if allowed {
+            return check(
+                "permissions",
+                Some(port),
+                CheckStatus::Pass,
+                format!("You can read and write {port}."),
+                None,
+            );
+        }
+
+        let group = group_name(metadata.gid()).unwrap_or_else(|| metadata.gid().to_string());
+        let hint = match group.as_str() {
+            "root" => String::from("Add a udev rule giving your group access to the Sprig, like SUBSYSTEM==\"tty\", ATTRS{idVendor}==\"2e8a\", MODE=\"0666\"."),
+            group => format!("Add yourself to the {group} group with `sudo usermod -aG {group} $USER`, then log out and back in."),
+        };
+        check(
+            "permissions",
+            Some(port),
+            CheckStatus::Fail,
+            format!("You cannot read and write {port}, which belongs to the {group} group."),
+            Some(&hint),
+        )
+    }
+
+    /// Returns the effective user ID of this process and the IDs of its
+    /// groups.
+    fn credentials() -> Option<(u32, Vec<u32>)> {
+        let status = fs::read_to_string("/proc/self/status").ok()?;
+        let field = |name: &str| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix(name))
+                .map(|ids| ids.split_whitespace().filter_map(|id| id.parse().ok()))
+        };
+
+        // The real ID comes first, then the effective ID.
+        let uid = field("Uid:")?.nth(1)?;
+        let mut groups: Vec<u32> = field("Groups:")?.collect();
+        groups.extend(field("Gid:")?.nth(1));
+
+        Some((uid, groups))
+    }
+
+    /// Returns the name of the group with the ID `gid`.
+    fn group_name(gid: u32) -> Option<String> {
+        let groups = fs::read_to_string("/etc/group").ok()?;
+        groups.lines().find_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse::<u32>().ok()?;
+            (id == gid).then(|| name.to_owned())
+        })
+    }
+}
@@ -0,0 +1,308 @@
+use spade_serial::firmware::FirmwareError;
+use spade_serial::{GameError, UploadError, UploadResult};
+use std::fmt;
+use std::io::ErrorKind;
+
+/// The ways spade-upload can fail. Each has its own exit code, so scripts can
+/// tell them apart.
+#[derive(Debug)]
+pub enum Failure {
+    /// More than one device running Spade was found, so the device has to be
+    /// passed explicitly. Holds the ports of the devices found.
+    AmbiguousDevice(Vec<String>),
+    /// The arguments cannot be used together. Holds a description of the
+    /// problem.
+    Usage(String),
+    /// The config file or an environment variable setting a default is not
+    /// valid. Holds a description of the problem.
+    Config(String),
+    /// The game source could not be read, or cannot be uploaded.
+    InvalidGame(GameError),
+    /// The game source is not valid, like an HTML page or not UTF-8. Holds a
+    /// description of the problem.
+    InvalidSource(String),
+    /// The manifest of `batch`, or a game listed in it, could not be read.
+    /// Holds a description of the problem.
+    Manifest(String),
+    /// The serial port could not be opened, or no device was found. Holds a
+    /// description of the problem.
+    DeviceNotFound(String),
+    /// The device is running a legacy Spade version.
+    Legacy,
+    /// The device does not have enough flash for the game.
+    OutOfFlash,
+    /// The device cannot store any more games.
+    OutOfMetadata,
+    /// The device rejected the game with a response spade-upload does not
+    /// know. Holds the response.
+    Rejected(String),
+    /// The device stopped responding.
+    Timeout,
+    /// Communication with the device failed for another reason. Holds a
+    /// description of the problem.
+    Io(String),
+    /// The source could not be watched for changes. Holds a description of
+    /// the problem.
+    Watch(String),
+    /// The game could not be downloaded from the Sprig gallery. Holds a
+    /// description of the problem.
+    #[cfg_attr(not(feature = "online"), allow(dead_code))]
+    Download(String),
+    /// Some of the checks run by `doctor` failed. Holds how many.
+    ChecksFailed(usize),
+    /// The firmware of the device could not be updated. Holds a description
+    /// of the problem and how to fix it.
+    Firmware(String),
+}
+
+impl Failure {
+    /// Returns the exit code of the process when it fails this way.
+    pub fn code(&self) -> u8 {
+        match self {
+            Failure::Io(_) | Failure::Watch(_) => 1,
+            // The same as clap uses for invalid arguments.
+            Failure::AmbiguousDevice(_) | Failure::Usage(_) | Failure::Config(_) => 2,
+            Failure::InvalidGame(_) | Failure::InvalidSource(_) | Failure::Manifest(_) => 3,
+            Failure::DeviceNotFound(_) => 4,
+            Failure::Legacy => 5,
+            Failure::OutOfFlash => 6,
+            Failure::OutOfMetadata => 7,
+            Failure::Timeout => 8,
+            Failure::Download(_) => 9,
+            Failure::ChecksFailed(_) => 10,
+            Failure::Firmware(_) => 11,
+            Failure::Rejected(_) => 12,
+        }
+    }
+
+    /// Returns a name for the way the process failed, for machine-readable
+    /// output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Failure::AmbiguousDevice(_) => "ambiguous_device",
+            Failure::Usage(_) => "usage",
+            Failure::Config(_) => "invalid_config",
+            Failure::InvalidGame(_) => "invalid_game",
+            Failure::InvalidSource(_) => "invalid_source",
+            Failure::Manifest(_) => "invalid_manifest",
+            Failure::DeviceNotFound(_) => "device_not_found",
+            Failure::Legacy => "legacy",
+            Failure::OutOfFlash => "out_of_flash",
+            Failure::OutOfMetadata => "out_of_metadata",
+            Failure::Timeout => "timeout",
+            Failure::Io(_) => "io",
+            Failure::Watch(_) => "watch",
+            Failure::Download(_) => "download",
+            Failure::ChecksFailed(_) => "checks_failed",
+            Failure::Firmware(_) => "firmware",
+            Failure::Rejected(_) => "rejected",
+        }
+    }
+}
+
+impl From<std::io::Error> for Failure {
+    fn from(value: std::io::Error) -> Self {
+        match value.kind() {
+            ErrorKind::TimedOut | ErrorKind::WriteZero => Failure::Timeout,
+            _ => Failure::Io(value.to_string()),
+        }
+    }
+}
+
+impl From<FirmwareError> for Failure {
+    fn from(value: FirmwareError) -> Self {
+        let problem = match value {
+            FirmwareError::InvalidImage => "The firmware image is not a UF2 image.\nCheck that it is the UF2 image of Spade, not the source code.",
+            FirmwareError::DriveNotFound => "The RPI-RP2 drive of the device was not found.\nIf it is connected, pass where it is mounted with --drive.",
+            FirmwareError::DeviceNotFound => "The firmware was copied, but the device did not appear again.\nReconnect the Sprig and check whether it is running Spade.",
+            FirmwareError::IOError => "The serial port could not be opened, or the firmware could not be copied to the drive.\nClose other programs using the serial port and try again.",
+        };
+        Failure::Firmware(problem.to_owned())
+    }
+}
+
+impl From<GameError> for Failure {
+    fn from(value: GameError) -> Self {
+        Failure::InvalidGame(value)
+    }
+}
+
+impl From<UploadError> for Failure {
+    fn from(value: UploadError) -> Self {
+        match value {
+            UploadError::NoResponse | UploadError::WriteStalled | UploadError::Timeout => {
+                Failure::Timeout
+            }
+            UploadError::InvalidName => Failure::InvalidGame(GameError::InvalidName),
+            UploadError::Disconnected => Failure::Io(String::from(
+                "the device was disconnected.\nReconnect the Sprig and try again.",
+            )),
+            _ => Failure::Io(value.to_string()),
+        }
+    }
+}
+
+/// Turns the response to an upload into a failure if the game was rejected.
+pub fn check_result(result: UploadResult) -> Result<(), Failure> {
+    match result {
+        UploadResult::AllGood => Ok(()),
+        UploadResult::OutOfFlash => Err(Failure::OutOfFlash),
+        UploadResult::OutOfMetadata => Err(Failure::OutOfMetadata),
+        UploadResult::Rejected(response) => Err(Failure::Rejected(response.to_string())),
+        // spade-upload never uploads to legacy devices, the only ones which
+        // leave uploads unconfirmed.
+        UploadResult::Unconfirmed => Ok(()),
+        _ => Err(Failure::Rejected(result.response().to_owned())),
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::AmbiguousDevice(ports) => write!(
+                f,
+                "More than one device running Spade was found. Pass one of these as the device:\n{}",
+                ports.join("\n")
+            ),
+            Failure::Usage(problem) => f.write_str(problem),
+            Failure::Config(problem) => f.write_str(problem),
+            Failure::InvalidGame(err) => write!(
+                f,
+                "The game cannot be uploaded: {err}.\nCheck that the source path is correct and the name is at most 100 bytes."
+            ),
+            Failure::InvalidSource(problem) => f.write_str(problem),
+            Failure::Manifest(problem) => f.write_str(problem),
+            Failure::DeviceNotFound(problem) => write!(
+                f,
+                "{problem}\nCheck that the Sprig is connected and turned on, or pass \"auto\" as the device to find it."
+            ),
+            Failure::Legacy => write!(
+                f,
+                "The device is running a legacy Spade version, which spade-upload cannot upload to.\nUpdate the Sprig's firmware with `spade-upload flash-firmware`."
+            ),
+            Failure::OutOfFlash => write!(
+                f,
+                "The device does not have enough space for the game.\nDelete some games from the Sprig, or make the game smaller."
+            ),
+            Failure::OutOfMetadata => write!(
+                f,
+                "The device cannot store any more games.\nDelete some games from the Sprig and try again."
+            ),
+            Failure::Rejected(response) => write!(
+                f,
+                "The device rejected the game with {response}, which spade-upload does not know.\nThe Sprig may be running a newer Spade version; check its release notes for what {response} means."
+            ),
+            Failure::Timeout => write!(
+                f,
+                "The device stopped responding.\nReset the Sprig and try again."
+            ),
+            Failure::Io(problem) => {
+                write!(f, "Communication with the device failed: {problem}")
+            }
+            Failure::Watch(problem) => f.write_str(problem),
+            Failure::Download(problem) => write!(
+                f,
+                "{problem}\nCheck the name of the game on its gallery page, and that you are online."
+            ),
+            Failure::ChecksFailed(1) => write!(f, "1 check failed. Follow its hint to fix it."),
+            Failure::ChecksFailed(failed) => {
+                write!(f, "{failed} checks failed. Follow their hints to fix them.")
+            }
+            Failure::Firmware(problem) => f.write_str(problem),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn codes_and_kinds() {
+        // Scripts rely on these, so changing one is a breaking change.
+        let failures = [
+            (Failure::Io(String::new()), 1, "io"),
+            (Failure::Watch(String::new()), 1, "watch"),
+            (Failure::AmbiguousDevice(Vec::new()), 2, "ambiguous_device"),
+            (Failure::Usage(String::new()), 2, "usage"),
+            (Failure::Config(String::new()), 2, "invalid_config"),
+            (
+                Failure::InvalidGame(GameError::EmptySource),
+                3,
+                "invalid_game",
+            ),
+            (Failure::InvalidSource(String::new()), 3, "invalid_source"),
+            (Failure::Manifest(String::new()), 3, "invalid_manifest"),
+            (
+                Failure::DeviceNotFound(String::new()),
+                4,
+                "device_not_found",
+            ),
+            (Failure::Legacy, 5, "legacy"),
+            (Failure::OutOfFlash, 6, "out_of_flash"),
+            (Failure::OutOfMetadata, 7, "out_of_metadata"),
+            (Failure::Timeout, 8, "timeout"),
+            (Failure::Download(String::new()), 9, "download"),
+            (Failure::ChecksFailed(1), 10, "checks_failed"),
+            (Failure::Firmware(String::new()), 11, "firmware"),
+            (Failure::Rejected(String::new()), 12, "rejected"),
+        ];
+
+        for (failure, code, kind) in failures {
+            assert_eq!(failure.code(), code, "{failure:?}");
+            assert_eq!(failure.kind(), kind, "{failure:?}");
+        }
+    }
+
+    #[test]
+    fn from_io_error() {
+        for kind in [io::ErrorKind::TimedOut, io::ErrorKind::WriteZero] {
+            assert!(matches!(
+                Failure::from(io::Error::from(kind)),
+                Failure::Timeout
+            ));
+        }
+        assert!(matches!(
+            Failure::from(io::Error::from(io::ErrorKind::BrokenPipe)),
+            Failure::Io(_)
+        ));
+    }
+
+    #[test]
+    fn from_upload_error() {
+        for err in [
+            UploadError::NoResponse,
+            UploadError::WriteStalled,
+            UploadError::Timeout,
+        ] {
+            assert!(matches!(Failure::from(err), Failure::Timeout));
+        }
+        assert!(matches!(
+            Failure::from(UploadError::InvalidName),
+            Failure::InvalidGame(GameError::InvalidName)
+        ));
+        for err in [
+            UploadError::Disconnected,
+            UploadError::IOError,
+            UploadError::FailedConversion,
+            UploadError::Cancelled,
+        ] {
+            assert!(matches!(Failure::from(err), Failure::Io(_)));
+        }
+    }
+
+    #[test]
+    fn check_results() {
+        assert!(check_result(UploadResult::AllGood).is_ok());
+        assert!(matches!(
+            check_result(UploadResult::OutOfFlash),
+            Err(Failure::OutOfFlash)
+        ));
+        assert!(matches!(
+            check_result(UploadResult::OutOfMetadata),
+            Err(Failure::OutOfMetadata)
+        ));
+    }
+}
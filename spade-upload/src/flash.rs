@@ -0,0 +1,199 @@
+use clap::Args;
+use spade_serial::firmware::{self, DEFAULT_WAIT};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::device::{resolve_port, Device};
+use crate::failure::Failure;
+use crate::report::{DeviceReport, Report};
+
+/// The latest release of Spade on GitHub, as JSON.
+#[cfg(feature = "online")]
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/hackclub/spade/releases/latest";
+
+/// The arguments of the `flash-firmware` command.
+#[derive(Args)]
+pub struct FlashArgs {
+    /// The serial port or alias of the Sprig device, or "auto" to use the one
+    /// serial port which could be a Sprig.
+    pub device: String,
+    #[command(flatten)]
+    pub firmware: FirmwareArgs,
+}
+
+/// The arguments choosing the firmware to flash and how, shared by the
+/// commands which can update the firmware.
+#[derive(Args)]
+pub struct FirmwareArgs {
+    /// Path to the UF2 image of Spade to flash.
+    #[arg(long, value_name = "PATH")]
+    pub uf2: Option<PathBuf>,
+    /// Download the latest release of Spade and flash it.
+    #[cfg(feature = "online")]
+    #[arg(long, conflicts_with = "uf2")]
+    pub latest: bool,
+    /// Where the RPI-RP2 drive the Sprig appears as while it is flashed is
+    /// mounted. If not specified, the places it is usually mounted are
+    /// checked.
+    #[arg(long, value_name = "PATH")]
+    pub drive: Option<PathBuf>,
+}
+
+/// Where the UF2 image of Spade to flash comes from.
+pub enum Image<'a> {
+    /// The image at this path.
+    File(&'a Path),
+    /// The latest release of Spade, which is downloaded.
+    #[cfg(feature = "online")]
+    Latest,
+}
+
+impl FirmwareArgs {
+    /// Returns the image passed with `--uf2` or `--latest`. If neither was
+    /// passed, the latest release of Spade is used if `latest` is `true` and
+    /// it can be downloaded.
+    pub fn image(&self, latest: bool) -> Option<Image<'_>> {
+        if let Some(path) = &self.uf2 {
+            return Some(Image::File(path));
+        }
+
+        #[cfg(feature = "online")]
+        if self.latest || latest {
+            return Some(Image::Latest);
+        }
+        #[cfg(not(feature = "online"))]
+        let _ = latest;
+
+        None
+    }
+}
+
+/// Runs the `flash-firmware` command with `args` and the defaults in
+/// `config`, recording the device once it has restarted in `report`. Prints
+/// each step unless `json` is `true`.
+pub fn run(
+    args: &FlashArgs,
+    config: &Config,
+    report: &mut Report,
+    json: bool,
+) -> Result<(), Failure> {
+    let Some(image) = args.firmware.image(false) else {
+        return Err(Failure::Usage(String::from(
+            "Pass the UF2 image of Spade to flash with --uf2.",
+        )));
+    };
+    let port = resolve_port(&args.device, config)?;
+    let port = flash(&port, &image, &args.firmware, json)?;
+
+    let device = Device::connect(&port, config)?;
+    report.device = Some(DeviceReport {
+        port: device.name,
+        legacy: device.legacy,
+    });
+    if !json {
+        match device.legacy {
+            true => eprintln!("Flashed a legacy Spade version. The device is at {port}."),
+            false => eprintln!("Flashed Spade. The device is at {port}."),
+        }
+    }
+
+    Ok(())
+}
+
+/// Updates the firmware of the device at the serial port `port` to `image`,
+/// using the drive passed in `firmware`. Returns the serial port of the
+/// device once it has restarted, which may have changed. Prints each step
+/// unless `json` is `true`.
+///
+/// The device is restarted into BOOTSEL mode, the image is copied to the
+/// drive it appears as, and then the device is waited for.
+pub fn flash(
+    port: &str,
+    image: &Image,
+    firmware: &FirmwareArgs,
+    json: bool,
+) -> Result<String, Failure> {
+    let status = |message: &str| {
+        if !json {
+            eprintln!("{message}");
+        }
+    };
+
+    let image = load(image)?;
+    firmware::validate_uf2(&image)?;
+
+    status("Restarting the device into BOOTSEL mode.");
+    firmware::enter_bootsel(port)?;
+    let drive = match &firmware.drive {
+        Some(drive) => drive.clone(),
+        None => firmware::find_drive(DEFAULT_WAIT)?,
+    };
+    status(&format!("Copying the firmware to {}.", drive.display()));
+    firmware::copy_uf2(&drive, &image, DEFAULT_WAIT)?;
+    status("Waiting for the device to restart.");
+
+    Ok(firmware::wait_for_device(DEFAULT_WAIT)?.port_name)
+}
+
+/// Reads or downloads `image`.
+fn load(image: &Image) -> Result<Vec<u8>, Failure> {
+    match image {
+        Image::File(path) => fs::read(path).map_err(|err| {
+            Failure::Firmware(format!(
+                "Could not read {}: {err}\nCheck that the path to the UF2 image is correct.",
+                path.display()
+            ))
+        }),
+        #[cfg(feature = "online")]
+        Image::Latest => download_latest(),
+    }
+}
+
+/// Downloads the UF2 image of the latest release of Spade.
+#[cfg(feature = "online")]
+fn download_latest() -> Result<Vec<u8>, Failure> {
+    use serde::Deserialize;
+    use std::io::Read;
+
+    #[derive(Deserialize)]
+    struct Release {
+        assets: Vec<Asset>,
+    }
+
+    #[derive(Deserialize)]
+    struct Asset {
+        name: String,
+        browser_download_url: String,
+    }
+
+    let failure = |err: &dyn std::fmt::Display| {
+        Failure::Firmware(format!(
+            "Could not download the latest release of Spade: {err}\nCheck that you are online, or download it yourself and pass it with --uf2."
+        ))
+    };
+
+    // GitHub rejects API requests without a user agent.
+    let release: Release = ureq::get(LATEST_RELEASE_URL)
+        .set("User-Agent", "spade-upload")
+        .call()
+        .map_err(|err| failure(&err))?
+        .into_string()
+        .map_err(|err| failure(&err))
+        .and_then(|json| serde_json::from_str(&json).map_err(|err| failure(&err)))?;
+    let asset = release
+        .assets
+        .into_iter()
+        .find(|asset| asset.name.ends_with(".uf2"))
+        .ok_or_else(|| failure(&"the release has no UF2 image"))?;
+
+    let mut image = Vec::new();
+    ureq::get(&asset.browser_download_url)
+        .call()
+        .map_err(|err| failure(&err))?
+        .into_reader()
+        .read_to_end(&mut image)
+        .map_err(|err| failure(&err))?;
+
+    Ok(image)
+}
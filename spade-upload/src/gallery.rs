@@ -0,0 +1,51 @@
+use crate::failure::Failure;
+
+/// Where the source of each game in the Sprig gallery is published, as
+/// `<slug>.js`.
+const SOURCE_URL: &str = "https://raw.githubusercontent.com/hackclub/sprig/main/games";
+
+/// The start of the URL of a game's page in the gallery, followed by its slug.
+const GALLERY_URL: &str = "sprig.hackclub.com/gallery/";
+
+/// Downloads the source of a game in the Sprig gallery. `game` is the slug of
+/// the game, like `pond`, or the URL of its page in the gallery.
+pub fn download(game: &str) -> Result<String, Failure> {
+    let slug = slug(game)?;
+    let url = format!("{SOURCE_URL}/{slug}.js");
+
+    match ureq::get(&url).call() {
+        Ok(response) => response
+            .into_string()
+            .map_err(|err| Failure::Download(format!("Could not read {url}: {err}"))),
+        Err(ureq::Error::Status(404, _)) => Err(Failure::Download(format!(
+            "There is no game called {slug} in the gallery."
+        ))),
+        Err(err) => Err(Failure::Download(format!(
+            "Could not download {url}: {err}"
+        ))),
+    }
+}
+
+/// Returns the slug of `game`, which is either a slug or the URL of a game's
+/// page in the gallery.
+pub fn slug(game: &str) -> Result<&str, Failure> {
+    let url = game
+        .strip_prefix("https://")
+        .or_else(|| game.strip_prefix("http://"))
+        .unwrap_or(game);
+    let slug = url
+        .strip_prefix(GALLERY_URL)
+        .unwrap_or(url)
+        .trim_end_matches('/');
+
+    let is_slug = !slug.is_empty()
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    match is_slug {
+        true => Ok(slug),
+        false => Err(Failure::Download(format!(
+            "{game} is not the name or gallery URL of a Sprig game."
+        ))),
+    }
+}
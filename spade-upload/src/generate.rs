@@ -0,0 +1,23 @@
+use clap::CommandFactory;
+use clap_complete::Shell;
+use clap_mangen::Man;
+use std::io::stdout;
+
+use crate::failure::Failure;
+use crate::Cli;
+
+/// Prints the completions for `shell` to stdout.
+pub fn completions(shell: Shell) -> Result<(), Failure> {
+    let mut command = Cli::command();
+    let name = command.get_name().to_owned();
+    clap_complete::generate(shell, &mut command, name, &mut stdout());
+
+    Ok(())
+}
+
+/// Prints a man page generated from the arguments to stdout, as roff.
+pub fn man() -> Result<(), Failure> {
+    Man::new(Cli::command()).render(&mut stdout())?;
+
+    Ok(())
+}
@@ -0,0 +1,454 @@
+use clap::Args;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, ReadWrite, Request, Response, StatusCode};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use crate::config::Config;
+use crate::failure::Failure;
+#[cfg(feature = "mqtt")]
+use crate::mqtt::{self, MqttArgs};
+use crate::report::ErrorReport;
+use crate::serve::{lock, to_value, Server, Sink, UploadParams};
+
+/// The largest request body read, which is far larger than a game fitting
+/// on the device.
+const MAX_BODY: u64 = 1024 * 1024;
+
+/// How often the clients of WebSockets are pinged to check they are still
+/// there.
+const PING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A WebSocket of `/monitor` or `/events`, shared by the threads sending
+/// over it.
+type Socket = Arc<Mutex<WebSocket<Box<dyn ReadWrite + Send>>>>;
+
+/// The arguments of the `serve-http` command.
+#[derive(Args)]
+pub struct ServeHttpArgs {
+    /// The address to listen for HTTP requests at. Pass 0.0.0.0:7331 to
+    /// accept them from other computers on the network, so anyone on it can
+    /// upload games.
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:7331")]
+    pub bind: String,
+    /// An origin whose pages may use the server, like
+    /// https://sprig.hackclub.com. Can be passed more than once, or as * to
+    /// allow pages from anywhere. By default, requests from pages are refused,
+    /// so sites visited on the network cannot upload games.
+    #[arg(long, value_name = "ORIGIN")]
+    pub allow_origin: Vec<String>,
+    #[cfg(feature = "mqtt")]
+    #[command(flatten)]
+    pub mqtt: MqttArgs,
+}
+
+/// A message sent over the WebSockets of `/monitor` and `/events`.
+#[derive(Serialize)]
+struct Event {
+    /// What happened, like `console` for a line printed by the device.
+    event: &'static str,
+    data: Value,
+}
+
+/// Runs the `serve-http` command with `args` and the defaults in `config`.
+/// Runs until interrupted.
+pub fn run(args: &ServeHttpArgs, config: &Config) -> Result<(), Failure> {
+    let http = tiny_http::Server::http(&args.bind)
+        .map_err(|err| Failure::Io(format!("Could not listen on {}: {err}", args.bind)))?;
+    eprintln!("Listening on http://{}.", args.bind);
+
+    let server = Arc::new(Server::new(config));
+    let origins: Arc<[String]> = args.allow_origin.clone().into();
+    #[cfg(feature = "mqtt")]
+    mqtt::start(&args.mqtt, &server)?;
+    for request in http.incoming_requests() {
+        let server = Arc::clone(&server);
+        let origins = Arc::clone(&origins);
+        thread::spawn(move || handle(&server, &origins, request));
+    }
+
+    Ok(())
+}
+
+/// Answers `request` using the devices open in `server`, if it is not from
+/// a page or is from one of `origins`.
+fn handle(server: &Server, origins: &[String], mut request: Request) {
+    let url = request.url().to_owned();
+    let (path, query) = url.split_once('?').unwrap_or((url.as_str(), ""));
+    match (request.method(), path) {
+        (Method::Get, "/monitor") => monitor(server, origins, request, query),
+        (Method::Get, "/events") => events(server, origins, request),
+        _ => {
+            let response = answer(server, origins, &mut request);
+            let _ = request.respond(response);
+        }
+    }
+}
+
+/// Returns the response to `request`, which is not for a WebSocket.
+fn answer(server: &Server, origins: &[String], request: &mut Request) -> Response<Cursor<Vec<u8>>> {
+    if let Some(response) = refusal(origins, request) {
+        return response;
+    }
+
+    let origin = header_value(request, "Origin");
+    let method = request.method().clone();
+    let url = request.url().to_owned();
+    let path = url.split_once('?').map_or(url.as_str(), |(path, _)| path);
+    let segments: Vec<_> = path.split('/').skip(1).collect();
+
+    let response = match (method, segments.as_slice()) {
+        // Browsers ask before sending JSON from pages served elsewhere.
+        (Method::Options, _) => Response::from_data(Vec::new())
+            .with_status_code(StatusCode(204))
+            .with_header(header("Access-Control-Allow-Methods", "GET, POST"))
+            .with_header(header("Access-Control-Allow-Headers", "Content-Type")),
+        (Method::Get, ["devices"]) => match server.devices() {
+            Ok(devices) => reply(200, &devices),
+            Err(failure) => failed(&failure),
+        },
+        (Method::Post, ["upload"]) => {
+            let body = request.as_reader().take(MAX_BODY);
+            let upload = serde_json::from_reader::<_, UploadParams>(body)
+                .map_err(|err| Failure::Usage(format!("The body is not a valid upload: {err}")))
+                .and_then(|params| server.upload(params));
+            match upload {
+                Ok(upload) => reply(200, &upload),
+                Err(failure) => failed(&failure),
+            }
+        }
+        // Spade only accepts uploads over serial, so there is no way to ask
+        // the device which games it has.
+        (Method::Get, ["device", _, "games"]) => reply(
+            501,
+            &ErrorReport::from(&Failure::Usage(String::from(
+                "Spade cannot list the games on the device over serial.",
+            ))),
+        ),
+        _ => reply(
+            404,
+            &ErrorReport::from(&Failure::Usage(format!("There is nothing at {path}."))),
+        ),
+    };
+
+    // The answer depends on the origin, so caches must not share it between
+    // pages.
+    let response = response.with_header(header("Vary", "Origin"));
+    match origin {
+        Some(origin) => response.with_header(header("Access-Control-Allow-Origin", &origin)),
+        None => response,
+    }
+}
+
+/// Returns the response refusing `request` if it was sent by a page whose
+/// origin is not one of `origins`, and they do not include `*`.
+fn refusal(origins: &[String], request: &Request) -> Option<Response<Cursor<Vec<u8>>>> {
+    let origin = header_value(request, "Origin")?;
+    if origins
+        .iter()
+        .any(|allowed| allowed == "*" || *allowed == origin)
+    {
+        return None;
+    }
+
+    let failure = Failure::Usage(format!(
+        "Pages from {origin} may not use this server. Pass --allow-origin {origin} to allow them."
+    ));
+    Some(reply(403, &ErrorReport::from(&failure)))
+}
+
+/// Returns the value of the header `name` of `request`, if it has one.
+fn header_value(request: &Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str().to_owned())
+}
+
+/// Upgrades `request` to a WebSocket, then sends each line printed by the
+/// device in `query` over it as a `console` event until the client has gone.
+fn monitor(server: &Server, origins: &[String], request: Request, query: &str) {
+    let device = query_param(query, "device").unwrap_or_else(|| String::from("auto"));
+    let Some(socket) = websocket(origins, request) else {
+        return;
+    };
+
+    let connection = server.connect();
+    let sink = sink(&socket);
+    if let Err(failure) = server.monitor(&device, Arc::clone(&sink), connection) {
+        sink("error", to_value(ErrorReport::from(&failure)));
+        return;
+    }
+    keep_alive(server, &socket, connection);
+}
+
+/// Upgrades `request` to a WebSocket, then sends every event of `server`
+/// over it until the client has gone: the output of each monitored device
+/// and the progress of each upload.
+fn events(server: &Server, origins: &[String], request: Request) {
+    let Some(socket) = websocket(origins, request) else {
+        return;
+    };
+
+    let connection = server.connect();
+    server.subscribe(sink(&socket), connection);
+    keep_alive(server, &socket, connection);
+}
+
+/// Pings the client of `socket` every `PING_INTERVAL` until it has gone,
+/// then stops sending it events for `connection`, which closes the socket.
+///
+/// Nothing is read from the socket, since a read would block sending events
+/// until the client sends something, so the client having gone is noticed
+/// when a ping fails to send.
+fn keep_alive(server: &Server, socket: &Socket, connection: usize) {
+    while lock(socket).send(Message::Ping(Vec::new())).is_ok() {
+        thread::sleep(PING_INTERVAL);
+    }
+    server.disconnect(connection);
+}
+
+/// Returns a sink sending events over `socket` as JSON objects with the
+/// `event` and its `data`.
+fn sink(socket: &Socket) -> Sink {
+    let socket = Arc::clone(socket);
+    Arc::new(move |event, data| {
+        let event = Event { event, data };
+        let message = serde_json::to_string(&event).expect("events serialize to JSON");
+        lock(&socket).send(Message::text(message)).is_ok()
+    })
+}
+
+/// Upgrades `request` to a WebSocket if it was not sent by a page, or by one
+/// from `origins`. Answers with status 403 if it was sent by another page,
+/// or 426 if it is not a WebSocket request, and returns `None`.
+fn websocket(origins: &[String], request: Request) -> Option<Socket> {
+    if let Some(response) = refusal(origins, &request) {
+        let _ = request.respond(response);
+        return None;
+    }
+    let Some(key) = header_value(&request, "Sec-WebSocket-Key") else {
+        let failure = Failure::Usage(format!("{} only accepts WebSockets.", request.url()));
+        let _ = request.respond(reply(426, &ErrorReport::from(&failure)));
+        return None;
+    };
+
+    let response = Response::empty(StatusCode(101))
+        .with_header(header("Upgrade", "websocket"))
+        .with_header(header("Connection", "Upgrade"))
+        .with_header(header(
+            "Sec-WebSocket-Accept",
+            &derive_accept_key(key.as_bytes()),
+        ));
+    let stream = request.upgrade("websocket", response);
+    Some(Arc::new(Mutex::new(WebSocket::from_raw_socket(
+        stream,
+        Role::Server,
+        None,
+    ))))
+}
+
+/// Returns a response with the status `status` and `body` as JSON.
+fn reply(status: u16, body: &impl Serialize) -> Response<Cursor<Vec<u8>>> {
+    let body = serde_json::to_vec(body).expect("responses serialize to JSON");
+    Response::from_data(body)
+        .with_status_code(StatusCode(status))
+        .with_header(header("Content-Type", "application/json"))
+}
+
+/// Returns the response describing `failure`, with the status closest to
+/// it.
+fn failed(failure: &Failure) -> Response<Cursor<Vec<u8>>> {
+    let status = match failure {
+        Failure::AmbiguousDevice(_)
+        | Failure::Usage(_)
+        | Failure::InvalidGame(_)
+        | Failure::InvalidSource(_) => 400,
+        Failure::DeviceNotFound(_) => 404,
+        Failure::Legacy | Failure::OutOfFlash | Failure::OutOfMetadata | Failure::Rejected(_) => {
+            409
+        }
+        Failure::Timeout => 504,
+        _ => 502,
+    };
+    reply(status, &ErrorReport::from(failure))
+}
+
+/// Returns the header `name` with `value`.
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("headers are ASCII")
+}
+
+/// Returns the value of `name` in the query string `query`, with percent
+/// escapes decoded.
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| percent_decode(value))
+    })
+}
+
+/// Decodes the percent escapes and plus signs in `value`, like the serial
+/// port `%2Fdev%2FttyACM0`. Invalid escapes are kept as they are.
+fn percent_decode(value: &str) -> String {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(escaped)) => {
+                decoded.push(escaped);
+                rest = &tail[2..];
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                rest = tail;
+            }
+            _ => {
+                decoded.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use tiny_http::TestRequest;
+
+    use super::*;
+
+    /// Returns the value of the header `name` of `response`, if it has one.
+    fn response_header(response: &Response<Cursor<Vec<u8>>>, name: &'static str) -> Option<String> {
+        response
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv(name))
+            .map(|header| header.value.as_str().to_owned())
+    }
+
+    /// Returns the answer of a server allowing `origins` to `request`.
+    fn answer_with(origins: &[&str], request: TestRequest) -> Response<Cursor<Vec<u8>>> {
+        let server = Server::new(&Config::default());
+        let origins: Vec<_> = origins.iter().map(|origin| origin.to_string()).collect();
+        answer(&server, &origins, &mut request.into())
+    }
+
+    #[test]
+    fn routing() {
+        let response = answer_with(&[], TestRequest::new().with_path("/nothing"));
+        assert_eq!(response.status_code(), StatusCode(404));
+        assert_eq!(
+            response_header(&response, "Access-Control-Allow-Origin"),
+            None
+        );
+
+        let response = answer_with(&[], TestRequest::new().with_path("/device/auto/games"));
+        assert_eq!(response.status_code(), StatusCode(501));
+
+        let request = TestRequest::new()
+            .with_method(Method::Post)
+            .with_path("/upload")
+            .with_body("not json");
+        assert_eq!(answer_with(&[], request).status_code(), StatusCode(400));
+
+        let request = TestRequest::new()
+            .with_method(Method::Post)
+            .with_path("/upload")
+            .with_body(r#"{"device": "auto", "source": "console.log(1)"}"#);
+        assert_eq!(answer_with(&[], request).status_code(), StatusCode(400));
+    }
+
+    #[test]
+    fn origins() {
+        let preflight = || {
+            TestRequest::new()
+                .with_method(Method::Options)
+                .with_path("/upload")
+                .with_header(header("Origin", "https://sprig.hackclub.com"))
+        };
+
+        let response = answer_with(&[], preflight());
+        assert_eq!(response.status_code(), StatusCode(403));
+        assert_eq!(
+            response_header(&response, "Access-Control-Allow-Origin"),
+            None
+        );
+
+        let response = answer_with(&["https://example.com"], preflight());
+        assert_eq!(response.status_code(), StatusCode(403));
+
+        for origins in [&["https://sprig.hackclub.com"], &["*"]] {
+            let response = answer_with(origins, preflight());
+            assert_eq!(response.status_code(), StatusCode(204));
+            assert_eq!(
+                response_header(&response, "Access-Control-Allow-Origin").as_deref(),
+                Some("https://sprig.hackclub.com")
+            );
+        }
+    }
+
+    #[test]
+    fn websocket_origins() {
+        let upgrade = |origin: &str| {
+            TestRequest::new()
+                .with_path("/events")
+                .with_header(header("Origin", origin))
+                .with_header(header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="))
+                .into()
+        };
+
+        assert!(websocket(&[], upgrade("https://example.com")).is_none());
+        let origins = [String::from("https://sprig.hackclub.com")];
+        assert!(websocket(&origins, upgrade("https://example.com")).is_none());
+        assert!(websocket(&origins, upgrade("https://sprig.hackclub.com")).is_some());
+
+        let request = TestRequest::new().with_path("/events").into();
+        assert!(websocket(&origins, request).is_none());
+    }
+
+    #[test]
+    fn failure_statuses() {
+        for (failure, status) in [
+            (Failure::Usage(String::new()), 400),
+            (Failure::AmbiguousDevice(Vec::new()), 400),
+            (Failure::DeviceNotFound(String::new()), 404),
+            (Failure::OutOfFlash, 409),
+            (Failure::Rejected(String::from("NO")), 409),
+            (Failure::Timeout, 504),
+            (Failure::Io(String::new()), 502),
+        ] {
+            assert_eq!(failed(&failure).status_code(), StatusCode(status));
+        }
+    }
+
+    #[test]
+    fn query_params() {
+        assert_eq!(
+            query_param("device=%2Fdev%2FttyACM0", "device").as_deref(),
+            Some("/dev/ttyACM0")
+        );
+        assert_eq!(query_param("a=1&device=b", "device").as_deref(), Some("b"));
+        assert_eq!(query_param("devices=1", "device"), None);
+        assert_eq!(query_param("", "device"), None);
+
+        assert_eq!(percent_decode("COM3"), "COM3");
+        assert_eq!(percent_decode("my+sprig"), "my sprig");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%2"), "%zz%2");
+        assert_eq!(percent_decode("%C3%A9"), "é");
+        assert_eq!(percent_decode("%FF"), "\u{FFFD}");
+    }
+}
@@ -0,0 +1,101 @@
+use clap::Args;
+use serialport::SerialPortType;
+use spade_serial::{discover, probe, ProbeResult};
+use std::time::Instant;
+
+use crate::config::Config;
+use crate::device::open_quietly;
+use crate::failure::Failure;
+use crate::report::{millis, DeviceReport, Report, UsbReport};
+
+/// The arguments of the `info` command.
+#[derive(Args)]
+pub struct InfoArgs {
+    /// The serial port or alias of the Sprig device, or "auto" to use the one
+    /// serial port which could be a Sprig.
+    pub device: String,
+}
+
+/// Runs the `info` command with `args` and the defaults in `config`,
+/// recording what was found in `report`. Prints it unless `json` is `true`.
+///
+/// Spade does not report its version or how much space is left, so only the
+/// USB details of the port and whether the device runs a legacy Spade version
+/// are known. A device running a legacy version is left waiting for a game.
+pub fn run(
+    args: &InfoArgs,
+    config: &Config,
+    report: &mut Report,
+    json: bool,
+) -> Result<(), Failure> {
+    let start = Instant::now();
+    let (name, mut port) = open_quietly(&args.device, config)?;
+    let result = probe(&mut *port)?;
+    report.timings.connect_ms = Some(millis(start.elapsed()));
+
+    let legacy = match result {
+        ProbeResult::Modern => false,
+        ProbeResult::Legacy => true,
+        ProbeResult::NotSpade => {
+            return Err(Failure::DeviceNotFound(format!(
+                "The device at {name} did not respond like Spade."
+            )))
+        }
+    };
+    report.usb = usb_details(&name);
+    report.device = Some(DeviceReport { port: name, legacy });
+
+    if !json {
+        print_info(report);
+    }
+
+    Ok(())
+}
+
+/// Returns the USB details of the serial port `name`, if it is a USB port
+/// which could be a Sprig.
+fn usb_details(name: &str) -> Option<UsbReport> {
+    let port = discover()
+        .ok()?
+        .into_iter()
+        .find(|port| port.port_name == name)?;
+
+    match port.port_type {
+        SerialPortType::UsbPort(usb) => Some(UsbReport {
+            vid: usb.vid,
+            pid: usb.pid,
+            serial_number: usb.serial_number,
+            manufacturer: usb.manufacturer,
+            product: usb.product,
+        }),
+        _ => None,
+    }
+}
+
+/// Prints the device and USB details in `report`.
+fn print_info(report: &Report) {
+    if let Some(device) = &report.device {
+        println!("Port:           {}", device.port);
+        let version = match device.legacy {
+            true => "legacy version",
+            false => "current version",
+        };
+        println!("Spade:          {version}");
+    }
+
+    let Some(usb) = &report.usb else {
+        return;
+    };
+    let description = [usb.manufacturer.as_deref(), usb.product.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+    println!("USB ID:         {:04x}:{:04x}", usb.vid, usb.pid);
+    if !description.is_empty() {
+        println!("USB device:     {description}");
+    }
+    if let Some(serial_number) = &usb.serial_number {
+        println!("Serial number:  {serial_number}");
+    }
+}
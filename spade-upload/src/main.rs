@@ -1,52 +1,256 @@
-use clap::Parser;
-use spade_serial::{is_running_legacy, upload_game};
-use std::fs;
-use std::io::{stdin, Read, Write};
+mod alias;
+mod batch;
+mod bundle;
+mod config;
+mod device;
+mod doctor;
+mod failure;
+mod flash;
+#[cfg(feature = "online")]
+mod gallery;
+mod generate;
+#[cfg(feature = "http")]
+mod http;
+mod info;
+mod minify;
+mod monitor;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod multi;
+mod report;
+mod serve;
+mod transcript;
+mod upload;
+mod validate;
+mod watch;
+
+use alias::AliasCommand;
+use batch::BatchArgs;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use config::{Config, TimeoutProfile};
+use doctor::DoctorArgs;
+use failure::Failure;
+use flash::FlashArgs;
+#[cfg(feature = "http")]
+use http::ServeHttpArgs;
+use info::InfoArgs;
+use monitor::MonitorArgs;
+use report::{ErrorReport, Report};
+use serve::ServeArgs;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{stderr, stdin, IsTerminal};
 use std::path::PathBuf;
 use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tracing_subscriber::filter::LevelFilter;
+use upload::UploadArgs;
 
 /// Uploads games to a Sprig device running Spade using serial communications.
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
-struct Args {
-    /// The serial port of the Sprig device.
-    device: String,
-    /// The name that the game should appear under. Limited to 100 bytes.
-    name: String,
-    /// Path to the JavaScript source of a Sprig game. If not specified, the
-    /// game is read from stdin.
-    source: Option<PathBuf>,
+struct Cli {
+    /// Print JSON to stdout instead of messages and a progress bar: an object
+    /// describing the result of the command, preceded by an object for each
+    /// line printed by the device with `monitor`.
+    #[arg(long, global = true)]
+    json: bool,
+    /// Like --json, but also print an object for each step of an upload as
+    /// it happens, each on its own line, for programs showing the progress.
+    #[arg(long, global = true)]
+    porcelain: bool,
+    /// The baud rate to open serial ports at. Defaults to 115200.
+    #[arg(long, global = true, value_name = "RATE")]
+    baud: Option<u32>,
+    /// How long to wait for the device to respond, in milliseconds. Defaults
+    /// to 1000.
+    #[arg(long, global = true, value_name = "MS")]
+    timeout: Option<u64>,
+    /// How many times to try an upload again if the device stops responding
+    /// or communication fails. Defaults to 0.
+    #[arg(long, global = true, value_name = "COUNT")]
+    retries: Option<u32>,
+    /// A preset for how long to wait for the device, how many times to try
+    /// again and how to write games, for how reliable the connection is.
+    /// --timeout and --retries take precedence. Defaults to "default".
+    #[arg(long, global = true, value_enum, value_name = "PROFILE")]
+    timeout_profile: Option<TimeoutProfile>,
+    /// If the device is not connected yet, wait up to this many seconds for
+    /// it to be connected, or 60 if no number is passed.
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        num_args = 0..=1,
+        default_missing_value = "60"
+    )]
+    wait_for_device: Option<u64>,
+    /// Fail instead of asking which device to use when "auto" finds more than
+    /// one. This is the default if stdin is not a terminal, or with --json.
+    #[arg(long, global = true)]
+    non_interactive: bool,
+    /// Log more details to stderr: -v for retries and cancellations, -vv for
+    /// each step of the protocol and the responses of the device, and -vvv
+    /// for every write and everything read.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Only log errors to stderr.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Write everything sent to and received from the device to a file, as
+    /// timestamped hexadecimal and ASCII. Attach it to bug reports.
+    #[arg(long, global = true, value_name = "PATH")]
+    transcript: Option<PathBuf>,
+    /// Print a man page for spade-upload to stdout, as roff.
+    #[arg(long, exclusive = true, hide = true)]
+    generate_man: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Upload a game to the device.
+    Upload(UploadArgs),
+    /// Print the output of the device, like `console.log` calls and errors
+    /// from the running game. Runs until interrupted.
+    Monitor(MonitorArgs),
+    /// Upload each game listed in a manifest, then print a summary of the
+    /// results.
+    Batch(BatchArgs),
+    /// Check for common problems connecting to the device, and suggest how to
+    /// fix them.
+    Doctor(DoctorArgs),
+    /// Print what is known about the device: whether it runs a legacy Spade
+    /// version, and its USB details.
+    Info(InfoArgs),
+    /// Update the firmware of the device to a UF2 image of Spade, like to
+    /// upgrade from a legacy Spade version.
+    FlashFirmware(FlashArgs),
+    /// Answer JSON-RPC requests to upload games and monitor devices, keeping
+    /// the serial ports open between them. Reads requests from stdin unless
+    /// --socket or --listen is passed.
+    Serve(ServeArgs),
+    /// Answer HTTP requests to upload games and monitor devices, like from
+    /// Chromebooks on the same network as the device.
+    #[cfg(feature = "http")]
+    ServeHttp(ServeHttpArgs),
+    /// Name devices, so the names can be passed in place of their serial
+    /// ports.
+    Alias {
+        #[command(subcommand)]
+        command: AliasCommand,
+    },
+    /// Print the completions for a shell to stdout.
+    #[command(hide = true)]
+    Completions {
+        /// The shell to complete the arguments of spade-upload in.
+        #[arg(value_enum)]
+        shell: Shell,
+    },
 }
 
 fn main() -> ExitCode {
-    let args = Args::parse();
-
-    let mut port = serialport::new(args.device, 115200)
-        .timeout(Duration::from_millis(1000))
-        .open()
-        .unwrap();
-
-    if is_running_legacy(&mut port).unwrap() {
-        eprintln!("The device is a legacy Spade version.");
-
-        ExitCode::FAILURE
-    } else {
-        let game = match args.source {
-            Some(path) => fs::read_to_string(path).unwrap(),
-            None => {
-                let mut game = String::new();
-                stdin().read_to_string(&mut game).unwrap();
-                game
-            }
-        };
-
-        let upload = upload_game(&mut port, &args.name, &game);
-
-        if upload.is_ok() {
-            ExitCode::SUCCESS
-        } else {
-            ExitCode::FAILURE
+    let mut cli = parse_cli();
+    if let Some(Command::Upload(args)) = &mut cli.command {
+        args.resolve_legacy_order();
+    }
+    init_logging(cli.verbose, cli.quiet);
+
+    // --porcelain only adds to the output of --json.
+    let json = cli.json || cli.porcelain;
+    let mut report = Report::default();
+    let outcome = Config::load().and_then(|mut config| {
+        config.merge(Config {
+            baud: cli.baud,
+            timeout: cli.timeout,
+            retries: cli.retries,
+            timeout_profile: cli.timeout_profile,
+            ..Config::default()
+        });
+        config.porcelain = cli.porcelain;
+        config.device_wait = cli.wait_for_device.map(Duration::from_secs);
+        config.interactive =
+            !cli.non_interactive && !json && stdin().is_terminal() && stderr().is_terminal();
+        if let Some(path) = &cli.transcript {
+            let file = File::create(path).map_err(|err| {
+                Failure::Io(format!("Could not create {}: {err}", path.display()))
+            })?;
+            config.transcript = Some(Arc::new(Mutex::new(file)));
         }
+
+        match &cli.command {
+            Some(Command::Upload(args)) => upload::run(args, &config, &mut report, json),
+            Some(Command::Monitor(args)) => monitor::run(args, &config, json),
+            Some(Command::Batch(args)) => batch::run(args, &config, &mut report, json),
+            Some(Command::Doctor(args)) => doctor::run(args, &config, &mut report, json),
+            Some(Command::Info(args)) => info::run(args, &config, &mut report, json),
+            Some(Command::FlashFirmware(args)) => flash::run(args, &config, &mut report, json),
+            Some(Command::Serve(args)) => serve::run(args, &config),
+            #[cfg(feature = "http")]
+            Some(Command::ServeHttp(args)) => http::run(args, &config),
+            Some(Command::Alias { command }) => alias::run(command, &config, &mut report, json),
+            Some(Command::Completions { shell }) => generate::completions(*shell),
+            // A subcommand is required unless --generate-man is passed.
+            None => generate::man(),
+        }
+    });
+    report.success = outcome.is_ok();
+    if let Err(failure) = &outcome {
+        report.error = Some(ErrorReport::from(failure));
     }
+
+    if json {
+        println!("{}", serde_json::to_string(&report).unwrap());
+    } else if let Err(failure) = &outcome {
+        eprintln!("{failure}");
+    }
+
+    match outcome {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => ExitCode::from(failure.code()),
+    }
+}
+
+/// Parses the arguments of the process. Arguments without a subcommand are
+/// parsed as those of `upload`, like spade-upload took them before it had
+/// subcommands, with a warning that this is deprecated.
+fn parse_cli() -> Cli {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let err = match Cli::try_parse_from(&args) {
+        Ok(cli) if cli.command.is_some() || cli.generate_man => return cli,
+        Ok(_) => Cli::command().error(ErrorKind::MissingSubcommand, "A subcommand is required."),
+        Err(err) if err.kind() == ErrorKind::InvalidSubcommand => err,
+        Err(err) => err.exit(),
+    };
+
+    let mut upload_args = args.clone();
+    upload_args.insert(1.min(args.len()), OsString::from("upload"));
+    match Cli::try_parse_from(upload_args) {
+        Ok(cli) => {
+            eprintln!("Warning: Uploading without the upload subcommand is deprecated. Run `spade-upload upload` instead.");
+            cli
+        }
+        Err(_) => err.exit(),
+    }
+}
+
+/// Logs the events of the library to stderr, at the level set by `verbose`
+/// and `quiet`. By default, only warnings and errors are logged.
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => LevelFilter::WARN,
+        (false, 1) => LevelFilter::INFO,
+        (false, 2) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(stderr)
+        .init();
 }
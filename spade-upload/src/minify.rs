@@ -0,0 +1,386 @@
+/// Keywords after which a `/` starts a regular expression, not a division.
+const REGEX_KEYWORDS: [&str; 12] = [
+    "return",
+    "typeof",
+    "instanceof",
+    "in",
+    "of",
+    "new",
+    "delete",
+    "void",
+    "throw",
+    "case",
+    "do",
+    "else",
+];
+
+/// The header comment of Sprig games contains this, so comments containing it
+/// are kept.
+const HEADER: &str = "@title:";
+
+/// Removes comments and whitespace from the JavaScript source `source`, so it
+/// takes up less space on the device.
+///
+/// Line breaks are kept where they could end a statement, since Sprig games
+/// often leave out semicolons, and strings, template literals and regular
+/// expressions are copied as they are. Comments containing the `@title:`
+/// header are kept too.
+pub fn minify(source: &str) -> String {
+    Minifier {
+        chars: source.chars().collect(),
+        index: 0,
+        out: String::with_capacity(source.len()),
+        gap: Gap::None,
+        templates: Vec::new(),
+    }
+    .run()
+}
+
+/// The whitespace removed between the last character written and the next.
+#[derive(Clone, Copy, PartialEq)]
+enum Gap {
+    None,
+    Space,
+    LineBreak,
+}
+
+struct Minifier {
+    chars: Vec<char>,
+    index: usize,
+    out: String,
+    gap: Gap,
+    /// How many braces are open in each `${}` of the template literals the
+    /// minifier is in.
+    templates: Vec<usize>,
+}
+
+impl Minifier {
+    fn run(mut self) -> String {
+        while let Some(&c) = self.chars.get(self.index) {
+            match c {
+                c if c.is_whitespace() => {
+                    self.index += 1;
+                    self.widen(match c {
+                        '\n' | '\r' | '\u{2028}' | '\u{2029}' => Gap::LineBreak,
+                        _ => Gap::Space,
+                    });
+                }
+                '/' if self.peek(1) == Some('/') => self.line_comment(),
+                '/' if self.peek(1) == Some('*') => self.block_comment(),
+                '\'' | '"' => {
+                    self.separate(c);
+                    self.string(c);
+                }
+                '`' => {
+                    self.separate(c);
+                    self.index += 1;
+                    self.out.push('`');
+                    self.template();
+                }
+                '/' if self.regex_allowed() => {
+                    self.separate(c);
+                    self.regex();
+                }
+                '{' if !self.templates.is_empty() => {
+                    *self.templates.last_mut().unwrap() += 1;
+                    self.copy(c);
+                }
+                '}' if self.templates.last() == Some(&0) => {
+                    self.templates.pop();
+                    self.copy(c);
+                    self.template();
+                }
+                '}' if !self.templates.is_empty() => {
+                    *self.templates.last_mut().unwrap() -= 1;
+                    self.copy(c);
+                }
+                c => self.copy(c),
+            }
+        }
+
+        self.out
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.index + offset).copied()
+    }
+
+    /// Records that whitespace of at least `gap` was removed.
+    fn widen(&mut self, gap: Gap) {
+        if gap == Gap::LineBreak || self.gap == Gap::None {
+            self.gap = gap;
+        }
+    }
+
+    /// Writes `c` after the whitespace before it, if it is needed.
+    fn copy(&mut self, c: char) {
+        self.separate(c);
+        self.out.push(c);
+        self.index += 1;
+    }
+
+    /// Writes whatever the whitespace removed before `next` has to be
+    /// replaced with.
+    fn separate(&mut self, next: char) {
+        let gap = std::mem::replace(&mut self.gap, Gap::None);
+        let Some(last) = self.out.chars().last() else {
+            return;
+        };
+
+        match gap {
+            Gap::None => {}
+            // A line break could end a statement, unless the statement
+            // clearly continues.
+            Gap::LineBreak if !matches!(last, ';' | '{' | '(' | '[' | ',' | '\n') => {
+                if !matches!(next, ';' | '}' | ')' | ']' | ',' | '.') {
+                    self.out.push('\n');
+                }
+            }
+            Gap::LineBreak => {}
+            Gap::Space => {
+                let words = is_word(last) && is_word(next);
+                let operators = (last == '+' || last == '-') && last == next;
+                let number = last.is_ascii_digit() && next == '.';
+                if words || operators || number {
+                    self.out.push(' ');
+                }
+            }
+        }
+    }
+
+    fn line_comment(&mut self) {
+        let start = self.index;
+        while self.peek(0).is_some_and(|c| c != '\n') {
+            self.index += 1;
+        }
+        self.keep_header(start);
+    }
+
+    fn block_comment(&mut self) {
+        let start = self.index;
+        self.index += 2;
+        while self.index < self.chars.len()
+            && !(self.peek(0) == Some('*') && self.peek(1) == Some('/'))
+        {
+            self.index += 1;
+        }
+        self.index = (self.index + 2).min(self.chars.len());
+
+        if !self.keep_header(start) {
+            match self.chars[start..self.index].contains(&'\n') {
+                true => self.widen(Gap::LineBreak),
+                false => self.widen(Gap::Space),
+            }
+        }
+    }
+
+    /// Writes the comment starting at `start` if it contains the header.
+    /// Returns whether it did.
+    fn keep_header(&mut self, start: usize) -> bool {
+        let comment: String = self.chars[start..self.index].iter().collect();
+        if !comment.contains(HEADER) {
+            return false;
+        }
+
+        self.separate('/');
+        self.out.push_str(&comment);
+        self.widen(Gap::LineBreak);
+        true
+    }
+
+    /// Copies the string starting with the quote `quote`.
+    fn string(&mut self, quote: char) {
+        self.out.push(quote);
+        self.index += 1;
+        while let Some(c) = self.peek(0) {
+            self.out.push(c);
+            self.index += 1;
+            match c {
+                '\\' => {
+                    if let Some(escaped) = self.peek(0) {
+                        self.out.push(escaped);
+                        self.index += 1;
+                    }
+                }
+                // An unterminated string is left for the engine to report.
+                '\n' => return,
+                c if c == quote => return,
+                _ => {}
+            }
+        }
+    }
+
+    /// Copies a template literal from after its opening backtick or a `}`
+    /// closing a substitution, until its closing backtick or the start of a
+    /// substitution.
+    fn template(&mut self) {
+        while let Some(c) = self.peek(0) {
+            self.out.push(c);
+            self.index += 1;
+            match c {
+                '\\' => {
+                    if let Some(escaped) = self.peek(0) {
+                        self.out.push(escaped);
+                        self.index += 1;
+                    }
+                }
+                '`' => return,
+                '$' if self.peek(0) == Some('{') => {
+                    self.out.push('{');
+                    self.index += 1;
+                    self.templates.push(0);
+                    return;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Copies the regular expression literal starting here, without its
+    /// flags, which are copied like a word.
+    fn regex(&mut self) {
+        self.out.push('/');
+        self.index += 1;
+        let mut class = false;
+        while let Some(c) = self.peek(0) {
+            self.out.push(c);
+            self.index += 1;
+            match c {
+                '\\' => {
+                    if let Some(escaped) = self.peek(0) {
+                        self.out.push(escaped);
+                        self.index += 1;
+                    }
+                }
+                '[' => class = true,
+                ']' => class = false,
+                '/' if !class => return,
+                '\n' => return,
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether a `/` here starts a regular expression, judging by what came
+    /// before it.
+    fn regex_allowed(&self) -> bool {
+        let Some(last) = self.out.chars().last() else {
+            return true;
+        };
+        if !is_word(last) {
+            return !matches!(last, ')' | ']' | '}');
+        }
+
+        let word: String = self
+            .out
+            .chars()
+            .rev()
+            .take_while(|&c| is_word(c))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect();
+        REGEX_KEYWORDS.contains(&word.as_str())
+    }
+}
+
+/// Whether `c` can be part of an identifier, keyword or number.
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$' || !c.is_ascii()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn semicolon_less_game() {
+        let source = r#"
+const player = "p"
+
+setLegend(
+  [ player, bitmap`
+..00..
+.0..0.` ],
+)
+
+// Move the player.
+onInput("w", () => {
+  getFirst(player).y -= 1
+})
+
+let score = 0
+score++
+addText(`Score: ${score}`, { x: 1, y: 1 })
+"#;
+
+        assert_eq!(
+            minify(source),
+            r#"const player="p"
+setLegend([player,bitmap`
+..00..
+.0..0.`],)
+onInput("w",()=>{getFirst(player).y-=1})
+let score=0
+score++
+addText(`Score: ${score}`,{x:1,y:1})"#
+        );
+    }
+
+    #[test]
+    fn line_breaks_before_brackets() {
+        // Without a semicolon, these continue the statement, so the line
+        // break has to be kept for the source to mean the same.
+        assert_eq!(minify("a\n(b)"), "a\n(b)");
+        assert_eq!(minify("a\n[b]"), "a\n[b]");
+        assert_eq!(minify("a\n  .b()"), "a.b()");
+    }
+
+    #[test]
+    fn division_and_regex() {
+        assert_eq!(minify("x = a / b / c"), "x=a/b/c");
+        assert_eq!(minify("x = (a) / b"), "x=(a)/b");
+        assert_eq!(minify("return /re/g"), "return/re/g");
+        assert_eq!(minify("x = / [/ ] /.test(y)"), "x=/ [/ ] /.test(y)");
+        assert_eq!(minify("f(/ a // b /)"), "f(/ a // b /)");
+    }
+
+    #[test]
+    fn nested_template_braces() {
+        assert_eq!(minify("`${ {a:1}.a }`"), "`${{a:1}.a}`");
+        assert_eq!(minify("`a ${ `b ${ c }` } d`"), "`a ${`b ${c}`} d`");
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(minify("a - -b"), "a- -b");
+        assert_eq!(minify("a + ++b"), "a+ ++b");
+        assert_eq!(minify("a - +b"), "a-+b");
+    }
+
+    #[test]
+    fn number_members() {
+        assert_eq!(minify("1 .toString()"), "1 .toString()");
+        assert_eq!(minify("x .y"), "x.y");
+    }
+
+    #[test]
+    fn strings_and_comments() {
+        assert_eq!(
+            minify("let a = 'x  // y' /* c */ + \"z\\\"  \""),
+            "let a='x  // y'+\"z\\\"  \""
+        );
+        assert_eq!(minify("a /* c */ b"), "a b");
+        assert_eq!(minify("a /*\n*/ b"), "a\nb");
+    }
+
+    #[test]
+    fn header_comment() {
+        let source = "/*\n@title: Pond\n@author: Jo\n*/\n\n// Not kept.\nsetMap(map`.`)\n";
+        assert_eq!(
+            minify(source),
+            "/*\n@title: Pond\n@author: Jo\n*/\nsetMap(map`.`)"
+        );
+        assert_eq!(minify("// @title: Pond\nx = 1"), "// @title: Pond\nx=1");
+    }
+}
@@ -0,0 +1,170 @@
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use spade_serial::{attach_console, CancellationToken, ConsoleLine, RuntimeError, SpadeTransport};
+use std::io::{stdout, ErrorKind, IsTerminal, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::device::open_quietly;
+use crate::failure::Failure;
+use crate::report::millis;
+
+/// Dims the text after it on ANSI terminals.
+const DIM: &str = "\x1b[2m";
+/// Colors the text after it red on ANSI terminals.
+const RED: &str = "\x1b[31m";
+/// Resets the style of the text after it on ANSI terminals.
+const RESET: &str = "\x1b[0m";
+
+/// The arguments of the `monitor` command.
+#[derive(Args)]
+pub struct MonitorArgs {
+    /// The serial port or alias of the Sprig device, or "auto" to use the one
+    /// serial port which could be a Sprig.
+    pub device: String,
+    /// Print the output exactly as it is received, without timestamps or
+    /// colors.
+    #[arg(long, conflicts_with = "json")]
+    pub raw: bool,
+    /// When to color timestamps and runtime errors.
+    #[arg(long, value_enum, default_value_t = Color::Auto)]
+    pub color: Color,
+}
+
+/// When to color the output of `monitor`.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Color {
+    /// Color the output if stdout is a terminal.
+    Auto,
+    /// Always color the output.
+    Always,
+    /// Never color the output.
+    Never,
+}
+
+impl Color {
+    /// Returns whether the output should be colored.
+    fn enabled(self) -> bool {
+        match self {
+            Color::Auto => stdout().is_terminal(),
+            Color::Always => true,
+            Color::Never => false,
+        }
+    }
+}
+
+/// How runtime errors printed by the device are shown after uploading a
+/// game.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+    /// Like the rest of the output.
+    Human,
+    /// As `file:line:col: message`, referencing the source of the game, for
+    /// the problem matchers of VS Code tasks.
+    Vscode,
+}
+
+/// A line printed by the device, printed as JSON with `--json`.
+#[derive(Serialize)]
+struct LineReport {
+    /// How long after monitoring started the line was received.
+    elapsed_ms: u64,
+    text: String,
+    /// The runtime error printed on the line, if it is one.
+    error: Option<RuntimeError>,
+}
+
+/// Runs the `monitor` command with `args` and the defaults in `config`,
+/// printing each line as JSON if `json` is `true`. Runs until the device
+/// disconnects.
+pub fn run(args: &MonitorArgs, config: &Config, json: bool) -> Result<(), Failure> {
+    // Probing the device is skipped, since a legacy device would wait for a
+    // game afterwards instead of running the current one.
+    let (name, mut port) = open_quietly(&args.device, config)?;
+    if args.raw {
+        return dump(&mut *port);
+    }
+
+    if !json {
+        eprintln!("Monitoring {name}. Press Ctrl-C to stop.");
+    }
+
+    print_output(&mut *port, json, args.color, None)
+}
+
+/// Prints each line of output from `port` with the time since this was
+/// called, or as JSON if `json` is `true`. If `source` is passed, runtime
+/// errors are printed like `problem` instead. Runs until the device
+/// disconnects.
+pub fn print_output(
+    port: &mut dyn SpadeTransport,
+    json: bool,
+    color: Color,
+    source: Option<&Path>,
+) -> Result<(), Failure> {
+    let color = color.enabled();
+    let start = Instant::now();
+    attach_console(port, &CancellationToken::new(), |line| {
+        let elapsed = start.elapsed();
+        if json {
+            let report = LineReport {
+                elapsed_ms: millis(elapsed),
+                text: line.text().to_owned(),
+                error: line.runtime_error(),
+            };
+            println!("{}", serde_json::to_string(&report).unwrap());
+        } else if let Some(problem) = source.and_then(|source| problem(&line, source)) {
+            println!("{problem}");
+        } else {
+            println!("{}", format_line(&line, elapsed, color));
+        }
+    })?;
+
+    Ok(())
+}
+
+/// Formats `line` with the time since monitoring started, coloring runtime
+/// errors red if `color` is `true`.
+fn format_line(line: &ConsoleLine, elapsed: Duration, color: bool) -> String {
+    let timestamp = format!("[{:>10.3}]", elapsed.as_secs_f64());
+    match (color, line.runtime_error()) {
+        (false, _) => format!("{timestamp} {line}"),
+        (true, None) => format!("{DIM}{timestamp}{RESET} {line}"),
+        (true, Some(_)) => format!("{DIM}{timestamp}{RESET} {RED}{line}{RESET}"),
+    }
+}
+
+/// Formats the runtime error printed on `line` as `file:line:col: message`,
+/// where the file is `source`, or returns `None` if it is not a runtime error
+/// or has no line number. The column is 1 if it was not printed.
+pub fn problem(line: &ConsoleLine, source: &Path) -> Option<String> {
+    let error = line.runtime_error()?;
+    let column = error.column.unwrap_or(1);
+
+    Some(format!(
+        "{}:{}:{column}: {}",
+        source.display(),
+        error.line?,
+        error.message
+    ))
+}
+
+/// Copies everything read from `port` to stdout until the device
+/// disconnects.
+fn dump(port: &mut dyn SpadeTransport) -> Result<(), Failure> {
+    let mut stdout = stdout().lock();
+    let mut buf = [0; 256];
+    loop {
+        match port.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(bytes) => {
+                stdout.write_all(&buf[..bytes])?;
+                stdout.flush()?;
+            }
+            Err(err) if matches!(err.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {}
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use clap::Args;
+use rumqttc::{Client, MqttOptions, QoS};
+use serde_json::Value;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::failure::Failure;
+use crate::serve::Server;
+
+/// The port MQTT brokers listen on without TLS.
+const DEFAULT_PORT: u16 = 1883;
+/// How often the devices found are published.
+const INVENTORY_INTERVAL: Duration = Duration::from_secs(30);
+/// How long to wait before connecting to the broker again after failing.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// The arguments for publishing to an MQTT broker, shared by `serve` and
+/// `serve-http`.
+#[derive(Args)]
+pub struct MqttArgs {
+    /// Publish the devices found, the outcome of each upload and the output
+    /// of monitored devices to the MQTT broker at this address, like
+    /// localhost:1883.
+    #[arg(long, value_name = "HOST[:PORT]")]
+    pub mqtt: Option<String>,
+    /// The topic the MQTT messages are published under.
+    #[arg(long, value_name = "TOPIC", default_value = "spade")]
+    pub mqtt_topic: String,
+}
+
+/// Publishes the events of `server` to the broker in `args`, if there is
+/// one, on other threads:
+///
+/// - `<topic>/devices`: the result of `devices`, retained and published
+///   every `INVENTORY_INTERVAL`
+/// - `<topic>/device/<port>/upload`: the `uploaded` event of each upload
+/// - `<topic>/device/<port>/console`: each line printed by a monitored device
+///
+/// Messages are dropped while the broker cannot be reached.
+pub fn start(args: &MqttArgs, server: &Arc<Server>) -> Result<(), Failure> {
+    let Some(broker) = &args.mqtt else {
+        return Ok(());
+    };
+    let (host, port) = match broker.rsplit_once(':') {
+        Some((host, port)) => {
+            let port = port.parse().map_err(|_| {
+                Failure::Usage(format!(
+                    "The port of the MQTT broker {broker} is not valid."
+                ))
+            })?;
+            (host, port)
+        }
+        None => (broker.as_str(), DEFAULT_PORT),
+    };
+
+    let mut options = MqttOptions::new(format!("spade-upload-{}", process::id()), host, port);
+    options.set_keep_alive(Duration::from_secs(30));
+    let (client, mut connection) = Client::new(options, 64);
+    // Messages are only sent while the events of the connection are read,
+    // which also connects again after failing.
+    let broker = broker.clone();
+    thread::spawn(move || {
+        for event in connection.iter() {
+            if let Err(err) = event {
+                eprintln!("Could not reach the MQTT broker at {broker}: {err}");
+                thread::sleep(RECONNECT_DELAY);
+            }
+        }
+    });
+
+    let topic = args.mqtt_topic.trim_end_matches('/').to_owned();
+    server.subscribe(
+        Arc::new({
+            let client = client.clone();
+            let topic = topic.clone();
+            move |event, params| {
+                if let Some(topic) = event_topic(&topic, event, &params) {
+                    let payload = serde_json::to_vec(&params).expect("events serialize to JSON");
+                    let _ = client.try_publish(topic, QoS::AtMostOnce, false, payload);
+                }
+                true
+            }
+        }),
+        server.connect(),
+    );
+
+    let server = Arc::clone(server);
+    thread::spawn(move || loop {
+        if let Ok(devices) = server.devices() {
+            let payload = serde_json::to_vec(&devices).expect("devices serialize to JSON");
+            let _ = client.try_publish(format!("{topic}/devices"), QoS::AtLeastOnce, true, payload);
+        }
+        thread::sleep(INVENTORY_INTERVAL);
+    });
+
+    Ok(())
+}
+
+/// Returns the topic under `topic` to publish the event `event` with
+/// `params` to, or `None` if it is not published.
+fn event_topic(topic: &str, event: &str, params: &Value) -> Option<String> {
+    let name = match event {
+        "console" => "console",
+        "uploaded" => "upload",
+        _ => return None,
+    };
+    let port = params.get("port")?.as_str()?;
+
+    Some(format!("{topic}/device/{}/{name}", topic_level(port)))
+}
+
+/// Converts the serial port `port` into a single topic level, since serial
+/// ports like `/dev/ttyACM0` contain slashes.
+fn topic_level(port: &str) -> String {
+    port.trim_start_matches('/').replace(['/', '+', '#'], "_")
+}
@@ -0,0 +1,133 @@
+use indicatif::MultiProgress;
+use spade_serial::{Game, UploadResult};
+use std::thread;
+use std::time::Instant;
+
+use crate::config::Config;
+use crate::device::{find_all, Device};
+use crate::failure::{check_result, Failure};
+use crate::report::{
+    millis, outcome, print_summary, DeviceEntry, ErrorReport, GameReport, Report, TransferReport,
+};
+use crate::upload::{progress_bar, send};
+
+/// The name of a device and the result of connecting to it.
+type Connection = (String, Result<Device, Failure>);
+
+/// Returns whether `device` names several devices: `"all"` for every
+/// connected device running Spade, or a comma-separated list of serial ports
+/// or aliases.
+pub fn names_several(device: &str) -> bool {
+    device == "all" || device.contains(',')
+}
+
+/// Connects to each of the devices named by `device` with the settings in
+/// `config`, returning the name of each and the result of connecting to it.
+fn connect_all(device: &str, config: &Config) -> Result<Vec<Connection>, Failure> {
+    if device == "all" {
+        let devices = find_all(config)?;
+        return Ok(devices
+            .into_iter()
+            .map(|device| (device.name.clone(), Ok(device)))
+            .collect());
+    }
+
+    Ok(device
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| (name.to_owned(), Device::connect(name, config)))
+        .collect())
+}
+
+/// Uploads `game` to each of the devices named by `device` at once, with the
+/// settings in `config`, recording the result for each in `report`. Unless
+/// `json` is `true`, a progress bar is shown for each device, then a summary
+/// of the results.
+///
+/// A device which fails does not stop the others. If any fail, the failure of
+/// the first one listed is returned.
+pub fn upload_all(
+    device: &str,
+    config: &Config,
+    game: &Game,
+    report: &mut Report,
+    json: bool,
+) -> Result<(), Failure> {
+    let start = Instant::now();
+    let devices = connect_all(device, config)?;
+    report.timings.connect_ms = Some(millis(start.elapsed()));
+    report.game = Some(GameReport {
+        name: game.name().as_str().to_owned(),
+        bytes: game.source().len(),
+    });
+
+    let bars = MultiProgress::new();
+    let start = Instant::now();
+    let results: Vec<_> = thread::scope(|scope| {
+        let uploads: Vec<_> = devices
+            .into_iter()
+            .map(|(name, device)| {
+                let bar = match json {
+                    true => progress_bar(game.source().len(), true),
+                    false => bars.add(progress_bar(game.source().len(), false)),
+                };
+                bar.set_prefix(format!("{name} "));
+
+                let upload = scope.spawn(move || {
+                    let result = match device {
+                        Ok(device) if device.legacy => Err(Failure::Legacy),
+                        Ok(mut device) => {
+                            send(&mut device, game, config, &bar).map_err(Failure::from)
+                        }
+                        Err(failure) => Err(failure),
+                    };
+                    bar.finish_and_clear();
+                    result
+                });
+                (name, upload)
+            })
+            .collect();
+
+        uploads
+            .into_iter()
+            .map(|(name, upload)| (name, upload.join().expect("upload thread panicked")))
+            .collect()
+    });
+    report.timings.upload_ms = Some(millis(start.elapsed()));
+
+    let mut first_failure = None;
+    let mut entries = Vec::with_capacity(results.len());
+    for (port, result) in results {
+        let (result, transfer, failure): (Option<UploadResult>, _, _) = match result {
+            Ok(upload) => (
+                Some(upload.result.clone()),
+                Some(TransferReport::from(&upload)),
+                check_result(upload.result).err(),
+            ),
+            Err(failure) => (None, None, Some(failure)),
+        };
+        entries.push(DeviceEntry {
+            port,
+            result,
+            transfer,
+            error: failure.as_ref().map(ErrorReport::from),
+        });
+        if let Some(failure) = failure {
+            first_failure.get_or_insert(failure);
+        }
+    }
+
+    if !json {
+        let rows = entries
+            .iter()
+            .map(|entry| (entry.port.as_str(), outcome(&entry.result, &entry.error)));
+        print_summary("DEVICE", rows);
+    }
+    report.devices = Some(entries);
+
+    match first_failure {
+        Some(failure) => Err(failure),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,206 @@
+use serde::Serialize;
+use spade_serial::{UploadReport, UploadResult};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::config::Alias;
+use crate::failure::Failure;
+
+/// What happened while running a command, printed as JSON with `--json`.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    /// Whether the command succeeded.
+    pub success: bool,
+    /// The device the command used, once it was found.
+    pub device: Option<DeviceReport>,
+    /// The USB details of the device found by `info`, if it is connected
+    /// over USB.
+    pub usb: Option<UsbReport>,
+    /// The game being uploaded, once it was read.
+    pub game: Option<GameReport>,
+    /// The response from the device to the upload, if it responded.
+    pub result: Option<UploadResult>,
+    /// How fast the game was sent, if the device responded.
+    pub transfer: Option<TransferReport>,
+    /// Each game in the manifest of `batch`, once they were read.
+    pub games: Option<Vec<BatchEntry>>,
+    /// Each device uploaded to, when uploading to several at once.
+    pub devices: Option<Vec<DeviceEntry>>,
+    /// Each check run by `doctor`, once they were run.
+    pub checks: Option<Vec<CheckReport>>,
+    /// The aliases listed by `alias list`.
+    pub aliases: Option<BTreeMap<String, Alias>>,
+    /// How long each part of the command took.
+    pub timings: Timings,
+    /// Why the command failed, if it did.
+    pub error: Option<ErrorReport>,
+}
+
+/// The device a command used.
+#[derive(Debug, Serialize)]
+pub struct DeviceReport {
+    /// The serial port the device is connected to.
+    pub port: String,
+    /// Whether the device is running a legacy Spade version.
+    pub legacy: bool,
+}
+
+/// The USB details of a device.
+#[derive(Debug, Serialize)]
+pub struct UsbReport {
+    /// The USB vendor ID.
+    pub vid: u16,
+    /// The USB product ID.
+    pub pid: u16,
+    /// The serial number of the device, which tells apart devices of the
+    /// same kind.
+    pub serial_number: Option<String>,
+    /// Who made the device.
+    pub manufacturer: Option<String>,
+    /// What the device is called.
+    pub product: Option<String>,
+}
+
+/// The game being uploaded.
+#[derive(Debug, Serialize)]
+pub struct GameReport {
+    /// The name the game is uploaded under.
+    pub name: String,
+    /// The size of the game source.
+    pub bytes: usize,
+}
+
+/// A game uploaded by `batch`.
+#[derive(Debug, Serialize)]
+pub struct BatchEntry {
+    /// The name the game is uploaded under.
+    pub name: String,
+    /// The response from the device to the upload, if it responded.
+    pub result: Option<UploadResult>,
+    /// Why the game was not uploaded, if it was tried and failed. Games
+    /// skipped after an earlier failure have neither a result nor an error.
+    pub error: Option<ErrorReport>,
+}
+
+/// A device uploaded to when uploading to several at once.
+#[derive(Debug, Serialize)]
+pub struct DeviceEntry {
+    /// The serial port the device is connected to.
+    pub port: String,
+    /// The response from the device to the upload, if it responded.
+    pub result: Option<UploadResult>,
+    /// How fast the game was sent to the device, if it responded.
+    pub transfer: Option<TransferReport>,
+    /// Why the game was not uploaded to the device, if it failed.
+    pub error: Option<ErrorReport>,
+}
+
+/// How fast a game was sent to a device, to spot slow cables and hubs.
+#[derive(Debug, Serialize)]
+pub struct TransferReport {
+    /// The bytes written to the device, including those of retries.
+    pub bytes_sent: usize,
+    /// How many times the upload was retried.
+    pub retries: u32,
+    /// The rate the game was sent at, in bits per second.
+    pub effective_baud: u64,
+}
+
+impl From<&UploadReport> for TransferReport {
+    fn from(value: &UploadReport) -> Self {
+        TransferReport {
+            bytes_sent: value.bytes_sent,
+            retries: value.retries,
+            effective_baud: value.effective_baud() as u64,
+        }
+    }
+}
+
+/// A check run by `doctor`.
+#[derive(Debug, Serialize)]
+pub struct CheckReport {
+    /// A name for what was checked, which does not change between versions.
+    pub check: &'static str,
+    /// The serial port checked, if the check is for one.
+    pub port: Option<String>,
+    /// Whether the check passed.
+    pub status: CheckStatus,
+    /// What was found.
+    pub message: String,
+    /// How to fix the problem found, if there is one.
+    pub hint: Option<String>,
+}
+
+/// Whether a check run by `doctor` passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    /// Nothing is wrong.
+    Pass,
+    /// Something may cause problems, but uploading can still work.
+    Warn,
+    /// Something stops uploading from working.
+    Fail,
+}
+
+/// How long each part of a command took, in milliseconds.
+#[derive(Debug, Default, Serialize)]
+pub struct Timings {
+    /// Finding and opening the device, and checking its Spade version.
+    pub connect_ms: Option<u64>,
+    /// Writing the game and waiting for the device to respond.
+    pub upload_ms: Option<u64>,
+}
+
+/// Why a command failed.
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    /// A name for the kind of failure, which does not change between
+    /// versions.
+    pub kind: &'static str,
+    /// The exit code of the process.
+    pub exit_code: u8,
+    /// The message which would have been printed without `--json`.
+    pub message: String,
+}
+
+impl From<&Failure> for ErrorReport {
+    fn from(value: &Failure) -> Self {
+        ErrorReport {
+            kind: value.kind(),
+            exit_code: value.code(),
+            message: value.to_string(),
+        }
+    }
+}
+
+/// Converts `duration` to whole milliseconds.
+pub fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+/// Describes the outcome of uploading a game with `result` and `error` in a
+/// word or two, for summaries.
+pub fn outcome(result: &Option<UploadResult>, error: &Option<ErrorReport>) -> &'static str {
+    match (result, error) {
+        (_, Some(error)) => error.kind,
+        (Some(_), None) => "uploaded",
+        (None, None) => "skipped",
+    }
+}
+
+/// Prints a table of `rows`, each a name and the outcome of uploading to or
+/// with it. `heading` is printed above the names.
+pub fn print_summary<'a>(heading: &str, rows: impl Iterator<Item = (&'a str, &'a str)> + Clone) {
+    let width = rows
+        .clone()
+        .map(|(name, _)| name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(heading.len());
+
+    println!("{heading:width$}  RESULT");
+    for (name, outcome) in rows {
+        println!("{name:width$}  {outcome}");
+    }
+}
@@ -0,0 +1,754 @@
+use clap::Args;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use spade_serial::{
+    attach_console, discover, upload_game_reported, CancellationToken, Game, RuntimeError,
+    UploadError, UploadResult,
+};
+use spade_serial::{GameError, GameMetadata, GameName};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, stdin, stdout, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+
+use crate::config::Config;
+use crate::device::{resolve_port, Device};
+use crate::failure::{check_result, Failure};
+#[cfg(feature = "mqtt")]
+use crate::mqtt::{self, MqttArgs};
+use crate::report::{ErrorReport, GameReport, TransferReport};
+use crate::upload::{upload_options, ProgressReport};
+
+/// The error code for a request which is not valid JSON.
+const PARSE_ERROR: i64 = -32700;
+/// The error code for JSON which is not a request.
+const INVALID_REQUEST: i64 = -32600;
+/// The error code for a request for a method which does not exist.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// The error code for a request with the wrong parameters for its method.
+const INVALID_PARAMS: i64 = -32602;
+/// The error code for a request which failed like a command would, with the
+/// failure in the data of the error.
+const COMMAND_FAILED: i64 = -32000;
+
+/// The arguments of the `serve` command.
+#[derive(Args)]
+pub struct ServeArgs {
+    /// Listen for connections on a Unix domain socket at this path, instead
+    /// of reading requests from stdin.
+    #[cfg(unix)]
+    #[arg(long, value_name = "PATH", conflicts_with = "listen")]
+    pub socket: Option<PathBuf>,
+    /// Listen for TCP connections at this address, like 127.0.0.1:7330,
+    /// instead of reading requests from stdin. Anyone who can connect can
+    /// upload games, so only listen on addresses you trust.
+    #[arg(long, value_name = "ADDR")]
+    pub listen: Option<String>,
+    #[cfg(feature = "mqtt")]
+    #[command(flatten)]
+    pub mqtt: MqttArgs,
+}
+
+/// A JSON-RPC 2.0 request, or a notification if it has no ID.
+#[derive(Deserialize)]
+struct Request {
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// A JSON-RPC 2.0 response to a request.
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+/// A JSON-RPC 2.0 notification sent by the server, like for a line printed
+/// by a monitored device.
+#[derive(Serialize)]
+struct Notification {
+    jsonrpc: &'static str,
+    method: &'static str,
+    params: Value,
+}
+
+/// Why a request failed.
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+    /// How the request failed, like `--json` reports the failure of a
+    /// command, if it got as far as talking to a device.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<ErrorReport>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl From<Failure> for RpcError {
+    fn from(value: Failure) -> Self {
+        RpcError {
+            code: COMMAND_FAILED,
+            message: value.to_string(),
+            data: Some(ErrorReport::from(&value)),
+        }
+    }
+}
+
+/// The parameters of methods which take only a device.
+#[derive(Deserialize)]
+struct DeviceParams {
+    /// The serial port or alias of the device, or "auto".
+    device: String,
+}
+
+/// The parameters of `upload`.
+#[derive(Deserialize)]
+pub struct UploadParams {
+    /// The serial port or alias of the device, or "auto".
+    device: String,
+    /// The source of the game.
+    source: String,
+    /// The name to upload the game under. Defaults to the `@title:` header
+    /// of the game.
+    name: Option<String>,
+}
+
+/// A serial port which could be a device, in the result of `devices`.
+#[derive(Serialize)]
+pub struct DeviceInfo {
+    port: String,
+    /// Whether the server is holding the port open.
+    open: bool,
+    /// Whether the output of the device is being sent to a client.
+    monitored: bool,
+}
+
+/// The result of `upload`.
+#[derive(Serialize)]
+pub struct UploadResponse {
+    port: String,
+    game: GameReport,
+    transfer: TransferReport,
+}
+
+/// The result of `monitor` and `stop_monitor`.
+#[derive(Serialize)]
+struct MonitorResponse {
+    port: String,
+}
+
+/// The parameters of the `console` event, for a line printed by a monitored
+/// device.
+#[derive(Serialize)]
+struct ConsoleParams {
+    port: String,
+    text: String,
+    /// The runtime error printed on the line, if it is one.
+    error: Option<RuntimeError>,
+}
+
+/// The parameters of the `monitor_stopped` event, sent when reading from a
+/// monitored device fails.
+#[derive(Serialize)]
+struct MonitorStoppedParams {
+    port: String,
+    error: ErrorReport,
+}
+
+/// The parameters of the `uploaded` event, sent when an upload finishes.
+#[derive(Serialize)]
+struct UploadedParams {
+    port: String,
+    game: GameReport,
+    /// The response of the device, if it got as far as responding.
+    result: Option<UploadResult>,
+    /// Why the upload failed, if it did.
+    error: Option<ErrorReport>,
+}
+
+/// Where messages to a client are written.
+type Output = Arc<Mutex<dyn Write + Send>>;
+
+/// Where the events of a monitor are sent, as the name of the event and its
+/// parameters. Returns `false` once the client has gone.
+pub type Sink = Arc<dyn Fn(&'static str, Value) -> bool + Send + Sync>;
+
+/// The clients sent every event of the server, with their connections.
+type Subscribers = Arc<Mutex<Vec<(usize, Sink)>>>;
+
+/// A thread sending the output of a device to a client.
+struct Monitor {
+    token: CancellationToken,
+    thread: JoinHandle<()>,
+    /// The connection of the client, so the monitor can be stopped when it
+    /// disconnects.
+    connection: usize,
+    sink: Sink,
+}
+
+/// The devices open for clients, shared by every connection to the server.
+pub struct Server {
+    config: Config,
+    /// The devices which have been connected to, by serial port, kept open
+    /// so they are not found and checked again for every request.
+    devices: Mutex<HashMap<String, Arc<Mutex<Device>>>>,
+    /// The devices being monitored, by serial port.
+    monitors: Mutex<HashMap<String, Monitor>>,
+    /// The clients sent every event, with their connections.
+    subscribers: Subscribers,
+    /// The number given to the next client to connect.
+    next_connection: AtomicUsize,
+}
+
+/// Runs the `serve` command with `args` and the defaults in `config`. Runs
+/// until stdin ends, or until interrupted when listening on a socket.
+pub fn run(args: &ServeArgs, config: &Config) -> Result<(), Failure> {
+    let server = Arc::new(Server::new(config));
+    #[cfg(feature = "mqtt")]
+    mqtt::start(&args.mqtt, &server)?;
+
+    #[cfg(unix)]
+    if let Some(path) = &args.socket {
+        let listener = UnixListener::bind(path)
+            .map_err(|err| Failure::Io(format!("Could not listen on {}: {err}", path.display())))?;
+        eprintln!("Listening on {}.", path.display());
+        accept(&server, listener.incoming(), UnixStream::try_clone);
+        return Ok(());
+    }
+
+    if let Some(address) = &args.listen {
+        let listener = TcpListener::bind(address)
+            .map_err(|err| Failure::Io(format!("Could not listen on {address}: {err}")))?;
+        eprintln!("Listening on {}.", listener.local_addr()?);
+        accept(&server, listener.incoming(), TcpStream::try_clone);
+        return Ok(());
+    }
+
+    server.handle(stdin().lock(), Arc::new(Mutex::new(stdout())));
+    Ok(())
+}
+
+/// Answers the requests of each client connecting through `incoming` on its
+/// own thread. Connections which fail before they are answered are skipped.
+fn accept<S: Read + Write + Send + 'static>(
+    server: &Arc<Server>,
+    incoming: impl Iterator<Item = io::Result<S>>,
+    try_clone: impl Fn(&S) -> io::Result<S>,
+) {
+    for stream in incoming {
+        let Ok((stream, input)) = stream.and_then(|stream| Ok((try_clone(&stream)?, stream)))
+        else {
+            continue;
+        };
+        let server = Arc::clone(server);
+        thread::spawn(move || server.handle(BufReader::new(input), Arc::new(Mutex::new(stream))));
+    }
+}
+
+impl Server {
+    /// Creates a server using the settings in `config`, with no devices open.
+    pub fn new(config: &Config) -> Self {
+        let mut config = config.clone();
+        // Nobody is there to choose between devices, and stdout may be
+        // carrying responses.
+        config.interactive = false;
+        config.porcelain = false;
+
+        Server {
+            config,
+            devices: Mutex::new(HashMap::new()),
+            monitors: Mutex::new(HashMap::new()),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            next_connection: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns a number for a client which has connected, to stop its
+    /// monitors with `disconnect` once it has gone.
+    pub fn connect(&self) -> usize {
+        self.next_connection.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Stops the monitors started by the client `connection`, and stops
+    /// sending it events.
+    pub fn disconnect(&self, connection: usize) {
+        lock(&self.subscribers).retain(|(subscriber, _)| *subscriber != connection);
+        let monitors: Vec<_> = lock(&self.monitors)
+            .iter()
+            .filter(|(_, monitor)| monitor.connection == connection)
+            .map(|(port, _)| port.clone())
+            .collect();
+        for port in monitors {
+            self.end_monitor(&port);
+        }
+    }
+
+    /// Answers each JSON-RPC request read from `input`, one per line, on
+    /// `output` until `input` ends, then stops the monitors the client
+    /// started.
+    fn handle(&self, input: impl BufRead, output: Output) {
+        let connection = self.connect();
+        for line in input.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.respond(&line, &output, connection) {
+                write_message(&output, &response);
+            }
+        }
+
+        self.disconnect(connection);
+    }
+
+    /// Runs the request `line`, returning the response to it, or `None` if
+    /// it is a notification.
+    fn respond(&self, line: &str, output: &Output, connection: usize) -> Option<Response> {
+        let request = match serde_json::from_str::<Value>(line) {
+            Ok(request) => serde_json::from_value::<Request>(request)
+                .map_err(|err| RpcError::new(INVALID_REQUEST, err.to_string())),
+            Err(err) => Err(RpcError::new(PARSE_ERROR, err.to_string())),
+        };
+        let request = match request {
+            Ok(request) => request,
+            Err(error) => return Some(response(Value::Null, Err(error))),
+        };
+
+        let result = match request.method.as_str() {
+            "devices" => self.devices().map(to_value).map_err(RpcError::from),
+            "upload" => {
+                params(request.params).and_then(|params| Ok(to_value(self.upload(params)?)))
+            }
+            "monitor" => params(request.params).and_then(|params: DeviceParams| {
+                let output = Arc::clone(output);
+                let sink: Sink = Arc::new(move |method, params| notify(&output, method, params));
+                let port = self.monitor(&params.device, sink, connection)?;
+                Ok(to_value(MonitorResponse { port }))
+            }),
+            "subscribe" => {
+                let output = Arc::clone(output);
+                self.subscribe(
+                    Arc::new(move |method, params| notify(&output, method, params)),
+                    connection,
+                );
+                Ok(Value::Null)
+            }
+            "stop_monitor" => params(request.params).and_then(|params: DeviceParams| {
+                let port = self.stop_monitor(&params.device)?;
+                Ok(to_value(MonitorResponse { port }))
+            }),
+            // Spade only accepts uploads over serial, so there is no way to
+            // ask the device for these.
+            "list" | "delete" => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                "Spade cannot list or delete games over serial. Manage them on the device.",
+            )),
+            method => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("There is no method called {method}."),
+            )),
+        };
+
+        request.id.map(|id| response(id, result))
+    }
+
+    /// Lists the serial ports which could be devices, and the ones already
+    /// open.
+    pub fn devices(&self) -> Result<Vec<DeviceInfo>, Failure> {
+        let mut ports: BTreeSet<_> = discover()
+            .map_err(|err| Failure::DeviceNotFound(err.to_string()))?
+            .into_iter()
+            .map(|port| port.port_name)
+            .collect();
+        let devices = lock(&self.devices);
+        let monitors = lock(&self.monitors);
+        ports.extend(devices.keys().cloned());
+
+        Ok(ports
+            .into_iter()
+            .map(|port| DeviceInfo {
+                open: devices.contains_key(&port),
+                monitored: monitors
+                    .get(&port)
+                    .is_some_and(|monitor| !monitor.thread.is_finished()),
+                port,
+            })
+            .collect())
+    }
+
+    /// Uploads the game in `params`. If the device is being monitored, the
+    /// monitor is paused while uploading, then shows the output of the new
+    /// game.
+    pub fn upload(&self, params: UploadParams) -> Result<UploadResponse, Failure> {
+        let name = match &params.name {
+            Some(name) => GameName::new(name).map_err(GameError::from)?,
+            None => {
+                let title = GameMetadata::parse(&params.source).title.ok_or_else(|| {
+                    Failure::Usage(String::from(
+                        "The game has no @title: header to name it after. Pass a name.",
+                    ))
+                })?;
+                GameName::truncate_lossy(&title)
+            }
+        };
+        let game = Game::new(name, params.source)?;
+
+        let port = resolve_port(&params.device, &self.config)?;
+        let device = self.device(&port)?;
+        let monitor = self.end_monitor(&port);
+
+        let options = upload_options(&self.config).on_progress({
+            let subscribers = Arc::clone(&self.subscribers);
+            let port = port.clone();
+            let total = game.source().len();
+            move |progress| {
+                let report = ProgressReport::new(&port, total, progress);
+                publish(&subscribers, "progress", to_value(report));
+            }
+        });
+        let upload = upload_game_reported(&mut *lock(&device).port, &game, &options);
+        // The port is most likely gone, so it is opened again next time.
+        if matches!(
+            upload,
+            Err(UploadError::IOError | UploadError::Disconnected)
+        ) {
+            lock(&self.devices).remove(&port);
+        } else if let Some((sink, connection)) = monitor {
+            self.start_monitor(&port, device, sink, connection);
+        }
+
+        let game_report = || GameReport {
+            name: game.name().as_str().to_owned(),
+            bytes: game.source().len(),
+        };
+        let result = upload.as_ref().ok().map(|upload| upload.result.clone());
+        let upload = upload.map_err(Failure::from).and_then(|upload| {
+            check_result(upload.result.clone())?;
+            Ok(upload)
+        });
+        let params = UploadedParams {
+            port: port.clone(),
+            game: game_report(),
+            result,
+            error: upload.as_ref().err().map(ErrorReport::from),
+        };
+        publish(&self.subscribers, "uploaded", to_value(params));
+
+        Ok(UploadResponse {
+            port,
+            game: game_report(),
+            transfer: TransferReport::from(&upload?),
+        })
+    }
+
+    /// Starts sending each line printed by `device` to `sink` as a `console`
+    /// event, for the client `connection`. Returns the serial port of the
+    /// device.
+    pub fn monitor(&self, device: &str, sink: Sink, connection: usize) -> Result<String, Failure> {
+        let port = resolve_port(device, &self.config)?;
+        let device = self.device(&port)?;
+        self.end_monitor(&port);
+        self.start_monitor(&port, device, sink, connection);
+
+        Ok(port)
+    }
+
+    /// Stops monitoring `device`, if it is being monitored. Returns the
+    /// serial port of the device.
+    pub fn stop_monitor(&self, device: &str) -> Result<String, Failure> {
+        let port = resolve_port(device, &self.config)?;
+        self.end_monitor(&port);
+
+        Ok(port)
+    }
+
+    /// Sends every event of the server to `sink` for the client
+    /// `connection`: the output of each monitored device as `console`
+    /// events, each step of every upload as `progress` events, and the
+    /// outcome of every upload as an `uploaded` event. Stops
+    /// once `sink` returns `false` or the client disconnects.
+    pub fn subscribe(&self, sink: Sink, connection: usize) {
+        lock(&self.subscribers).push((connection, sink));
+    }
+
+    /// Returns the device at the serial port `port`, connecting to it if it
+    /// is not open yet. Legacy devices are not supported.
+    fn device(&self, port: &str) -> Result<Arc<Mutex<Device>>, Failure> {
+        if let Some(device) = lock(&self.devices).get(port) {
+            return Ok(Arc::clone(device));
+        }
+
+        // Connecting takes seconds, so it is done without holding up requests
+        // for other devices.
+        let device = Device::connect(port, &self.config)?;
+        if device.legacy {
+            return Err(Failure::Legacy);
+        }
+        // If another request connected to the device meanwhile, its
+        // connection is kept.
+        let mut devices = lock(&self.devices);
+        let device = devices
+            .entry(port.to_owned())
+            .or_insert_with(|| Arc::new(Mutex::new(device)));
+        Ok(Arc::clone(device))
+    }
+
+    /// Sends the output of `device` to `sink` on another thread until
+    /// `end_monitor` is called with `port`, or the client has gone.
+    fn start_monitor(&self, port: &str, device: Arc<Mutex<Device>>, sink: Sink, connection: usize) {
+        let token = CancellationToken::new();
+        let thread = thread::spawn({
+            let token = token.clone();
+            let sink = Arc::clone(&sink);
+            let subscribers = Arc::clone(&self.subscribers);
+            let port = port.to_owned();
+            move || {
+                let result = attach_console(&mut *lock(&device).port, &token, |line| {
+                    let params = ConsoleParams {
+                        port: port.clone(),
+                        text: line.text().to_owned(),
+                        error: line.runtime_error(),
+                    };
+                    let params = to_value(params);
+                    publish(&subscribers, "console", params.clone());
+                    if !sink("console", params) {
+                        token.cancel();
+                    }
+                });
+                if let Err(err) = result {
+                    let params = MonitorStoppedParams {
+                        port,
+                        error: ErrorReport::from(&Failure::from(err)),
+                    };
+                    sink("monitor_stopped", to_value(params));
+                }
+            }
+        });
+
+        let monitor = Monitor {
+            token,
+            thread,
+            connection,
+            sink,
+        };
+        lock(&self.monitors).insert(port.to_owned(), monitor);
+    }
+
+    /// Stops monitoring the device at `port`, waiting for it to be released.
+    /// Returns where the output was sent and the connection of the client,
+    /// if the monitor was still running.
+    fn end_monitor(&self, port: &str) -> Option<(Sink, usize)> {
+        let monitor = lock(&self.monitors).remove(port)?;
+        // A monitor which has already ended lost its client or its device,
+        // so it is not worth starting again.
+        let running = !monitor.thread.is_finished();
+        monitor.token.cancel();
+        // The thread only ends once a read times out, within the timeout of
+        // the port.
+        let _ = monitor.thread.join();
+
+        running.then_some((monitor.sink, monitor.connection))
+    }
+}
+
+/// Sends the event `event` with `params` to each of `subscribers`, dropping
+/// the ones which have gone.
+fn publish(subscribers: &Mutex<Vec<(usize, Sink)>>, event: &'static str, params: Value) {
+    lock(subscribers).retain(|(_, sink)| sink(event, params.clone()));
+}
+
+/// Reads the parameters of a method from `params`.
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+}
+
+/// Returns the response to the request `id` with `result`.
+fn response(id: Value, result: Result<Value, RpcError>) -> Response {
+    let (result, error) = match result {
+        Ok(result) => (Some(result), None),
+        Err(error) => (None, Some(error)),
+    };
+    Response {
+        jsonrpc: "2.0",
+        id,
+        result,
+        error,
+    }
+}
+
+/// Sends the notification `method` with `params` to `output`. Returns
+/// whether it was written.
+fn notify(output: &Output, method: &'static str, params: Value) -> bool {
+    let notification = Notification {
+        jsonrpc: "2.0",
+        method,
+        params,
+    };
+    write_message(output, &notification)
+}
+
+/// Writes `message` to `output` as one line of JSON. Returns whether it was
+/// written, since failing means the client has gone.
+fn write_message(output: &Output, message: &impl Serialize) -> bool {
+    let mut output = lock(output);
+    serde_json::to_writer(&mut *output, message).is_ok()
+        && writeln!(output).is_ok()
+        && output.flush().is_ok()
+}
+
+/// Converts `value` to JSON.
+pub fn to_value(value: impl Serialize) -> Value {
+    serde_json::to_value(value).expect("results serialize to JSON")
+}
+
+/// Locks `mutex`, even if a thread panicked while holding it.
+pub fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Returns the response of a new server to the request `line` as JSON,
+    /// and what it wrote to the client.
+    fn respond(line: &str) -> (Option<Value>, Vec<u8>) {
+        let server = Server::new(&Config::default());
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let response = server.respond(line, &(Arc::clone(&output) as Output), 0);
+        let written = lock(&output).clone();
+        (response.map(to_value), written)
+    }
+
+    /// Returns the error code of `response`.
+    fn code(response: &Option<Value>) -> Option<i64> {
+        response.as_ref()?["error"]["code"].as_i64()
+    }
+
+    #[test]
+    fn errors() {
+        let (response, _) = respond("{\"id\": 1, \"method\":");
+        assert_eq!(code(&response), Some(PARSE_ERROR));
+        assert_eq!(response.unwrap()["id"], Value::Null);
+
+        let (response, _) = respond(r#"{"id": 1, "params": []}"#);
+        assert_eq!(code(&response), Some(INVALID_REQUEST));
+        let (response, _) = respond("[1, 2]");
+        assert_eq!(code(&response), Some(INVALID_REQUEST));
+
+        let (response, _) = respond(r#"{"id": 2, "method": "flash"}"#);
+        assert_eq!(code(&response), Some(METHOD_NOT_FOUND));
+        assert_eq!(response.unwrap()["id"], 2);
+
+        let (response, _) =
+            respond(r#"{"id": 3, "method": "upload", "params": {"device": "auto"}}"#);
+        assert_eq!(code(&response), Some(INVALID_PARAMS));
+        let (response, _) = respond(r#"{"id": 4, "method": "monitor"}"#);
+        assert_eq!(code(&response), Some(INVALID_PARAMS));
+
+        let (response, _) = respond(
+            r#"{"id": 5, "method": "upload", "params": {"device": "auto", "source": "console.log(1)"}}"#,
+        );
+        let response = response.unwrap();
+        assert_eq!(response["error"]["code"], COMMAND_FAILED);
+        assert_eq!(response["error"]["data"]["kind"], "usage");
+    }
+
+    #[test]
+    fn list_and_delete() {
+        for method in ["list", "delete"] {
+            let (response, _) = respond(&format!(r#"{{"id": "{method}", "method": "{method}"}}"#));
+            assert_eq!(code(&response), Some(METHOD_NOT_FOUND));
+            let response = response.unwrap();
+            assert_eq!(response["id"], method);
+            assert!(response["error"]["message"]
+                .as_str()
+                .unwrap()
+                .starts_with("Spade cannot list or delete games"));
+        }
+    }
+
+    #[test]
+    fn notifications() {
+        assert_eq!(respond(r#"{"method": "flash"}"#), (None, Vec::new()));
+        assert_eq!(respond(r#"{"method": "list"}"#), (None, Vec::new()));
+        assert_eq!(
+            respond(r#"{"method": "upload", "params": 1}"#),
+            (None, Vec::new())
+        );
+    }
+
+    #[test]
+    fn handle() {
+        let server = Server::new(&Config::default());
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let input = "{\"id\": 1, \"method\": \"subscribe\"}\n\n{\"id\": 2, \"method\": \"list\"}\n";
+        server.handle(Cursor::new(input), Arc::clone(&output) as Output);
+
+        let written = String::from_utf8(lock(&output).clone()).unwrap();
+        let lines: Vec<Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], json!({"jsonrpc": "2.0", "id": 1, "result": null}));
+        assert_eq!(lines[1]["id"], 2);
+
+        // The client stopped getting events once its input ended.
+        assert!(lock(&server.subscribers).is_empty());
+    }
+
+    #[test]
+    fn events() {
+        let server = Server::new(&Config::default());
+        let output = Arc::new(Mutex::new(Vec::new()));
+        assert_eq!(
+            server
+                .respond(
+                    r#"{"id": 1, "method": "subscribe"}"#,
+                    &(Arc::clone(&output) as Output),
+                    0
+                )
+                .map(to_value),
+            Some(json!({"jsonrpc": "2.0", "id": 1, "result": null}))
+        );
+        lock(&output).clear();
+
+        publish(&server.subscribers, "console", json!({"text": "hi"}));
+        let written: Value = serde_json::from_slice(&lock(&output)).unwrap();
+        assert_eq!(
+            written,
+            json!({"jsonrpc": "2.0", "method": "console", "params": {"text": "hi"}})
+        );
+
+        server.disconnect(0);
+        assert!(lock(&server.subscribers).is_empty());
+    }
+}
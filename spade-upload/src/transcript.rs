@@ -0,0 +1,80 @@
+use spade_serial::transport::{write_transcript, ProtocolRecorder};
+use spade_serial::SpadeTransport;
+use std::fs::File;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The file a transcript is written to, shared by every serial port opened
+/// during a command.
+pub type TranscriptFile = Arc<Mutex<File>>;
+
+/// A serial port which writes everything sent to and received from the
+/// device to a transcript file as it happens, for bug reports.
+pub struct Transcript<T> {
+    /// The name of the serial port, which starts each line of the
+    /// transcript since several ports can share a file.
+    name: String,
+    port: ProtocolRecorder<T>,
+    file: TranscriptFile,
+}
+
+impl<T: SpadeTransport> Transcript<T> {
+    /// Records the communication over the serial port `name` to `file`.
+    /// Times in the transcript are since the port was opened.
+    pub fn new(name: &str, port: T, file: TranscriptFile) -> Self {
+        Self {
+            name: name.to_owned(),
+            port: ProtocolRecorder::new(port),
+            file,
+        }
+    }
+
+    /// Writes what was sent and received since the last call to the file.
+    /// A transcript which cannot be written is not worth failing the command
+    /// for, so errors are ignored.
+    fn write_events(&mut self) {
+        let mut transcript = Vec::new();
+        let _ = write_transcript(&self.port.take_events(), &mut transcript);
+        if transcript.is_empty() {
+            return;
+        }
+
+        let mut lines = String::new();
+        for line in String::from_utf8_lossy(&transcript).lines() {
+            lines.push_str(&self.name);
+            lines.push(' ');
+            lines.push_str(line);
+            lines.push('\n');
+        }
+        if let Ok(mut file) = self.file.lock() {
+            let _ = Write::write_all(&mut *file, lines.as_bytes());
+        }
+    }
+}
+
+impl<T: SpadeTransport> SpadeTransport for Transcript<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        let result = self.port.read(buf);
+        self.write_events();
+        result
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        let result = self.port.write(buf);
+        self.write_events();
+        result
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.port.flush()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), std::io::Error> {
+        self.port.set_timeout(timeout)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.port.timeout()
+    }
+}
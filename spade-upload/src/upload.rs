@@ -0,0 +1,576 @@
+use clap::Args;
+use dialoguer::Confirm;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+use spade_serial::{
+    upload_game_reported, Game, GameError, GameMetadata, GameName, UploadError, UploadOptions,
+    UploadProgress, UploadReport,
+};
+use std::fs;
+use std::io::{stdin, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::bundle::bundle;
+use crate::config::Config;
+use crate::device::Device;
+use crate::failure::{check_result, Failure};
+use crate::flash::{self, FirmwareArgs};
+#[cfg(feature = "online")]
+use crate::gallery;
+use crate::minify::minify;
+use crate::monitor::{print_output, Color, ErrorFormat};
+use crate::report::{millis, DeviceReport, GameReport, Report, TransferReport};
+use crate::validate::validate;
+use crate::{multi, watch};
+
+/// The arguments of the `upload` command.
+#[derive(Args)]
+pub struct UploadArgs {
+    /// The serial port or alias of the Sprig device, or "auto" to find the
+    /// connected Sprig. To upload to several devices at once, pass their serial
+    /// ports or aliases separated by commas, or "all" for every connected
+    /// Sprig.
+    pub device: String,
+    /// Path to the JavaScript source of a Sprig game, or "-" to read it from
+    /// stdin. If not specified, the game is read from stdin unless it is a
+    /// terminal.
+    pub source: Option<PathBuf>,
+    /// The source, when the name is passed before it like in the deprecated
+    /// `DEVICE NAME SOURCE` order. See `UploadArgs::resolve_legacy_order`.
+    #[arg(hide = true, conflicts_with = "name")]
+    pub legacy_source: Option<PathBuf>,
+    /// The name that the game should appear under. Limited to 100 bytes. If
+    /// not specified, the game is named after its `@title:` header, or else
+    /// its file name without the extension.
+    #[arg(short, long)]
+    pub name: Option<String>,
+    /// Download the game from the Sprig gallery instead, by the name in its
+    /// URL (like "pond") or its gallery URL.
+    #[cfg(feature = "online")]
+    #[arg(long, value_name = "GAME", conflicts_with_all = ["source", "entry"])]
+    pub from_gallery: Option<String>,
+    /// Path to the entry point of a game split across several files instead.
+    /// The local files it imports are bundled into one source.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["source", "watch"])]
+    pub entry: Option<PathBuf>,
+    /// Upload the game again whenever the source changes, printing the
+    /// output of the device in between. Runs until interrupted. Cannot be
+    /// used with several devices.
+    #[arg(long, requires = "source", conflicts_with = "json")]
+    pub watch: bool,
+    /// After the game is uploaded, print the output of the device like
+    /// `monitor` until interrupted. Cannot be used with several devices.
+    #[arg(long, conflicts_with = "watch")]
+    pub follow: bool,
+    /// How to print runtime errors with --watch or --follow. "vscode" prints
+    /// them as `file:line:col: message` referencing the source, for the
+    /// problem matchers of VS Code tasks.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value_t = ErrorFormat::Human,
+        conflicts_with_all = ["json", "minify"]
+    )]
+    pub error_format: ErrorFormat,
+    /// Upload the game without checking it for common mistakes first, like
+    /// an HTML page instead of JavaScript. Sources which are not UTF-8 text
+    /// are only uploaded with this.
+    #[arg(long)]
+    pub no_validate: bool,
+    /// Remove comments and whitespace from the game before uploading it, so
+    /// it takes up less space on the device.
+    #[arg(long)]
+    pub minify: bool,
+    /// If the device is running a legacy Spade version, update its firmware
+    /// without asking, then upload the game. Without the `online` feature,
+    /// the image has to be passed with --uf2.
+    #[arg(long)]
+    pub auto_upgrade: bool,
+    #[command(flatten)]
+    pub firmware: FirmwareArgs,
+}
+
+impl UploadArgs {
+    /// Takes the arguments after the device to be the name then the source,
+    /// like spade-upload took them before `--name`, printing a warning that
+    /// this is deprecated. They are if there are two of them, or if the one
+    /// there is not a file and the game is piped to stdin.
+    pub fn resolve_legacy_order(&mut self) {
+        let legacy = match (&self.source, &self.legacy_source) {
+            (Some(_), Some(_)) => true,
+            (Some(path), None) => {
+                self.name.is_none()
+                    && path != Path::new(STDIN)
+                    && !path.exists()
+                    && !stdin().is_terminal()
+            }
+            (None, _) => false,
+        };
+        if !legacy {
+            return;
+        }
+
+        eprintln!("Warning: Passing the name before the source is deprecated. Pass it with --name instead.");
+        self.name = self
+            .source
+            .take()
+            .map(|name| name.to_string_lossy().into_owned());
+        self.source = self.legacy_source.take();
+    }
+}
+
+/// Creates a progress bar for uploading a game of `total` bytes, which is
+/// updated by the progress reported with `UploadOptions::on_progress`. It is
+/// hidden if `hidden` is `true`. Its prefix is shown before it, to tell apart
+/// several bars.
+pub fn progress_bar(total: usize, hidden: bool) -> ProgressBar {
+    if hidden {
+        return ProgressBar::hidden();
+    }
+
+    let bar = ProgressBar::new(total as u64).with_style(
+        ProgressStyle::with_template(
+            "{prefix}{msg:18} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec} ETA {eta}",
+        )
+        .unwrap()
+        .progress_chars("=> "),
+    );
+    bar.set_message("Starting upload");
+
+    bar
+}
+
+/// A step of an upload, printed as a line of JSON with `--porcelain`, or
+/// sent to the clients of `serve`.
+#[derive(Serialize)]
+pub struct ProgressReport {
+    /// The serial port of the device the game is uploaded to.
+    port: String,
+    /// What the upload is doing: `header`, `transferring`, `waiting` or
+    /// `done`.
+    phase: &'static str,
+    /// The bytes of the game written so far.
+    written: usize,
+    /// The size of the game in bytes.
+    total: usize,
+    /// How much of the game was written, from 0 to 100.
+    percent: u8,
+}
+
+impl ProgressReport {
+    /// Describes `progress` of uploading a game of `total` bytes to the
+    /// device at `port`.
+    pub fn new(port: &str, total: usize, progress: UploadProgress) -> Self {
+        let (phase, written) = match progress {
+            UploadProgress::Header => ("header", 0),
+            UploadProgress::Game { written, .. } => ("transferring", written),
+            UploadProgress::Waiting => ("waiting", total),
+            UploadProgress::Done => ("done", total),
+        };
+        ProgressReport {
+            port: port.to_owned(),
+            phase,
+            written,
+            total,
+            percent: (written * 100).checked_div(total).unwrap_or(100) as u8,
+        }
+    }
+}
+
+/// Prints `progress` of uploading a game of `total` bytes to the device at
+/// `port` as a line of JSON.
+fn print_progress(port: &str, total: usize, progress: UploadProgress) {
+    let report = ProgressReport::new(port, total, progress);
+    println!("{}", serde_json::to_string(&report).unwrap());
+}
+
+/// Shows `progress` on `bar`.
+fn show_progress(bar: &ProgressBar, progress: UploadProgress) {
+    match progress {
+        UploadProgress::Header => bar.set_message("Sending header"),
+        UploadProgress::Game { written, .. } => {
+            bar.set_message("Transferring");
+            bar.set_position(written as u64);
+        }
+        UploadProgress::Waiting => bar.set_message("Waiting for device"),
+        UploadProgress::Done => bar.finish_and_clear(),
+    }
+}
+
+/// The source path which stands for stdin.
+const STDIN: &str = "-";
+
+/// Returns the path of the game source in `args`, or `None` if it is read
+/// from stdin.
+pub fn source_path(args: &UploadArgs) -> Option<&PathBuf> {
+    args.source
+        .as_ref()
+        .filter(|path| *path != Path::new(STDIN))
+}
+
+/// Whether `args` say where the game comes from, instead of leaving it to be
+/// piped to stdin.
+fn names_game(args: &UploadArgs) -> bool {
+    #[cfg(feature = "online")]
+    if args.from_gallery.is_some() {
+        return true;
+    }
+
+    args.source.is_some() || args.entry.is_some()
+}
+
+/// Reads the game source from `source`, or from stdin if there is none.
+fn read_source(source: Option<&PathBuf>) -> Result<Vec<u8>, Failure> {
+    let game = match source {
+        Some(path) => fs::read(path),
+        None => {
+            if stdin().is_terminal() {
+                eprintln!("Reading the game from stdin. End it with Ctrl-D, or Ctrl-Z then Enter on Windows.");
+            }
+            let mut game = Vec::new();
+            stdin().read_to_end(&mut game).map(|_| game)
+        }
+    };
+
+    Ok(game.map_err(|_| GameError::IOError)?)
+}
+
+/// Checks `source` with `validate` unless `--no-validate` was passed in
+/// `args`, printing any warnings, then minifies it if `--minify` was passed.
+///
+/// Sources which are not UTF-8 text are uploaded as they are with
+/// `--no-validate`, unless they are to be minified.
+fn prepare_source(args: &UploadArgs, source: Vec<u8>) -> Result<Vec<u8>, Failure> {
+    let mut source = match String::from_utf8(source) {
+        Ok(source) => source,
+        Err(err) if args.no_validate && !args.minify => return Ok(err.into_bytes()),
+        Err(err) => {
+            let fix = match args.minify {
+                true => "Save it as UTF-8 in your editor to minify it.",
+                false => {
+                    "Save it as UTF-8 in your editor, or pass --no-validate to upload it as it is."
+                }
+            };
+            return Err(Failure::InvalidSource(format!(
+                "The source is not UTF-8 text, from byte {}. {fix}",
+                err.utf8_error().valid_up_to()
+            )));
+        }
+    };
+
+    if !args.no_validate {
+        let warnings;
+        (source, warnings) = validate(source)?;
+        for warning in warnings {
+            eprintln!("Warning: {warning}");
+        }
+    }
+
+    if args.minify {
+        let minified = minify(&source);
+        eprintln!(
+            "Minified the game from {} to {} bytes.",
+            source.len(),
+            minified.len()
+        );
+        source = minified;
+    }
+
+    Ok(source.into_bytes())
+}
+
+/// Reads or downloads the game described by `args`, naming it like
+/// `game_name`.
+pub fn load_game(args: &UploadArgs, config: &Config) -> Result<Game, Failure> {
+    #[cfg(feature = "online")]
+    if let Some(game) = &args.from_gallery {
+        let source = prepare_source(args, gallery::download(game)?.into_bytes())?;
+        // Games in the gallery are stored as `<slug>.js`.
+        let name = game_name(args, config, &source, Some(gallery::slug(game)?))?;
+        return Ok(Game::new(name, source)?);
+    }
+
+    let (source, path) = match &args.entry {
+        Some(entry) => (bundle(entry)?.into_bytes(), Some(entry)),
+        None => (read_source(source_path(args))?, source_path(args)),
+    };
+    let source = prepare_source(args, source)?;
+    let file = path
+        .and_then(|path| path.file_stem())
+        .map(|stem| stem.to_string_lossy());
+    let name = game_name(args, config, &source, file.as_deref())?;
+
+    Ok(Game::new(name, source)?)
+}
+
+/// Returns the name to upload `source` under, which was read from the file
+/// named `file` without its extension. This is `--name` if it was passed, or
+/// else the name template in `config`, the `@title:` header of the source, or
+/// `file`. Names which are not passed are made to fit with
+/// `GameName::truncate_lossy`.
+fn game_name(
+    args: &UploadArgs,
+    config: &Config,
+    source: &[u8],
+    file: Option<&str>,
+) -> Result<GameName, Failure> {
+    if let Some(name) = &args.name {
+        return Ok(GameName::new(name).map_err(GameError::from)?);
+    }
+
+    let title = GameMetadata::parse(&String::from_utf8_lossy(source)).title;
+    let name = match &config.name {
+        Some(template) => expand_name(template, title.as_deref(), file)?,
+        None => title.or(file.map(str::to_owned)).ok_or_else(|| {
+            Failure::Usage(String::from(
+                "The game has no @title: header or file name to name it after. Pass --name to name it.",
+            ))
+        })?,
+    };
+
+    Ok(GameName::truncate_lossy(&name))
+}
+
+/// Replaces `{title}` and `{file}` in the name template `template` with
+/// `title` and `file`.
+fn expand_name(template: &str, title: Option<&str>, file: Option<&str>) -> Result<String, Failure> {
+    let mut name = template.to_owned();
+    for (placeholder, value) in [("{title}", title), ("{file}", file)] {
+        if !name.contains(placeholder) {
+            continue;
+        }
+        let value = value.ok_or_else(|| {
+            Failure::Usage(format!(
+                "The name template uses {placeholder}, which the game does not have. Pass --name to name it."
+            ))
+        })?;
+        name = name.replace(placeholder, value);
+    }
+
+    Ok(name)
+}
+
+/// Connects to the device at the serial port `name` like `Device::connect`,
+/// recording it in `report`. Legacy devices are not supported.
+pub fn connect(name: &str, config: &Config, report: &mut Report) -> Result<Device, Failure> {
+    let start = Instant::now();
+    let device = Device::connect(name, config)?;
+    report.timings.connect_ms = Some(millis(start.elapsed()));
+    report.device = Some(DeviceReport {
+        port: device.name.clone(),
+        legacy: device.legacy,
+    });
+
+    match device.legacy {
+        true => Err(Failure::Legacy),
+        false => Ok(device),
+    }
+}
+
+/// Uploads the game described by `args` to `device` with the settings in
+/// `config`, recording what happens in `report`. The progress bar is hidden if
+/// `json` is `true`.
+pub fn upload(
+    args: &UploadArgs,
+    device: &mut Device,
+    config: &Config,
+    report: &mut Report,
+    json: bool,
+) -> Result<(), Failure> {
+    let game = load_game(args, config)?;
+    report.game = Some(GameReport {
+        name: game.name().as_str().to_owned(),
+        bytes: game.source().len(),
+    });
+
+    let bar = progress_bar(game.source().len(), json);
+    let start = Instant::now();
+    let upload = send(device, &game, config, &bar);
+    report.timings.upload_ms = Some(millis(start.elapsed()));
+
+    let upload = upload?;
+    report.result = Some(upload.result.clone());
+    report.transfer = Some(TransferReport::from(&upload));
+    check_result(upload.result)
+}
+
+/// Uploads `game` to `device` with the settings in `config`, retrying as many
+/// times as it allows and showing the progress on `bar`, or printing it as
+/// JSON if `config` says so. Returns how fast it went along with the result.
+pub fn send(
+    device: &mut Device,
+    game: &Game,
+    config: &Config,
+    bar: &ProgressBar,
+) -> Result<UploadReport, UploadError> {
+    let options = match config.porcelain {
+        true => {
+            let port = device.name.clone();
+            let total = game.source().len();
+            upload_options(config)
+                .on_progress(move |progress| print_progress(&port, total, progress))
+        }
+        false => {
+            let bar = bar.clone();
+            upload_options(config).on_progress(move |progress| show_progress(&bar, progress))
+        }
+    };
+    let upload = upload_game_reported(&mut *device.port, game, &options);
+    bar.finish_and_clear();
+
+    upload
+}
+
+/// Returns the absolute path of the source to refer to in runtime errors if
+/// `--error-format vscode` was passed in `args`, so problem matchers find it
+/// from any directory.
+pub fn problem_source(args: &UploadArgs) -> Result<Option<PathBuf>, Failure> {
+    match (args.error_format, source_path(args)) {
+        (ErrorFormat::Vscode, Some(path)) => Ok(Some(
+            fs::canonicalize(path).map_err(|_| GameError::IOError)?,
+        )),
+        _ => Ok(None),
+    }
+}
+
+/// Returns the options for uploading with the settings in `config`, without
+/// a progress callback.
+pub fn upload_options(config: &Config) -> UploadOptions {
+    let mut options = UploadOptions::new()
+        .retries(config.retries())
+        .max_timeouts(config.max_timeouts());
+    if let Some(chunk_size) = config.chunk_size() {
+        options = options.chunk_size(chunk_size);
+    }
+    if let Some(delay) = config.header_delay() {
+        options = options.header_delay(delay);
+    }
+
+    options
+}
+
+/// Offers to update the firmware of the legacy device recorded in `report`
+/// if `config` allows asking, or updates it without asking with
+/// `--auto-upgrade`, then connects to it again. The image is the one passed
+/// in `args`, or else the latest release of Spade if it can be downloaded.
+fn upgrade(
+    args: &UploadArgs,
+    config: &Config,
+    report: &mut Report,
+    json: bool,
+) -> Result<Device, Failure> {
+    let port = report.device.as_ref().map(|device| device.port.clone());
+    let (Some(port), Some(image)) = (port, args.firmware.image(true)) else {
+        return Err(Failure::Legacy);
+    };
+
+    if !args.auto_upgrade {
+        if !config.interactive {
+            return Err(Failure::Legacy);
+        }
+        let agreed = Confirm::new()
+            .with_prompt("The device is running a legacy Spade version, which spade-upload cannot upload to. Update its firmware?")
+            .default(true)
+            .interact_opt()
+            .map_err(|err| Failure::Io(err.to_string()))?;
+        if agreed != Some(true) {
+            return Err(Failure::Legacy);
+        }
+    }
+
+    let port = flash::flash(&port, &image, &args.firmware, json)?;
+    connect(&port, config, report)
+}
+
+/// Runs the `upload` command with `args` and the defaults in `config`,
+/// recording what happens in `report`.
+pub fn run(
+    args: &UploadArgs,
+    config: &Config,
+    report: &mut Report,
+    json: bool,
+) -> Result<(), Failure> {
+    if !names_game(args) && stdin().is_terminal() {
+        return Err(Failure::Usage(String::from(
+            "No game was passed. Pass the path to its source, or - to type it into stdin.",
+        )));
+    }
+    if args.watch && source_path(args).is_none() {
+        return Err(Failure::Usage(String::from(
+            "--watch needs the path to the source, not -.",
+        )));
+    }
+    if args.error_format == ErrorFormat::Vscode && source_path(args).is_none() {
+        return Err(Failure::Usage(String::from(
+            "--error-format vscode needs the path to the source, to refer to it in errors.",
+        )));
+    }
+    if args.auto_upgrade && args.firmware.image(true).is_none() {
+        return Err(Failure::Usage(String::from(
+            "Pass the UF2 image of Spade to upgrade to with --uf2.",
+        )));
+    }
+
+    if multi::names_several(&args.device) {
+        if args.watch || args.follow || args.auto_upgrade {
+            return Err(Failure::Usage(String::from(
+                "--watch, --follow and --auto-upgrade can only be used with one device.",
+            )));
+        }
+        return multi::upload_all(
+            &args.device,
+            config,
+            &load_game(args, config)?,
+            report,
+            json,
+        );
+    }
+
+    let mut device = match connect(&args.device, config, report) {
+        Err(Failure::Legacy) => upgrade(args, config, report, json)?,
+        device => device?,
+    };
+
+    if args.watch {
+        return watch::watch(args, config, &mut device);
+    }
+
+    upload(args, &mut device, config, report, json)?;
+    if args.follow {
+        if !json {
+            eprintln!("Uploaded. Printing the output of the device; press Ctrl-C to stop.");
+        }
+        let source = problem_source(args)?;
+        print_output(&mut *device.port, json, Color::Auto, source.as_deref())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_templates() {
+        assert_eq!(
+            expand_name("{title} (dev)", Some("Pond"), Some("pond")).unwrap(),
+            "Pond (dev)"
+        );
+        assert_eq!(
+            expand_name("{file}-{title}", Some("Pond"), Some("pond")).unwrap(),
+            "pond-Pond"
+        );
+        assert_eq!(expand_name("fixed", None, None).unwrap(), "fixed");
+        assert!(matches!(
+            expand_name("{title}", None, Some("pond")),
+            Err(Failure::Usage(_))
+        ));
+        assert!(matches!(
+            expand_name("{file}", Some("Pond"), None),
+            Err(Failure::Usage(_))
+        ));
+    }
+}
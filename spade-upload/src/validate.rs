@@ -0,0 +1,125 @@
+use crate::failure::Failure;
+
+/// Functions the Sprig engine provides to games. A game which calls none of
+/// them is probably not a Sprig game.
+const SPRIG_API: [&str; 8] = [
+    "setMap",
+    "setLegend",
+    "setSolids",
+    "onInput",
+    "addSprite",
+    "getFirst",
+    "addText",
+    "playTune",
+];
+
+/// The tags HTML pages usually start with.
+const HTML_TAGS: [&str; 4] = ["<!doctype", "<html", "<head", "<script"];
+
+/// HTML entities left in code copied from the source of a web page.
+const HTML_ENTITIES: [&str; 3] = ["&lt;", "&gt;", "&amp;"];
+
+/// Checks the game source `source` for mistakes which would keep it from
+/// running on the device, before it takes up space there. Returns the source
+/// without a byte order mark, and warnings about likely mistakes which do not
+/// stop it from being uploaded.
+pub fn validate(mut source: String) -> Result<(String, Vec<String>), Failure> {
+    let mut warnings = Vec::new();
+
+    if let Some(stripped) = source.strip_prefix('\u{feff}') {
+        source = stripped.to_owned();
+        warnings.push(String::from(
+            "The source starts with a byte order mark, which was removed.",
+        ));
+    }
+
+    let start = source.trim_start();
+    if start.is_empty() {
+        return Err(Failure::InvalidSource(String::from(
+            "The source is empty or only whitespace.\nPass --no-validate to upload it anyway.",
+        )));
+    }
+    let lowercase_start: String = start.chars().take(16).collect::<String>().to_lowercase();
+    if HTML_TAGS.iter().any(|tag| lowercase_start.starts_with(tag)) {
+        return Err(Failure::InvalidSource(String::from(
+            "The source is an HTML page, not JavaScript. Copy the game from the Sprig editor's code, or save it with the editor.\nPass --no-validate to upload it anyway.",
+        )));
+    }
+
+    if HTML_ENTITIES.iter().any(|entity| source.contains(entity)) {
+        warnings.push(String::from(
+            "The source contains HTML entities like &lt;, so it may have been copied from the source of a web page.",
+        ));
+    }
+    if !SPRIG_API.iter().any(|function| source.contains(function)) {
+        warnings.push(String::from(
+            "The source does not call Sprig functions like setMap or setLegend, so it may not be a Sprig game.",
+        ));
+    }
+
+    Ok((source, warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME: &str = "setLegend([player, bitmap`...`]);\nsetMap(map`p`);";
+
+    #[test]
+    fn valid() {
+        assert_eq!(
+            validate(GAME.to_owned()).unwrap(),
+            (GAME.to_owned(), Vec::new())
+        );
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        let (source, warnings) = validate(format!("\u{feff}{GAME}")).unwrap();
+        assert_eq!(source, GAME);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("byte order mark"));
+    }
+
+    #[test]
+    fn empty() {
+        for source in ["", " \n\t", "\u{feff}\n"] {
+            assert!(matches!(
+                validate(source.to_owned()),
+                Err(Failure::InvalidSource(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn html() {
+        for source in [
+            "<!DOCTYPE html><html></html>",
+            "\n  <html lang=\"en\">",
+            "<HEAD>",
+            "<script src=\"game.js\"></script>",
+        ] {
+            assert!(
+                matches!(validate(source.to_owned()), Err(Failure::InvalidSource(_))),
+                "{source}"
+            );
+        }
+        // Only the start of the source is checked for tags.
+        assert!(validate(format!("{GAME}\n// <html>")).is_ok());
+    }
+
+    #[test]
+    fn html_entities() {
+        let (_, warnings) = validate(format!("{GAME}\nif (a &lt; b) {{}}")).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("HTML entities"));
+    }
+
+    #[test]
+    fn not_sprig() {
+        let (_, warnings) = validate(String::from("console.log('hello')")).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("Sprig functions"));
+    }
+}
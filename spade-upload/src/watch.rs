@@ -0,0 +1,89 @@
+use notify::{Event, RecursiveMode, Watcher};
+use spade_serial::{attach_console, CancellationToken, GameError};
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::device::Device;
+use crate::failure::Failure;
+use crate::monitor::problem;
+use crate::report::Report;
+use crate::upload::{problem_source, source_path, upload, UploadArgs};
+
+/// How long the source has to stay unchanged before it is uploaded, since
+/// editors often write a file several times when saving it.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Uploads the game described by `args` to `device` with the settings in
+/// `config` whenever its source changes, printing the output of the device in
+/// between. Failed uploads are reported and tried again on the next change, so
+/// this only returns if the source cannot be watched or the device cannot be
+/// read.
+pub fn watch(args: &UploadArgs, config: &Config, device: &mut Device) -> Result<(), Failure> {
+    // clap requires a source with `--watch`, and `upload::run` checks that
+    // it is not stdin.
+    let source = source_path(args).expect("watching needs a source");
+    let source = fs::canonicalize(source).map_err(|_| GameError::IOError)?;
+    let problem_source = problem_source(args)?;
+
+    // Editors often replace the file when saving it, which ends a watch on
+    // the file itself, so the directory holding it is watched instead.
+    let directory = source.parent().unwrap_or(&source);
+    let (sender, mut changes) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(watch_failure)?;
+    watcher
+        .watch(directory, RecursiveMode::NonRecursive)
+        .map_err(watch_failure)?;
+
+    loop {
+        match upload(args, device, config, &mut Report::default(), false) {
+            Ok(()) => eprintln!("Uploaded {}. Watching for changes.", source.display()),
+            Err(failure) => eprintln!("{failure}\nWatching for changes."),
+        }
+
+        let token = CancellationToken::new();
+        let waiter = thread::spawn({
+            let token = token.clone();
+            let source = source.clone();
+            move || {
+                wait_for_change(&changes, &source);
+                token.cancel();
+                changes
+            }
+        });
+        attach_console(&mut *device.port, &token, |line| {
+            match problem_source
+                .as_deref()
+                .and_then(|source| problem(&line, source))
+            {
+                Some(problem) => println!("{problem}"),
+                None => println!("{line}"),
+            }
+        })?;
+        changes = waiter.join().expect("watching thread panicked");
+    }
+}
+
+/// Blocks until `source` changes and then no more changes are seen for
+/// `DEBOUNCE`.
+fn wait_for_change(changes: &Receiver<notify::Result<Event>>, source: &Path) {
+    let changes_source = |event: &notify::Result<Event>| match event {
+        Ok(event) => !event.kind.is_access() && event.paths.iter().any(|path| path == source),
+        Err(_) => false,
+    };
+
+    while let Ok(event) = changes.recv() {
+        if changes_source(&event) {
+            break;
+        }
+    }
+    while changes.recv_timeout(DEBOUNCE).is_ok() {}
+}
+
+/// Describes a failure to watch the source for changes.
+fn watch_failure(err: notify::Error) -> Failure {
+    Failure::Watch(format!("Could not watch the source for changes: {err}"))
+}